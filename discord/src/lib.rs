@@ -36,7 +36,7 @@ pub struct User<'a> {
     pub flags: Option<u64>,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Snowflake(String); // Actually a u64
 
 impl ::std::fmt::Display for Snowflake {
@@ -161,7 +161,8 @@ pub struct Message<'a> {
     pub mention_everyone: bool,
     pub mentions: Vec<User<'a>>,
     pub mention_roles: Vec<Snowflake>,
-    //pub attachments: Vec<Attachment>,
+    #[serde(borrow, default)]
+    pub attachments: Vec<Attachment<'a>>,
     //jpub embeds: Vec<Embed>,
     //pub reactions: Option<Vec<Reaction>>,
     pub nonce: Option<Snowflake>,
@@ -173,6 +174,20 @@ pub struct Message<'a> {
     //pub application: Option<MessageApplication>,
 }
 
+#[derive(Clone, Debug, Deserialize)]
+pub struct Attachment<'a> {
+    pub id: Snowflake,
+    #[serde(borrow)]
+    pub filename: Cow<'a, str>,
+    pub size: u64,
+    #[serde(borrow)]
+    pub url: Cow<'a, str>,
+    #[serde(borrow)]
+    pub proxy_url: Cow<'a, str>,
+    pub height: Option<u64>,
+    pub width: Option<u64>,
+}
+
 macro_rules! serial_single_field {
     ($typ:ident as $field:ident: $inner:path) => {
         impl ::serde::Serialize for $typ {
@@ -1,3 +1,4 @@
+use conn::IString;
 use std::collections::hash_map::{IntoIter, Iter};
 use std::collections::HashMap;
 use std::hash::Hash;
@@ -6,6 +7,11 @@ use std::hash::Hash;
 pub struct BiMap<L, R> {
     left_to_right: HashMap<L, R>,
     right_to_left: HashMap<R, L>,
+    /// The name and qualifier `insert_unique` was given for each left, so it can be relabelled
+    /// when something else turns up with the same name
+    names: HashMap<L, (R, String)>,
+    /// How many lefts `insert_unique` was given each name for
+    name_counts: HashMap<R, usize>,
 }
 
 impl<L, R> BiMap<L, R>
@@ -17,6 +23,8 @@ where
         Self {
             left_to_right: HashMap::new(),
             right_to_left: HashMap::new(),
+            names: HashMap::new(),
+            name_counts: HashMap::new(),
         }
     }
 
@@ -44,11 +52,99 @@ where
         self.left_to_right.get(left)
     }
 
+    /// Removes the pair with this right value, returning its left
+    pub fn remove_right<Q: ?Sized>(&mut self, right: &Q) -> Option<L>
+    where
+        R: ::std::borrow::Borrow<Q>,
+        Q: Hash + Eq,
+    {
+        let left = self.right_to_left.remove(right)?;
+        self.left_to_right.remove(&left);
+        self.forget_name(&left);
+        Some(left)
+    }
+
+    /// The name `insert_unique` was given for `left`, before any qualifier was added to it
+    pub fn name_of(&self, left: &L) -> Option<&R> {
+        self.names.get(left).map(|(name, _)| name)
+    }
+
+    fn forget_name(&mut self, left: &L) {
+        if let Some((name, _)) = self.names.remove(left) {
+            let remaining = match self.name_counts.get_mut(&name) {
+                Some(count) => {
+                    *count -= 1;
+                    *count
+                }
+                None => return,
+            };
+            if remaining == 0 {
+                self.name_counts.remove(&name);
+            }
+        }
+    }
+
     pub fn iter(&self) -> Iter<L, R> {
         self.left_to_right.iter()
     }
 }
 
+impl<L> BiMap<L, IString>
+where
+    L: Eq + Hash + Clone,
+{
+    /// Inserts a pair whose name may belong to other things too. A name that's shared isn't left
+    /// to mean any one of them: they all get labels like `name (qualifier)`, with a number added
+    /// if even that is taken, and whatever had the bare name is relabelled. Returns the label this
+    /// one ended up with.
+    pub fn insert_unique(&mut self, left: L, name: IString, qualifier: &str) -> IString {
+        if let Some(old) = self.left_to_right.remove(&left) {
+            self.right_to_left.remove(&old);
+        }
+        self.forget_name(&left);
+
+        let shared = {
+            let count = self.name_counts.entry(name.clone()).or_insert(0);
+            *count += 1;
+            *count > 1
+        };
+        if shared {
+            let other = self.right_to_left.get(&name).cloned();
+            if let Some(other) = other {
+                let other_qualifier = self.names.get(&other).map(|(_, q)| q.clone());
+                if let Some(other_qualifier) = other_qualifier {
+                    self.right_to_left.remove(&name);
+                    let label = self.free_label(&name, &other_qualifier, false);
+                    self.insert(other, label);
+                }
+            }
+        }
+
+        let label = self.free_label(&name, qualifier, !shared);
+        self.names
+            .insert(left.clone(), (name, qualifier.to_string()));
+        self.insert(left, label.clone());
+        label
+    }
+
+    /// The first of `name`, `name (qualifier)`, `name (qualifier 2)` and so on that isn't taken,
+    /// skipping the bare name unless `bare`
+    fn free_label(&self, name: &IString, qualifier: &str, bare: bool) -> IString {
+        let mut n = if bare { 0 } else { 1 };
+        loop {
+            let label = match n {
+                0 => name.clone(),
+                1 => IString::from(format!("{} ({})", name, qualifier)),
+                n => IString::from(format!("{} ({} {})", name, qualifier, n)),
+            };
+            if !self.right_to_left.contains_key(&label) {
+                return label;
+            }
+            n += 1;
+        }
+    }
+}
+
 impl<L, R> IntoIterator for BiMap<L, R>
 where
     L: Eq + Hash,
@@ -61,3 +157,50 @@ where
         self.left_to_right.into_iter()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unique_names() {
+        let mut users: BiMap<u32, IString> = BiMap::new();
+        assert_eq!(users.insert_unique(1, "alex".into(), "ajones"), "alex");
+        assert_eq!(users.get_left("alex"), Some(&1));
+
+        // Once the name is shared, nobody has it to themselves
+        assert_eq!(
+            users.insert_unique(2, "alex".into(), "asmith"),
+            "alex (asmith)"
+        );
+        assert_eq!(users.get_left("alex"), None);
+        assert_eq!(users.get_right(&1).unwrap(), "alex (ajones)");
+        assert_eq!(
+            users.insert_unique(3, "alex".into(), "asmith"),
+            "alex (asmith 2)"
+        );
+        assert_eq!(users.get_left("alex (asmith)"), Some(&2));
+        assert_eq!(users.name_of(&3).unwrap(), "alex");
+
+        // Renaming frees the old name, but it's still shared by the others
+        assert_eq!(users.insert_unique(1, "lex".into(), "ajones"), "lex");
+        assert_eq!(users.get_left("alex (ajones)"), None);
+        assert_eq!(
+            users.insert_unique(4, "alex".into(), "abrown"),
+            "alex (abrown)"
+        );
+
+        // Inserting the same thing again changes nothing
+        assert_eq!(
+            users.insert_unique(4, "alex".into(), "abrown"),
+            "alex (abrown)"
+        );
+        assert_eq!(users.iter().count(), 4);
+
+        // A name nobody else has stays bare
+        users.remove_right("alex (asmith)");
+        users.remove_right("alex (asmith 2)");
+        users.remove_right("alex (abrown)");
+        assert_eq!(users.insert_unique(5, "alex".into(), "aking"), "alex");
+    }
+}
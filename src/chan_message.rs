@@ -1,39 +1,119 @@
-use chrono::Timelike;
 use conn;
 use conn::{DateTime, IString};
+use regex::Regex;
+use theme::{Style, Theme, RESET};
+use tui::{DisplayConfig, URL_REGEX};
 
-lazy_static! {
-    static ref COLORS: Vec<::termion::color::AnsiValue> = {
-        let mut c = Vec::with_capacity(45);
-        for r in 1..6 {
-            for g in 1..6 {
-                for b in 1..6 {
-                    if r < 2 || g < 2 || b < 2 {
-                        c.push(::termion::color::AnsiValue::rgb(r, g, b));
-                    }
-                }
-            }
-        }
-        c
-    };
+/// How the time of each message is shown, set by `time_format` in the `[display]` config section
+#[derive(Clone, Copy, Deserialize)]
+pub enum TimeFormat {
+    #[serde(rename = "24h")]
+    TwentyFourHour,
+    #[serde(rename = "24h-seconds")]
+    TwentyFourHourSeconds,
+    #[serde(rename = "12h")]
+    TwelveHour,
+    #[serde(rename = "12h-seconds")]
+    TwelveHourSeconds,
+    #[serde(rename = "iso")]
+    Iso,
 }
 
-fn djb2(input: &str) -> u64 {
-    let mut hash: u64 = 5381;
+impl TimeFormat {
+    pub fn format<Tz>(self, time: &::chrono::DateTime<Tz>) -> String
+    where
+        Tz: ::chrono::TimeZone,
+        Tz::Offset: ::std::fmt::Display,
+    {
+        let format = match self {
+            TimeFormat::TwentyFourHour => "%H:%M",
+            TimeFormat::TwentyFourHourSeconds => "%H:%M:%S",
+            TimeFormat::TwelveHour => "%-I:%M %p",
+            TimeFormat::TwelveHourSeconds => "%-I:%M:%S %p",
+            TimeFormat::Iso => "%Y-%m-%dT%H:%M:%S",
+        };
+        time.format(format).to_string()
+    }
+}
+
+/// Something in a formatted message that can be clicked on
+#[derive(Clone, Debug, PartialEq)]
+pub enum SpanTarget {
+    Reaction(IString),
+    Url(String),
+    /// The index of one of the message's actions
+    Action(usize),
+}
 
-    for c in input.bytes() {
-        hash = (hash << 5).wrapping_add(hash).wrapping_add(u64::from(c));
+/// Where a `SpanTarget` is in the formatted message, by line and display column
+#[derive(Clone, Debug, PartialEq)]
+pub struct Span {
+    pub line: usize,
+    pub columns: ::std::ops::Range<usize>,
+    pub target: SpanTarget,
+}
+
+/// The URLs in some text by where they are in it, so that each part of a URL which has been
+/// wrapped onto several lines still opens the whole URL
+struct Urls<'a> {
+    text: &'a str,
+    urls: Vec<(::std::ops::Range<usize>, &'a str)>,
+    /// Where in `text` the lines seen so far end
+    position: usize,
+}
+
+impl<'a> Urls<'a> {
+    fn new(text: &'a str) -> Self {
+        Urls {
+            text,
+            urls: URL_REGEX
+                .find_iter(text)
+                .map(|m| (m.start()..m.end(), m.as_str()))
+                .collect(),
+            position: 0,
+        }
+    }
+
+    /// Records a span for every URL in `line`, the next wrapped line of the text, which starts
+    /// with an `indent` bytes long that was added by wrapping
+    fn find(&mut self, line_index: usize, line: &str, indent: usize, spans: &mut Vec<Span>) {
+        use width::str_width;
+        let offset = match self.text[self.position..].find(&line[indent..]) {
+            Some(found) => self.position + found,
+            None => return,
+        };
+        self.position = offset + line.len() - indent;
+        for m in URL_REGEX.find_iter(line).filter(|m| m.start() >= indent) {
+            let at = offset + m.start() - indent;
+            if let Some((_, url)) = self.urls.iter().find(|(range, _)| range.contains(&at)) {
+                let start = str_width(&line[..m.start()]);
+                spans.push(Span {
+                    line: line_index,
+                    columns: start..start + str_width(m.as_str()),
+                    target: SpanTarget::Url(url.to_string()),
+                });
+            }
+        }
     }
-    hash
 }
 
 pub struct ChanMessage {
     formatted_width: Option<usize>,
     pub raw: String,
+    attachments: Vec<conn::Attachment>,
+    actions: Vec<conn::Action>,
+    files: Vec<String>,
+    is_bot: bool,
     formatted: String,
+    spans: Vec<Span>,
     sender: IString,
     timestamp: DateTime,
     reactions: Vec<(IString, usize)>,
+    /// What /find is looking for, which is highlighted in the text
+    found: Option<Regex>,
+    // Set by regroup, depending on the message before this one
+    day_separator: bool,
+    continuation: bool,
 }
 
 impl From<conn::Message> for ChanMessage {
@@ -41,10 +121,44 @@ impl From<conn::Message> for ChanMessage {
         ChanMessage {
             formatted_width: None,
             raw: message.contents,
+            attachments: message.attachments,
+            actions: message.actions,
+            files: message.files,
+            is_bot: message.is_bot,
             formatted: String::new(),
+            spans: Vec::new(),
             sender: message.sender,
             timestamp: message.timestamp,
             reactions: message.reactions,
+            found: None,
+            day_separator: false,
+            continuation: false,
+        }
+    }
+}
+
+/// Decides which of `messages` start a new local day and which continue a run of messages from
+/// the same sender, looking at everything from index `from` onwards.
+pub fn regroup(messages: &mut [ChanMessage], from: usize, config: &DisplayConfig) {
+    let window = ::chrono::Duration::minutes(config.compact_minutes);
+    for i in from..messages.len() {
+        let (day_separator, continuation) = match i.checked_sub(1).map(|p| &messages[p]) {
+            None => (true, false),
+            Some(previous) => {
+                let current = &messages[i];
+                let new_day = previous.local_time().date() != current.local_time().date();
+                let continues = config.compact
+                    && !new_day
+                    && previous.sender == current.sender
+                    && *current.timestamp.as_chrono() - *previous.timestamp.as_chrono() < window;
+                (new_day, continues)
+            }
+        };
+        let message = &mut messages[i];
+        if message.day_separator != day_separator || message.continuation != continuation {
+            message.day_separator = day_separator;
+            message.continuation = continuation;
+            message.formatted_width = None;
         }
     }
 }
@@ -55,6 +169,49 @@ impl ChanMessage {
         &self.timestamp
     }
 
+    pub fn sender(&self) -> &IString {
+        &self.sender
+    }
+
+    /// The text of the message and then of each of its attachments
+    pub fn texts(&self) -> impl Iterator<Item = &str> {
+        ::std::iter::once(self.raw.as_str())
+            .chain(self.attachments.iter().map(|a| a.contents.as_str()))
+    }
+
+    /// The buttons and menu options in the message
+    pub fn actions(&self) -> &[conn::Action] {
+        &self.actions
+    }
+
+    /// Links to the files posted with the message
+    pub fn files(&self) -> &[String] {
+        &self.files
+    }
+
+    /// The clickable parts of the message as of the last call to `formatted_to`
+    pub fn spans(&self) -> &[Span] {
+        &self.spans
+    }
+
+    /// The message as of the last call to `formatted_to`
+    pub fn formatted(&self) -> &str {
+        &self.formatted
+    }
+
+    fn local_time(&self) -> ::chrono::DateTime<::chrono::Local> {
+        use chrono::TimeZone;
+        ::chrono::Local.from_utc_datetime(&self.timestamp.as_chrono().naive_utc())
+    }
+
+    /// Highlights the matches for `regex` from the next call to `formatted_to`
+    pub fn highlight(&mut self, regex: Option<&Regex>) {
+        if self.found.as_ref().map(Regex::as_str) != regex.map(Regex::as_str) {
+            self.found = regex.cloned();
+            self.formatted_width = None;
+        }
+    }
+
     pub fn add_reaction(&mut self, reaction: &str) {
         let mut found = false;
         if let Some(r) = self.reactions.iter_mut().find(|rxn| rxn.0 == reaction) {
@@ -83,100 +240,229 @@ impl ChanMessage {
     }
     */
 
-    pub fn formatted_to(&mut self, width: usize) -> &str {
+    pub fn formatted_to(&mut self, width: usize, config: &DisplayConfig, theme: &Theme) -> &str {
+        use std::borrow::Cow;
         use std::fmt::Write;
-        use termion::color::{AnsiValue, Fg, Reset};
-        use textwrap::{NoHyphenation, Wrapper};
+        use width::{str_width, wrap};
 
         if Some(width) == self.formatted_width {
             return &self.formatted;
         }
 
-        use chrono::TimeZone;
-        let timezone = ::chrono::offset::Local::now().timezone();
-        let localtime = timezone.from_utc_datetime(&self.timestamp.as_chrono().naive_utc());
+        let localtime = self.local_time();
 
         self.formatted_width = Some(width);
         self.formatted.clear();
+        self.spans.clear();
         let indent_str = "    ";
-        // 2 for the `: ` after the name, 8 for the time
-        let sender_spacer = " ".repeat(self.sender.chars().count() + 2 + 8);
-        let wrapper = Wrapper::with_splitter(width, NoHyphenation)
-            .subsequent_indent(indent_str)
-            .initial_indent(indent_str)
-            .break_words(true);
-        let first_line_wrapper = Wrapper::with_splitter(width, NoHyphenation)
-            .subsequent_indent(indent_str)
-            .initial_indent(&sender_spacer)
-            .break_words(true);
-
-        for (l, line) in self.raw.lines().enumerate() {
-            // wrap_iter produces nothing on an empty line, so we have to supply the required newline
+
+        if self.day_separator {
+            let date = format!(" {} ", localtime.format("%A, %B %-d, %Y"));
+            let date = ::width::truncate_to_width(&date, width);
+            let fill = width.saturating_sub(str_width(date));
+            let _ = writeln!(
+                self.formatted,
+                "{}{}{}{}{}",
+                theme.date,
+                "─".repeat(fill / 2),
+                date,
+                "─".repeat(fill - fill / 2),
+                RESET,
+            );
+        }
+
+        let time = config.time_format.format(&localtime);
+        let bot_tag = if self.is_bot { " [bot]" } else { "" };
+        // Continuations of a run of messages from one sender line up under its first message
+        let sender_spacer = if self.continuation {
+            String::from(indent_str)
+        } else {
+            // 2 for the `: ` after the name, 3 for the parentheses and space around the time
+            " ".repeat(str_width(&self.sender) + bot_tag.len() + 2 + str_width(&time) + 3)
+        };
+
+        let body = if config.emoji {
+            ::emoji::replace_shortcodes(&self.raw)
+        } else {
+            Cow::Borrowed(self.raw.as_str())
+        };
+
+        let mut urls = Urls::new(&body);
+
+        // Lines are drawn bottom to top, so each one has to set its own style
+        let text_reset = if theme.text == Default::default() {
+            ""
+        } else {
+            RESET
+        };
+        let found = self.found.as_ref();
+        let highlight =
+            |text: &str| ::find::highlight(text, found, &theme.found, &theme.text).into_owned();
+        // Only the text after an indent is highlighted, so a match for spaces doesn't reach into it
+        let highlight_indented = |line: &str, indent: &str| match line.strip_prefix(indent) {
+            Some(text) => format!("{}{}", indent, highlight(text)),
+            None => highlight(line),
+        };
+
+        for (l, line) in body.lines().enumerate() {
+            // wrap produces nothing on an empty line, so we have to supply the required newline
             if line == "" {
                 self.formatted.push('\n');
             }
 
             if l == 0 {
-                for (l, wrapped_line) in first_line_wrapper.wrap_iter(line.trim_left()).enumerate()
+                for (l, wrapped_line) in wrap(line.trim_start(), width, &sender_spacer, indent_str)
+                    .iter()
+                    .enumerate()
                 {
-                    if l == 0 {
-                        let _ = write!(
-                            self.formatted,
-                            "{}({:02}:{:02}) ",
-                            Fg(AnsiValue::grayscale(8)),
-                            localtime.time().hour(),
-                            localtime.time().minute(),
-                        );
+                    // The sender and time take up the same columns as the indent they replace
+                    let line_index = self.formatted.matches('\n').count();
+                    let indent = if l == 0 { &sender_spacer } else { indent_str };
+                    urls.find(line_index, wrapped_line, indent.len(), &mut self.spans);
+                    if l == 0 && !self.continuation {
+                        let _ = write!(self.formatted, "{}({}) {}", theme.timestamp, time, RESET,);
 
                         let _ = write!(
                             self.formatted,
-                            "{}{}{}: ",
-                            Fg(COLORS[djb2(&self.sender) as usize % COLORS.len()]),
+                            "{}{}{}{}{}{}: {}",
+                            theme.nick(&self.sender),
                             self.sender,
-                            Fg(Reset),
+                            RESET,
+                            theme.timestamp,
+                            bot_tag,
+                            RESET,
+                            theme.text,
                         );
 
                         self.formatted
-                            .extend(wrapped_line.chars().skip_while(|c| c.is_whitespace()));
+                            .push_str(&highlight(wrapped_line.trim_start()));
                     } else {
-                        self.formatted.push_str(&wrapped_line);
+                        let _ = write!(
+                            self.formatted,
+                            "{}{}",
+                            theme.text,
+                            highlight_indented(wrapped_line, indent_str)
+                        );
                     }
-                    self.formatted.push('\n');
+                    let _ = writeln!(self.formatted, "{}", text_reset);
                 }
             } else {
-                for wrapped_line in wrapper.wrap_iter(&line) {
-                    self.formatted.push_str(&wrapped_line);
-                    self.formatted.push('\n');
+                for wrapped_line in wrap(line, width, indent_str, indent_str) {
+                    let line_index = self.formatted.matches('\n').count();
+                    urls.find(line_index, &wrapped_line, indent_str.len(), &mut self.spans);
+                    let _ = writeln!(
+                        self.formatted,
+                        "{}{}{}",
+                        theme.text,
+                        highlight_indented(&wrapped_line, indent_str),
+                        text_reset
+                    );
                 }
             }
         }
 
-        if !self.reactions.is_empty() {
-            let gray = Fg(AnsiValue::grayscale(10)).to_string();
+        // Attachments are indented under the message, behind a bar in their own color
+        let bar_indent = format!("{}\u{258c} ", indent_str);
+        for attachment in &self.attachments {
+            let bar = attachment
+                .color
+                .map_or_else(|| theme.border.clone(), |(r, g, b)| Style::rgb(r, g, b));
+            let contents = if config.emoji {
+                ::emoji::replace_shortcodes(&attachment.contents)
+            } else {
+                Cow::Borrowed(attachment.contents.as_str())
+            };
+            let mut urls = Urls::new(&contents);
 
-            let mut reactions_string = String::new();
-            for (r, count) in &self.reactions {
-                let _ = write!(reactions_string, "{}({}) ", r, count);
+            for line in contents.lines() {
+                let mut wrapped = wrap(line, width, &bar_indent, &bar_indent);
+                // Blank lines still get a bar, so it doesn't break up the attachment
+                if wrapped.is_empty() {
+                    wrapped.push(bar_indent.clone());
+                }
+                for wrapped_line in wrapped {
+                    let line_index = self.formatted.matches('\n').count();
+                    urls.find(line_index, &wrapped_line, bar_indent.len(), &mut self.spans);
+                    let _ = writeln!(
+                        self.formatted,
+                        "{}{}\u{258c}{} {}{}{}",
+                        indent_str,
+                        bar,
+                        RESET,
+                        theme.text,
+                        highlight(&wrapped_line[bar_indent.len()..]),
+                        text_reset
+                    );
+                }
             }
+        }
 
-            let wrapper = Wrapper::with_splitter(width, NoHyphenation)
-                .break_words(true)
-                .initial_indent(indent_str)
-                .subsequent_indent(indent_str);
-            for line in wrapper.wrap_iter(&reactions_string) {
-                // Apparently terminal colors are reset by the Goto mechanism I'm using to move
-                // from one line to another
-                self.formatted.push_str(&gray);
-                self.formatted.push_str(&line);
-                self.formatted.push('\n');
+        // Actions are laid out in rows like buttons, each one whole on its line
+        let mut column = width;
+        for (i, action) in self.actions.iter().enumerate() {
+            let label = format!("[{}]", action.label);
+            let label = ::width::truncate_to_width(&label, width.saturating_sub(indent_str.len()));
+            let label_width = str_width(label);
+            if column + 1 + label_width > width {
+                if column < width {
+                    let _ = writeln!(self.formatted, "{}", RESET);
+                }
+                let _ = write!(self.formatted, "{}{}", indent_str, theme.reactions);
+                column = indent_str.len();
+            } else {
+                self.formatted.push(' ');
+                column += 1;
             }
+            self.spans.push(Span {
+                line: self.formatted.matches('\n').count(),
+                columns: column..column + label_width,
+                target: SpanTarget::Action(i),
+            });
+            self.formatted.push_str(label);
+            column += label_width;
+        }
+        if !self.actions.is_empty() {
+            let _ = writeln!(self.formatted, "{}", RESET);
+        }
 
-            // Clean trailing whitespace from messages
-            while self.formatted.ends_with(|p: char| p.is_whitespace()) {
-                self.formatted.pop();
+        if !self.reactions.is_empty() {
+            let mut reactions_string = String::new();
+            for (r, count) in &self.reactions {
+                let name = if config.emoji {
+                    ::emoji::to_unicode(r).unwrap_or(r)
+                } else {
+                    r
+                };
+                let _ = write!(reactions_string, "{}({}) ", name, count);
             }
 
-            let _ = write!(self.formatted, "{}", Fg(Reset));
+            let mut reactions = self.reactions.iter();
+            for line in wrap(&reactions_string, width, indent_str, indent_str) {
+                let line_index = self.formatted.matches('\n').count();
+                let mut rest = line.as_str();
+                while let Some(start) = rest.find(|c: char| !c.is_whitespace()) {
+                    let end = rest[start..]
+                        .find(char::is_whitespace)
+                        .map_or(rest.len(), |e| start + e);
+                    let token = &rest[start..end];
+                    let column = str_width(&line[..line.len() - rest.len() + start]);
+                    // A reaction too long for a line is split, and only its end has the count
+                    let reaction = if token.ends_with(')') {
+                        reactions.next()
+                    } else {
+                        self.reactions.get(self.reactions.len() - reactions.len())
+                    };
+                    if let Some((name, _)) = reaction {
+                        self.spans.push(Span {
+                            line: line_index,
+                            columns: column..column + str_width(token),
+                            target: SpanTarget::Reaction(name.clone()),
+                        });
+                    }
+                    rest = &rest[end..];
+                }
+                let _ = writeln!(self.formatted, "{}{}{}", theme.reactions, line, RESET);
+            }
         }
 
         // Clean trailing whitespace from messages
@@ -187,3 +473,331 @@ impl ChanMessage {
         &self.formatted
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use width::str_width;
+
+    fn message(sender: &str, contents: &str) -> ChanMessage {
+        ChanMessage::from(conn::Message {
+            server: conn::ServerId::unique(),
+            channel: "channel".into(),
+            sender: sender.into(),
+            contents: contents.into(),
+            attachments: Vec::new(),
+            actions: Vec::new(),
+            files: Vec::new(),
+            is_bot: false,
+            timestamp: DateTime::now(),
+            reactions: vec![("+1::skin-tone-4".into(), 2), ("sushi".into(), 1)],
+        })
+    }
+
+    fn message_at(sender: &str, minutes: i64) -> ChanMessage {
+        use chrono::TimeZone;
+        let noon = ::chrono::Local
+            .ymd(2026, 10, 19)
+            .and_hms(12, 0, 0)
+            .with_timezone(&::chrono::Utc);
+        ChanMessage::from(conn::Message {
+            server: conn::ServerId::unique(),
+            channel: "channel".into(),
+            sender: sender.into(),
+            contents: "hello".into(),
+            attachments: Vec::new(),
+            actions: Vec::new(),
+            files: Vec::new(),
+            is_bot: false,
+            timestamp: (noon + ::chrono::Duration::minutes(minutes)).into(),
+            reactions: Vec::new(),
+        })
+    }
+
+    #[test]
+    fn wide_glyphs_stay_inside_width() {
+        let mut msg = message(
+            "山田太郎",
+            "こんにちは、世界！ \u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467} family photo\n\
+             cafe\u{0301} \u{1F1EF}\u{1F1F5} 한국어 텍스트도 잘 보여야 합니다",
+        );
+        for width in 24..60 {
+            for line in msg
+                .formatted_to(width, &DisplayConfig::default(), &Theme::default())
+                .lines()
+            {
+                assert!(str_width(line) <= width, "{:?} wider than {}", line, width);
+            }
+        }
+    }
+
+    #[test]
+    fn continuation_lines_align_with_wide_sender() {
+        let mut msg = message("日本", "aaaa bbbb cccc dddd");
+        let formatted = msg
+            .formatted_to(26, &DisplayConfig::default(), &Theme::default())
+            .to_string();
+        let lines: Vec<&str> = formatted.lines().collect();
+        // Two lines of text and one of reactions
+        assert_eq!(lines.len(), 3);
+        // (HH:MM) 日本: is 14 columns wide
+        assert_eq!(str_width(lines[0]), 14 + "aaaa bbbb".len());
+        assert_eq!(lines[1], "    cccc dddd");
+    }
+
+    #[test]
+    fn bots_are_marked_after_their_name() {
+        let mut msg = message("jenkins", "aaaa bbbb cccc dddd");
+        msg.is_bot = true;
+        let formatted = msg
+            .formatted_to(33, &DisplayConfig::default(), &Theme::default())
+            .to_string();
+        let lines: Vec<&str> = formatted.lines().collect();
+        assert!(lines[0].contains(" [bot]"));
+        // (HH:MM) jenkins [bot]: is 23 columns wide
+        assert_eq!(str_width(lines[0]), 23 + "aaaa bbbb".len());
+        assert_eq!(lines[1], "    cccc dddd");
+        // The mark isn't part of the name, which runs of messages are grouped by
+        assert_eq!(msg.sender(), "jenkins");
+    }
+
+    #[test]
+    fn emoji_glyphs_can_be_turned_off() {
+        let mut msg = message("bob", "ship it :partyparrot: :tada:");
+        let with_glyphs = msg
+            .formatted_to(60, &DisplayConfig::default(), &Theme::default())
+            .to_string();
+        assert!(with_glyphs.contains("ship it :partyparrot: \u{1F389}"));
+        assert!(with_glyphs.contains("\u{1F44D}\u{1F3FD}(2) \u{1F363}(1)"));
+
+        let config = DisplayConfig {
+            emoji: false,
+            ..DisplayConfig::default()
+        };
+        let mut msg = message("bob", "ship it :partyparrot: :tada:");
+        let without_glyphs = msg.formatted_to(60, &config, &Theme::default()).to_string();
+        assert!(without_glyphs.contains(":tada:"));
+        assert!(without_glyphs.contains("+1::skin-tone-4(2) sushi(1)"));
+    }
+
+    #[test]
+    fn time_formats() {
+        use chrono::TimeZone;
+        let time = ::chrono::FixedOffset::east(3600)
+            .ymd(2026, 1, 2)
+            .and_hms(15, 4, 5);
+        assert_eq!(TimeFormat::TwentyFourHour.format(&time), "15:04");
+        assert_eq!(TimeFormat::TwentyFourHourSeconds.format(&time), "15:04:05");
+        assert_eq!(TimeFormat::TwelveHour.format(&time), "3:04 PM");
+        assert_eq!(TimeFormat::TwelveHourSeconds.format(&time), "3:04:05 PM");
+        assert_eq!(TimeFormat::Iso.format(&time), "2026-01-02T15:04:05");
+    }
+
+    #[test]
+    fn day_separators_and_compact_runs() {
+        let config = DisplayConfig {
+            compact: true,
+            ..DisplayConfig::default()
+        };
+        let mut messages = vec![
+            message_at("alice", 0),
+            message_at("alice", 2),
+            message_at("alice", 20),
+            message_at("bob", 21),
+            message_at("bob", 24 * 60),
+        ];
+        regroup(&mut messages, 0, &config);
+        let grouping: Vec<_> = messages
+            .iter()
+            .map(|m| (m.day_separator, m.continuation))
+            .collect();
+        assert_eq!(
+            grouping,
+            vec![
+                (true, false),
+                (false, true),
+                (false, false),
+                (false, false),
+                (true, false),
+            ]
+        );
+
+        let first = messages[0]
+            .formatted_to(60, &config, &Theme::default())
+            .to_string();
+        assert!(first
+            .lines()
+            .next()
+            .unwrap()
+            .contains("Monday, October 19, 2026"));
+        assert!(first.lines().nth(1).unwrap().contains("(12:00) "));
+        assert_eq!(
+            messages[1].formatted_to(60, &config, &Theme::default()),
+            "    hello"
+        );
+
+        regroup(&mut messages, 0, &DisplayConfig::default());
+        assert!(!messages[1].continuation);
+    }
+
+    #[test]
+    fn clickable_spans() {
+        let config = DisplayConfig {
+            emoji: false,
+            ..DisplayConfig::default()
+        };
+        let mut msg = message(
+            "bob",
+            "see https://example.com/a/very/long/path/that/wraps and\nhttps://b.example.org/",
+        );
+        let formatted = msg.formatted_to(40, &config, &Theme::default()).to_string();
+        let lines: Vec<&str> = formatted.lines().collect();
+
+        let long = "https://example.com/a/very/long/path/that/wraps";
+        let urls: Vec<&Span> = msg
+            .spans()
+            .iter()
+            .filter(|s| s.target == SpanTarget::Url(long.into()))
+            .collect();
+        // The URL is too long for a line, and the part on the first line still opens all of it
+        assert_eq!(urls.len(), 1);
+        let line = lines[urls[0].line];
+        let start = str_width(&line[..line.find("https").unwrap()]);
+        assert_eq!(urls[0].columns.start, start);
+        assert!(urls[0].columns.end <= 40);
+        assert!(msg
+            .spans()
+            .iter()
+            .any(|s| s.target == SpanTarget::Url("https://b.example.org/".into())));
+
+        // The start of a wrapped URL opens it, even when another URL is the same as that start
+        let short = "https://ex.com/a";
+        let longer = "https://ex.com/abcdefghijklmnopqrstuvwxyz0123";
+        let mut wrapped = message("alice", &format!("{} {}", short, longer));
+        wrapped.formatted_to(40, &config, &Theme::default());
+        let targets: Vec<(usize, &SpanTarget)> = wrapped
+            .spans()
+            .iter()
+            .filter(|s| s.line < 2)
+            .map(|s| (s.line, &s.target))
+            .collect();
+        assert_eq!(
+            targets,
+            vec![
+                (0, &SpanTarget::Url(short.into())),
+                (1, &SpanTarget::Url(longer.into()))
+            ]
+        );
+
+        let reactions: Vec<&Span> = msg
+            .spans()
+            .iter()
+            .filter(|s| matches!(s.target, SpanTarget::Reaction(_)))
+            .collect();
+        assert_eq!(reactions.len(), 2);
+        assert_eq!(reactions[1].target, SpanTarget::Reaction("sushi".into()));
+        let reaction_line = lines[reactions[1].line];
+        let sushi = "    +1::skin-tone-4(2) ".len();
+        assert_eq!(reactions[1].columns, sushi..sushi + "sushi(1)".len());
+        assert!(reaction_line.contains("sushi(1)"));
+    }
+
+    #[test]
+    fn attachments_have_a_colored_bar() {
+        let config = DisplayConfig {
+            emoji: false,
+            ..DisplayConfig::default()
+        };
+        let mut msg = message("ci", "Build finished");
+        msg.attachments = vec![conn::Attachment {
+            color: Some((255, 0, 0)),
+            contents: "Build #42 failed\n\nlog: https://ci.example.com/42".into(),
+        }];
+        let formatted = msg.formatted_to(40, &config, &Theme::default()).to_string();
+        let lines: Vec<&str> = formatted.lines().collect();
+        let bar = "    \x1b[38;2;255;0;0m\u{258c}\x1b[m ";
+        assert_eq!(lines[1], format!("{}Build #42 failed", bar));
+        assert_eq!(lines[2], bar);
+        assert_eq!(lines[3], format!("{}log: https://ci.example.com/42", bar));
+        assert!(lines[4].contains("sushi(1)"));
+
+        let url = msg
+            .spans()
+            .iter()
+            .find(|s| s.target == SpanTarget::Url("https://ci.example.com/42".into()))
+            .unwrap();
+        assert_eq!(url.line, 3);
+        assert_eq!(url.columns.start, "    \u{258c} log: ".chars().count());
+        assert_eq!(
+            msg.texts().collect::<Vec<_>>(),
+            vec![
+                "Build finished",
+                "Build #42 failed\n\nlog: https://ci.example.com/42"
+            ]
+        );
+    }
+
+    #[test]
+    fn found_text_is_highlighted() {
+        let config = DisplayConfig {
+            emoji: false,
+            ..DisplayConfig::default()
+        };
+        let theme = Theme::default();
+        let mut msg = message("alice", "lunch?\nsoup for lunch");
+        msg.reactions.clear();
+        msg.formatted_to(40, &config, &theme);
+        msg.highlight(Some(&Regex::new("lunch").unwrap()));
+        let formatted = msg.formatted_to(40, &config, &theme).to_string();
+        let lines: Vec<&str> = formatted.lines().collect();
+        let found = format!("{}lunch{}{}", theme.found, RESET, theme.text);
+        assert!(lines[0].ends_with(&format!("{}?", found)), "{:?}", lines[0]);
+        assert_eq!(lines[1], format!("{}    soup for {}", theme.text, found));
+
+        msg.highlight(None);
+        let formatted = msg.formatted_to(40, &config, &theme);
+        assert!(!formatted.contains(&theme.found.to_string()));
+    }
+
+    #[test]
+    fn actions_are_laid_out_in_rows() {
+        let mut msg = message("deploy-bot", "Deploy?");
+        msg.reactions.clear();
+        msg.actions = [
+            "Approve",
+            "Deny",
+            "Env: staging",
+            "a very long label indeed",
+        ]
+        .iter()
+        .map(|label| conn::Action {
+            label: label.to_string(),
+            url: None,
+            payload: String::new(),
+        })
+        .collect();
+        let formatted = msg
+            .formatted_to(30, &DisplayConfig::default(), &Theme::default())
+            .to_string();
+        let lines: Vec<&str> = formatted.lines().collect();
+        let row = |text: &str| format!("    {}{}{}", Theme::default().reactions, text, RESET);
+        assert_eq!(lines[1], row("[Approve] [Deny]"));
+        assert_eq!(lines[2], row("[Env: staging]"));
+        assert_eq!(lines[3], row("[a very long label indeed]"));
+
+        let actions: Vec<(usize, ::std::ops::Range<usize>, SpanTarget)> = msg
+            .spans()
+            .iter()
+            .map(|s| (s.line, s.columns.clone(), s.target.clone()))
+            .collect();
+        assert_eq!(
+            actions,
+            vec![
+                (1, 4..13, SpanTarget::Action(0)),
+                (1, 14..20, SpanTarget::Action(1)),
+                (2, 4..18, SpanTarget::Action(2)),
+                (3, 4..30, SpanTarget::Action(3)),
+            ]
+        );
+    }
+}
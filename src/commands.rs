@@ -0,0 +1,235 @@
+//! The /commands the client handles itself, and the help and completion for every command.
+//!
+//! Connections list the commands they handle in `ConnEvent::ServerConnected`, and anything typed
+//! with a leading slash is looked up in both lists. A command nobody has heard of is reported in
+//! the channel it was typed in, rather than being sent anywhere.
+use conn::{Command, Handler};
+
+pub const CLIENT_COMMANDS: &[Command] = &[
+    Command {
+        name: "help",
+        args: "[COMMAND]",
+        help: "Lists the commands, or explains one of them",
+        handler: Handler::Client,
+    },
+    Command {
+        name: "url",
+        args: "",
+        help: "Lists the URLs in this channel to open, copy or download",
+        handler: Handler::Client,
+    },
+    Command {
+        name: "find",
+        args: "REGEX",
+        help: "Lists the loaded messages in every server that match REGEX, and highlights the \
+               matches. Ctrl-f starts typing one and shows matches as you go.",
+        handler: Handler::Client,
+    },
+    Command {
+        name: "download",
+        args: "[URL...]",
+        help: "Saves the files posted with the selected message, or the newest message with \
+               files, or the given URLs",
+        handler: Handler::Client,
+    },
+    Command {
+        name: "upload",
+        args: "[--title TITLE] [--comment TEXT] [--thread] FILE...",
+        help: "Sends files to this channel, or into the selected message's thread with --thread",
+        handler: Handler::Client,
+    },
+];
+
+/// Splits `/name arguments` into the name and everything after it, or None if `line` isn't a
+/// command
+pub fn split(line: &str) -> Option<(&str, &str)> {
+    if !line.starts_with('/') {
+        return None;
+    }
+    let line = &line[1..];
+    match line.find(char::is_whitespace) {
+        Some(i) => Some((&line[..i], &line[i..])),
+        None => Some((line, "")),
+    }
+}
+
+/// The names of `commands` that start with `prefix`, in alphabetical order
+pub fn complete_name<'a, I>(commands: I, prefix: &str) -> Vec<String>
+where
+    I: IntoIterator<Item = &'a Command>,
+{
+    let mut names: Vec<String> = commands
+        .into_iter()
+        .filter(|c| c.name.starts_with(prefix))
+        .map(|c| String::from(c.name))
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// Splits the arguments of commands like `/msg @alice @bob hello` into the names, without their
+/// @, and the text after them
+pub fn split_recipients(args: &str) -> (Vec<&str>, &str) {
+    let mut names = Vec::new();
+    let mut rest = args.trim_start();
+    while rest.starts_with('@') {
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        names.push(&rest[1..end]);
+        rest = rest[end..].trim_start();
+    }
+    (names, rest.trim_end())
+}
+
+/// The channel named by `/join #CHANNEL`, without its #
+pub fn join_target(args: &str) -> Result<&str, String> {
+    let name = args.trim().trim_start_matches('#');
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        return Err(String::from("Usage: /join #CHANNEL"));
+    }
+    Ok(name)
+}
+
+/// The name of the channel `/create [--private] NAME` makes, without its #, and whether it's
+/// private
+pub fn create_target(args: &str) -> Result<(&str, bool), String> {
+    let usage = || String::from("Usage: /create [--private] NAME");
+    let mut private = false;
+    let mut name = None;
+    for word in args.split_whitespace() {
+        match word {
+            "--private" | "-p" => private = true,
+            _ if name.is_none() => name = Some(word.trim_start_matches('#')),
+            _ => return Err(usage()),
+        }
+    }
+    match name {
+        Some(name) if !name.is_empty() => Ok((name, private)),
+        _ => Err(usage()),
+    }
+}
+
+/// The people `/invite @USER...` names, without their @
+pub fn invitees(args: &str) -> Result<Vec<&str>, String> {
+    let names: Vec<&str> = args
+        .split_whitespace()
+        .map(|n| n.trim_start_matches('@'))
+        .filter(|n| !n.is_empty())
+        .collect();
+    if names.is_empty() {
+        return Err(String::from("Usage: /invite @USER..."));
+    }
+    Ok(names)
+}
+
+pub fn usage(command: &Command) -> String {
+    if command.args.is_empty() {
+        format!("/{}", command.name)
+    } else {
+        format!("/{} {}", command.name, command.args)
+    }
+}
+
+/// What /help shows: every command, or just the one asked about
+pub fn help<'a, I>(commands: I, topic: &str) -> Result<String, String>
+where
+    I: IntoIterator<Item = &'a Command>,
+{
+    let mut commands: Vec<&Command> = commands.into_iter().collect();
+    commands.sort_by_key(|c| c.name);
+    let topic = topic.trim().trim_start_matches('/');
+    if !topic.is_empty() {
+        return commands
+            .iter()
+            .find(|c| c.name == topic)
+            .map(|c| format!("{}\n{}", usage(c), c.help))
+            .ok_or_else(|| format!("There's no /{} command", topic));
+    }
+
+    let mut text = String::from("Commands:");
+    for command in commands {
+        text.push_str(&format!("\n{}  {}", usage(command), command.help));
+    }
+    text.push_str("\n+:emoji:  Reacts to the selected message, or the newest one");
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BACKEND: &[Command] = &[Command {
+        name: "join",
+        args: "CHANNEL",
+        help: "Joins a channel",
+        handler: Handler::Backend,
+    }];
+
+    #[test]
+    fn splits_commands() {
+        assert_eq!(split("/help"), Some(("help", "")));
+        assert_eq!(split("/upload  a b"), Some(("upload", "  a b")));
+        assert_eq!(split("/"), Some(("", "")));
+        assert_eq!(split("hello /help"), None);
+    }
+
+    #[test]
+    fn splits_recipients() {
+        assert_eq!(
+            split_recipients(" @alice @bob  hi @carol "),
+            (vec!["alice", "bob"], "hi @carol")
+        );
+        assert_eq!(split_recipients("@alice"), (vec!["alice"], ""));
+        assert_eq!(split_recipients("hello"), (vec![], "hello"));
+    }
+
+    #[test]
+    fn join_targets() {
+        assert_eq!(join_target(" #general "), Ok("general"));
+        assert_eq!(join_target("random"), Ok("random"));
+        assert!(join_target("#").is_err());
+        assert!(join_target("").is_err());
+        assert!(join_target("#a b").is_err());
+    }
+
+    #[test]
+    fn create_targets() {
+        assert_eq!(create_target("#plans"), Ok(("plans", false)));
+        assert_eq!(create_target("--private secrets"), Ok(("secrets", true)));
+        assert_eq!(create_target("secrets -p"), Ok(("secrets", true)));
+        assert!(create_target("--private").is_err());
+        assert!(create_target("#").is_err());
+        assert!(create_target("two names").is_err());
+    }
+
+    #[test]
+    fn invitee_names() {
+        assert_eq!(
+            invitees("@alice bob  @carol"),
+            Ok(vec!["alice", "bob", "carol"])
+        );
+        assert!(invitees("").is_err());
+        assert!(invitees("@").is_err());
+    }
+
+    #[test]
+    fn completes_names() {
+        let all = CLIENT_COMMANDS.iter().chain(BACKEND);
+        assert_eq!(complete_name(all.clone(), "u"), vec!["upload", "url"]);
+        assert_eq!(complete_name(all.clone(), "jo"), vec!["join"]);
+        assert_eq!(complete_name(all, "x"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn help_text() {
+        let all = CLIENT_COMMANDS.iter().chain(BACKEND);
+        let text = help(all.clone(), "").unwrap();
+        assert!(text.contains("\n/join CHANNEL  Joins a channel\n"));
+        assert!(text.contains("\n/url  Lists"));
+        assert_eq!(
+            help(all.clone(), "/join").unwrap(),
+            "/join CHANNEL\nJoins a channel"
+        );
+        assert!(help(all, "nope").is_err());
+    }
+}
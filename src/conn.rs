@@ -1,4 +1,6 @@
 pub use inlinable_string::InlinableString as IString;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::mpsc::SyncSender;
 use termion;
 
@@ -48,90 +50,338 @@ impl DateTime {
     }
 }
 
+/// Which connection something belongs to. Every connection gets its own when it starts, so two
+/// workspaces with the same name are still kept apart.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ServerId(usize);
+
+impl ServerId {
+    pub fn unique() -> Self {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        ServerId(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// A channel as its connection knows it, like a Slack conversation id or a Discord snowflake.
+/// Names change and needn't be unique, so events refer to channels by this instead.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ChannelId(IString);
+
+impl ChannelId {
+    pub fn as_str(&self) -> &str {
+        self.0.as_ref()
+    }
+}
+
+impl<'a> From<&'a str> for ChannelId {
+    fn from(id: &'a str) -> ChannelId {
+        ChannelId(IString::from(id))
+    }
+}
+
+impl ::std::fmt::Display for ChannelId {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
 pub struct Message {
-    pub server: IString,
-    pub channel: IString,
+    pub server: ServerId,
+    pub channel: ChannelId,
     pub sender: IString,
     pub contents: String,
+    /// Boxes of rich content under the text, like the alerts that integrations post
+    pub attachments: Vec<Attachment>,
+    pub actions: Vec<Action>,
+    /// Links to the files posted with the message, which is what /download saves
+    pub files: Vec<String>,
+    /// Posted by a bot or an integration rather than a person
+    pub is_bot: bool,
     pub timestamp: DateTime,
     pub reactions: Vec<(IString, usize)>,
 }
 
+/// A message found by a search, which can be anywhere in a server
+#[derive(Clone)]
+pub struct SearchHit {
+    pub channel: ChannelId,
+    pub channel_name: IString,
+    pub sender: IString,
+    pub timestamp: DateTime,
+    pub text: String,
+}
+
+/// A button or menu option in a message, which is drawn as something to click on
+#[derive(Clone, Debug, PartialEq)]
+pub struct Action {
+    pub label: String,
+    /// Opened in the browser when the action is taken, for buttons that are also links
+    pub url: Option<String>,
+    /// Whatever the connection needs to take the action, handed back in `TuiEvent::TakeAction`
+    pub payload: String,
+}
+
+/// Part of a message that is drawn indented with a bar down its left side
+#[derive(Clone, Debug, PartialEq)]
+pub struct Attachment {
+    /// The color of the bar as red, green and blue, or None for the plain text color
+    pub color: Option<(u8, u8, u8)>,
+    pub contents: String,
+}
+
+/// Which group a channel is listed under in the channel list, in the order they are shown
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Section {
+    Starred,
+    Channels,
+    Private,
+    DirectMessages,
+}
+
+impl Section {
+    pub fn title(self) -> &'static str {
+        match self {
+            Section::Starred => "Starred",
+            Section::Channels => "Channels",
+            Section::Private => "Private",
+            Section::DirectMessages => "Direct Messages",
+        }
+    }
+}
+
+pub struct ChannelInfo {
+    pub id: ChannelId,
+    /// What the channel is shown as, which can change while the id stays the same
+    pub name: IString,
+    pub section: Section,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Handler {
+    Client,
+    Backend,
+}
+
+/// A /command. The client has its own, and each connection lists the ones it handles when it
+/// connects.
+#[derive(Clone, Copy, Debug)]
+pub struct Command {
+    pub name: &'static str,
+    /// How to write the arguments, like `[--title TITLE] FILE...`
+    pub args: &'static str,
+    pub help: &'static str,
+    pub handler: Handler,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Direction {
+    Download,
+    Upload,
+}
+
+/// How far along a file upload or download is
+pub enum TransferStatus {
+    Progress {
+        file: String,
+        done: u64,
+        total: Option<u64>,
+    },
+    /// Where the file was saved to, or which file was uploaded
+    Finished(PathBuf),
+    Failed(String),
+}
+
 /// Events that a connection can send to a frontend
 pub enum ConnEvent {
     Message(Message),
     /*
     MessageEdited {
-        server: IString,
-        channel: IString,
+        server: ServerId,
+        channel: ChannelId,
         contents: String,
         timestamp: DateTime,
     },
     */
+    /// Messages from before we connected. These can overlap ones we already have.
     HistoryLoaded {
-        server: IString,
-        channel: IString,
+        server: ServerId,
+        channel: ChannelId,
         messages: Vec<Message>,
-        read_at: DateTime,
+        /// None for history that was loaded to show one message, which leaves the channel's read
+        /// marker where it was
+        read_at: Option<DateTime>,
     },
     Input(termion::event::Event),
     Error(String),
     ServerConnected {
+        id: ServerId,
         name: IString,
-        channels: Vec<IString>,
+        channels: Vec<ChannelInfo>,
         completer: Option<Box<Completer>>, // Autocompletion should be a blocking operation, no channels
         sender: SyncSender<TuiEvent>,      // How we tell the connection to do something
+        commands: &'static [Command],
+    },
+    /// A channel to show in the channel list, because we joined or created it or were added to it
+    ChannelAdded {
+        server: ServerId,
+        channel: ChannelInfo,
+    },
+    /// Switches to a channel, because the user opened it with something like /msg
+    ShowChannel {
+        server: ServerId,
+        channel: ChannelId,
+    },
+    /// A channel we left, or that was archived or deleted
+    ChannelRemoved {
+        server: ServerId,
+        channel: ChannelId,
+    },
+    ChannelRenamed {
+        server: ServerId,
+        channel: ChannelId,
+        name: IString,
+    },
+    /// Something the user should see in a channel they're looking at, like why a command failed
+    Notice {
+        server: ServerId,
+        channel: ChannelId,
+        text: String,
     },
     MarkChannelRead {
-        server: IString,
-        channel: IString,
+        server: ServerId,
+        channel: ChannelId,
         read_at: DateTime,
     },
     ReactionAdded {
-        server: IString,
-        channel: IString,
+        server: ServerId,
+        channel: ChannelId,
         timestamp: DateTime,
         reaction: IString,
     },
     ReactionRemoved {
-        server: IString,
-        channel: IString,
+        server: ServerId,
+        channel: ChannelId,
         timestamp: DateTime,
         reaction: IString,
     },
+    /// One page of the results of a search
+    SearchResults {
+        server: ServerId,
+        query: String,
+        /// Counted from 1
+        page: u32,
+        pages: u32,
+        total: u32,
+        hits: Vec<SearchHit>,
+    },
+    /// `id` is the URL for a download and the path for an upload
+    Transfer {
+        server: ServerId,
+        id: String,
+        direction: Direction,
+        status: TransferStatus,
+    },
     Resize,
 }
 
 /// Messages sent by a frontend to a connection
 pub enum TuiEvent {
     SendMessage {
-        server: IString,
-        channel: IString,
+        server: ServerId,
+        channel: ChannelId,
         contents: String,
     },
     MarkRead {
-        server: IString,
-        channel: IString,
+        server: ServerId,
+        channel: ChannelId,
     },
     Command {
-        server: IString,
-        channel: IString,
+        server: ServerId,
+        channel: ChannelId,
         command: IString,
     },
     AddReaction {
-        server: IString,
-        channel: IString,
+        server: ServerId,
+        channel: ChannelId,
+        reaction: IString,
+        timestamp: DateTime,
+    },
+    /// Adds the reaction if we haven't reacted with it already, otherwise removes it
+    ToggleReaction {
+        server: ServerId,
+        channel: ChannelId,
         reaction: IString,
         timestamp: DateTime,
     },
     SendTyping {
-        server: IString,
-        channel: IString,
+        server: ServerId,
+        channel: ChannelId,
+    },
+    /// Searches every channel in the server, including history that hasn't been loaded. Results
+    /// come back as `ConnEvent::SearchResults`, and problems are shown in `channel`.
+    Search {
+        server: ServerId,
+        channel: ChannelId,
+        query: String,
+        page: u32,
+    },
+    /// Loads the messages either side of the one sent at `timestamp`, so it can be shown
+    LoadAround {
+        server: ServerId,
+        channel: ChannelId,
+        timestamp: DateTime,
+    },
+    /// Presses a button or picks a menu option in the message sent at `timestamp`
+    TakeAction {
+        server: ServerId,
+        channel: ChannelId,
+        timestamp: DateTime,
+        action: Action,
     },
+    /// Saves a file linked in a message to `directory`, with whatever credentials it needs
+    Download {
+        server: ServerId,
+        url: String,
+        directory: PathBuf,
+    },
+    Upload {
+        server: ServerId,
+        channel: ChannelId,
+        files: Vec<PathBuf>,
+        title: Option<String>,
+        comment: Option<String>,
+        /// The message whose thread the files go in
+        thread: Option<DateTime>,
+    },
+}
+
+impl TuiEvent {
+    /// The connection the event is for
+    pub fn server(&self) -> ServerId {
+        use self::TuiEvent::*;
+        match *self {
+            SendMessage { server, .. }
+            | MarkRead { server, .. }
+            | Command { server, .. }
+            | AddReaction { server, .. }
+            | ToggleReaction { server, .. }
+            | SendTyping { server, .. }
+            | Search { server, .. }
+            | LoadAround { server, .. }
+            | TakeAction { server, .. }
+            | Download { server, .. }
+            | Upload { server, .. } => server,
+        }
+    }
 }
 
 // This is very derpy
 // Don't want to do this with channels, because autocompletion should happen instantly
 pub trait Completer: Send {
     fn autocomplete(&self, _word: &str) -> Vec<String>;
+
+    /// Completes the last argument of one of this connection's /commands
+    fn complete_argument(&self, _command: &str, _argument: &str) -> Vec<String> {
+        Vec::new()
+    }
 }
@@ -39,6 +39,13 @@ impl<T> CursorVec<T> {
         }
     }
 
+    /// Moves the cursor to `index`, if there is an element there
+    pub fn seek(&mut self, index: usize) {
+        if index < self.vec.len() {
+            self.index = index;
+        }
+    }
+
     pub fn get_first_mut(&mut self) -> &mut T {
         unsafe { self.vec.get_unchecked_mut(0) }
     }
@@ -51,10 +58,6 @@ impl<T> CursorVec<T> {
         self.vec.iter()
     }
 
-    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut T> {
-        self.vec.iter_mut()
-    }
-
     pub fn tell(&self) -> usize {
         self.index
     }
@@ -71,3 +74,17 @@ impl<T> CursorVec<T> {
         self.vec.sort_by_key(f);
     }
 }
+
+impl<T> ::std::ops::Index<usize> for CursorVec<T> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        &self.vec[index]
+    }
+}
+
+impl<T> ::std::ops::IndexMut<usize> for CursorVec<T> {
+    fn index_mut(&mut self, index: usize) -> &mut T {
+        &mut self.vec[index]
+    }
+}
@@ -1,14 +1,44 @@
-use conn::{Conn, DateTime, Event, IString};
+use conn::{
+    ChannelId, ChannelInfo, Command, ConnEvent, DateTime, Handler, IString, Section, ServerId,
+    TuiEvent,
+};
 use std::borrow::Borrow;
-use std::sync::mpsc::SyncSender;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::mpsc::{sync_channel, SyncSender};
+use std::sync::{Arc, Mutex};
 
 lazy_static! {
     pub static ref CLIENT: ::reqwest::Client = ::reqwest::Client::new();
 }
 
+const DISCORD_COMMANDS: &[Command] = &[
+    Command {
+        name: "msg",
+        args: "@USER [TEXT]",
+        help: "Opens a direct message with someone who has written in this server, and sends \
+               TEXT there",
+        handler: Handler::Backend,
+    },
+    Command {
+        name: "query",
+        args: "@USER [TEXT]",
+        help: "The same as /msg",
+        handler: Handler::Backend,
+    },
+];
+
 pub struct DiscordConn {
+    token: String,
+    server: ServerId,
     name: IString,
-    channels: Vec<IString>,
+    channels: Vec<(IString, ::discord::Snowflake)>,
+    /// The TUI knows messages by their channel and time, but replying to one takes its id
+    message_ids: Arc<Mutex<BTreeMap<(ChannelId, DateTime), ::discord::Snowflake>>>,
+    /// Everyone who wrote in the history we loaded, since finding people by name needs
+    /// permissions we usually don't have
+    users: Arc<Mutex<BTreeMap<IString, ::discord::Snowflake>>>,
+    sender: SyncSender<ConnEvent>,
 }
 
 macro_rules! deserialize_or_log {
@@ -40,6 +70,29 @@ struct Response {
     status: ::reqwest::StatusCode,
 }
 
+/// All we need from a channel Discord has just made for us
+#[derive(Deserialize)]
+struct CreatedChannel {
+    id: ::discord::Snowflake,
+}
+
+/// How the TUI refers to a channel
+fn channel_id(id: &::discord::Snowflake) -> ChannelId {
+    ChannelId::from(id.to_string().as_str())
+}
+
+/// The body of a response, or Discord's explanation of why the request failed
+fn response_text(response: &mut ::reqwest::Response) -> Result<String, String> {
+    let text = response.text().map_err(|e| e.to_string())?;
+    if response.status().is_success() {
+        Ok(text)
+    } else {
+        Err(::serde_json::from_str::<::discord::Error>(&text)
+            .map(|e| e.message.to_string())
+            .unwrap_or(text))
+    }
+}
+
 pub fn permissions_in(
     chan: &::discord::Channel,
     guild: Option<&::discord::Guild>,
@@ -64,7 +117,7 @@ pub fn permissions_in(
 }
 
 impl DiscordConn {
-    pub fn create_on(token: &str, sender: SyncSender<Event>, server: &str) -> Result<(), ()> {
+    pub fn create_on(token: &str, sender: SyncSender<ConnEvent>, server: &str) -> Result<(), ()> {
         let guild_resp = CLIENT
             .get(&format!("{}{}", ::discord::BASE_URL, "/users/@me/guilds"))
             .header("Authorization", token)
@@ -101,23 +154,98 @@ impl DiscordConn {
             })
             .collect();
 
-        let channel_names: Vec<IString> = channels
+        let channel_names: Vec<ChannelInfo> = channels
             .iter()
-            .filter_map(|c| c.name.as_ref())
-            .map(|name| IString::from(name.borrow()))
+            .filter_map(|c| {
+                c.name.as_ref().map(|name| ChannelInfo {
+                    id: channel_id(&c.id),
+                    name: IString::from(name.borrow()),
+                    section: Section::Channels,
+                })
+            })
             .collect();
 
-        let _ = sender.send(Event::Connected(Box::new(Self {
+        let server = ServerId::unique();
+        let (tui_send, tui_recv) = sync_channel(100);
+        let _ = sender.send(ConnEvent::ServerConnected {
+            id: server,
             name: guild_name.clone(),
             channels: channel_names,
-        })));
+            completer: None,
+            sender: tui_send,
+            commands: DISCORD_COMMANDS,
+        });
+
+        let message_ids = Arc::new(Mutex::new(BTreeMap::new()));
+        let users = Arc::new(Mutex::new(BTreeMap::new()));
+        let mut conn = Self {
+            token: token.to_string(),
+            server,
+            name: guild_name,
+            channels: channels
+                .iter()
+                .filter_map(|c| {
+                    c.name
+                        .as_ref()
+                        .map(|name| (IString::from(name.borrow()), c.id.clone()))
+                })
+                .collect(),
+            message_ids: message_ids.clone(),
+            users: users.clone(),
+            sender: sender.clone(),
+        };
+        // Create a background thread that will handle events from the TUI
+        ::std::thread::spawn(move || {
+            while let Ok(event) = tui_recv.recv() {
+                if event.server() != conn.server {
+                    error!(
+                        "Discord connection {:?} got an event for {:?}",
+                        conn.server,
+                        event.server()
+                    );
+                    continue;
+                }
+                match event {
+                    TuiEvent::Download { url, directory, .. } => conn.download(url, directory),
+                    TuiEvent::Upload {
+                        channel,
+                        files,
+                        title,
+                        comment,
+                        thread,
+                        ..
+                    } => conn.upload(&channel, files, title, comment, thread),
+                    TuiEvent::SendMessage {
+                        channel, contents, ..
+                    } => {
+                        if let Err(e) = conn.send_message(&channel, &contents) {
+                            conn.notice(&channel, e);
+                        }
+                    }
+                    TuiEvent::Command {
+                        channel, command, ..
+                    } => {
+                        if let Err(e) = conn.handle_cmd(&command) {
+                            conn.notice(&channel, e);
+                        }
+                    }
+                    TuiEvent::AddReaction { channel, .. }
+                    | TuiEvent::ToggleReaction { channel, .. } => conn.notice(
+                        &channel,
+                        String::from("Reacting to messages isn't supported in Discord yet"),
+                    ),
+                    _ => {}
+                }
+            }
+        });
 
         for channel in channels.into_iter().filter(|c| c.name.is_some()) {
-            let channel_name = IString::from(channel.name.unwrap().borrow());
             let token = token.to_string();
             let sender = sender.clone();
-            let guild_name = guild_name.clone();
             let id = channel.id.clone();
+            let channel = channel_id(&id);
+            let message_ids = message_ids.clone();
+            let users = users.clone();
 
             ::std::thread::spawn(move || {
                 if let Ok(history_resp) = CLIENT
@@ -131,24 +259,48 @@ impl DiscordConn {
                     }) {
                     let history =
                         deserialize_or_log!(history_resp, Vec<::discord::Message>).unwrap();
+                    let mut messages = Vec::new();
                     for message in history {
                         let timestamp = ::chrono::DateTime::parse_from_rfc3339(&message.timestamp)
                             .unwrap()
                             .with_timezone(&::chrono::Utc);
-                        let _ = sender.send(Event::Message(::conn::Message {
+                        let mut contents = String::from(message.content);
+                        let files: Vec<String> = message
+                            .attachments
+                            .iter()
+                            .map(|a| a.url.to_string())
+                            .collect();
+                        for url in &files {
+                            contents.push('\n');
+                            contents.push_str(url);
+                        }
+                        message_ids
+                            .lock()
+                            .unwrap()
+                            .insert((channel.clone(), timestamp.into()), message.id.clone());
+                        users.lock().unwrap().insert(
+                            IString::from(message.author.username.borrow()),
+                            message.author.id.clone(),
+                        );
+                        messages.push(::conn::Message {
                             sender: IString::from(message.author.username.borrow()),
-                            server: guild_name.clone(),
+                            server,
                             timestamp: timestamp.into(),
-                            contents: String::from(message.content),
-                            channel: channel_name.clone(),
+                            contents,
+                            attachments: Vec::new(),
+                            actions: Vec::new(),
+                            files,
+                            is_bot: message.author.bot.unwrap_or(false),
+                            channel: channel.clone(),
                             reactions: Vec::new(),
-                        }));
+                        });
                     }
 
-                    let _ = sender.send(Event::HistoryLoaded {
-                        server: guild_name,
-                        channel: channel_name.clone(),
-                        read_at: DateTime::now(),
+                    let _ = sender.send(ConnEvent::HistoryLoaded {
+                        server,
+                        channel,
+                        messages,
+                        read_at: Some(DateTime::now()),
                     });
                 }
             });
@@ -158,12 +310,209 @@ impl DiscordConn {
     }
 }
 
-impl Conn for DiscordConn {
-    fn name(&self) -> &str {
-        &self.name
+impl DiscordConn {
+    fn handle_cmd(&mut self, cmd: &str) -> Result<(), String> {
+        let (name, args) = match cmd.find(char::is_whitespace) {
+            Some(i) => (&cmd[..i], cmd[i..].trim()),
+            None => (cmd, ""),
+        };
+        match name {
+            "msg" | "query" => {
+                let (names, text) = ::commands::split_recipients(args);
+                let user = match names.as_slice() {
+                    [user] => *user,
+                    [] => return Err(format!("Usage: /{} @USER [TEXT]", name)),
+                    _ => {
+                        return Err(String::from(
+                            "Discord only opens direct messages with one person at a time",
+                        ))
+                    }
+                };
+                let opened = self.open_dm(user)?;
+                let _ = self.sender.send(ConnEvent::ShowChannel {
+                    server: self.server,
+                    channel: opened.clone(),
+                });
+                if !text.is_empty() {
+                    self.send_message(&opened, text)?;
+                }
+                Ok(())
+            }
+            _ => Err(format!("Discord doesn't know the command /{}", name)),
+        }
+    }
+
+    /// Creates a DM channel with `user`, or finds the one we already have, and lists it under
+    /// their name
+    fn open_dm(&mut self, user: &str) -> Result<ChannelId, String> {
+        let user_id = self
+            .users
+            .lock()
+            .unwrap()
+            .get(user)
+            .cloned()
+            .ok_or_else(|| format!("Nobody called @{} has written in {}", user, self.name))?;
+        let text = CLIENT
+            .post(&format!("{}/users/@me/channels", ::discord::BASE_URL))
+            .header("Authorization", self.token.as_str())
+            .json(&json!({ "recipient_id": user_id }))
+            .send()
+            .map_err(|e| e.to_string())
+            .and_then(|mut r| response_text(&mut r))?;
+        let created: CreatedChannel = ::serde_json::from_str(&text).map_err(|e| e.to_string())?;
+
+        let id = channel_id(&created.id);
+        if self.channels.iter().any(|(_, known)| *known == created.id) {
+            return Ok(id);
+        }
+        let name = IString::from(user);
+        self.channels.push((name.clone(), created.id));
+        let _ = self.sender.send(ConnEvent::ChannelAdded {
+            server: self.server,
+            channel: ChannelInfo {
+                id: id.clone(),
+                name,
+                section: Section::DirectMessages,
+            },
+        });
+        Ok(id)
+    }
+
+    /// The channel the TUI means, if it's one of ours
+    fn snowflake(&self, channel: &ChannelId) -> Option<&::discord::Snowflake> {
+        self.channels
+            .iter()
+            .map(|(_, id)| id)
+            .find(|id| id.to_string() == channel.as_str())
     }
 
-    fn channels(&self) -> &[IString] {
-        &self.channels
+    /// Posts a message, and shows it once Discord has it
+    fn send_message(&self, channel: &ChannelId, contents: &str) -> Result<(), String> {
+        let channel_id = match self.snowflake(channel) {
+            Some(id) => id,
+            None => return Err(format!("Unknown channel: {}", channel)),
+        };
+        let text = CLIENT
+            .post(&format!(
+                "{}/channels/{}/messages",
+                ::discord::BASE_URL,
+                channel_id
+            ))
+            .header("Authorization", self.token.as_str())
+            .json(&json!({ "content": contents }))
+            .send()
+            .map_err(|e| e.to_string())
+            .and_then(|mut r| response_text(&mut r))?;
+        let message: ::discord::Message =
+            ::serde_json::from_str(&text).map_err(|e| e.to_string())?;
+        let timestamp: DateTime = ::chrono::DateTime::parse_from_rfc3339(message.timestamp)
+            .map_err(|e| e.to_string())?
+            .with_timezone(&::chrono::Utc)
+            .into();
+        self.message_ids
+            .lock()
+            .unwrap()
+            .insert((channel.clone(), timestamp), message.id.clone());
+        let _ = self.sender.send(ConnEvent::Message(::conn::Message {
+            sender: IString::from(message.author.username.borrow()),
+            server: self.server,
+            timestamp,
+            contents: message.content.into_owned(),
+            attachments: Vec::new(),
+            actions: Vec::new(),
+            files: Vec::new(),
+            is_bot: message.author.bot.unwrap_or(false),
+            channel: channel.clone(),
+            reactions: Vec::new(),
+        }));
+        Ok(())
+    }
+
+    fn notice(&self, channel: &ChannelId, text: String) {
+        let _ = self.sender.send(ConnEvent::Notice {
+            server: self.server,
+            channel: channel.clone(),
+            text,
+        });
+    }
+
+    /// Attachments are served from Discord's CDN, which doesn't want the token
+    fn download(&self, url: String, directory: PathBuf) {
+        let request = CLIENT.get(&url);
+        let server = self.server;
+        let sender = self.sender.clone();
+        ::std::thread::spawn(move || ::download::fetch(request, server, url, &directory, &sender));
+    }
+
+    /// Discord takes up to 10 files in one message, with the text of the message alongside them
+    /// as JSON. There are no titles for files, so the title goes at the top of the text.
+    fn upload(
+        &self,
+        channel: &ChannelId,
+        files: Vec<PathBuf>,
+        title: Option<String>,
+        comment: Option<String>,
+        thread: Option<DateTime>,
+    ) {
+        use reqwest::multipart::Form;
+        use upload::ProgressReader;
+
+        let channel_id = match self.snowflake(channel) {
+            Some(id) => id.clone(),
+            None => {
+                error!(
+                    "Tried to upload to channel {} in server {} but channel does not exist",
+                    channel, self.name
+                );
+                return;
+            }
+        };
+        let reply_to = match thread {
+            Some(ts) => match self.message_ids.lock().unwrap().get(&(channel.clone(), ts)) {
+                Some(id) => Some(id.clone()),
+                None => {
+                    error!("Couldn't find the message to reply to in {}", channel);
+                    return;
+                }
+            },
+            None => None,
+        };
+        let content = match (title, comment) {
+            (Some(title), Some(comment)) => format!("**{}**\n{}", title, comment),
+            (Some(title), None) => format!("**{}**", title),
+            (None, Some(comment)) => comment,
+            (None, None) => String::new(),
+        };
+
+        let url = format!("{}/channels/{}/messages", ::discord::BASE_URL, channel_id);
+        let token = self.token.clone();
+        let server = self.server;
+        let sender = self.sender.clone();
+        ::std::thread::spawn(move || {
+            for (i, chunk) in files.chunks(10).enumerate() {
+                let mut payload = json!({ "content": if i == 0 { content.as_str() } else { "" } });
+                if let Some(ref id) = reply_to {
+                    payload["message_reference"] = json!({ "message_id": id });
+                }
+                let mut form = Ok(Form::new().text("payload_json", payload.to_string()));
+                for (n, path) in chunk.iter().enumerate() {
+                    form = form.and_then(|form| {
+                        ProgressReader::open(path, server, sender.clone())
+                            .map(|reader| form.part(format!("files[{}]", n), reader.into_part()))
+                            .map_err(|e| format!("{}: {}", path.display(), e))
+                    });
+                }
+                let result = form.and_then(|form| {
+                    CLIENT
+                        .post(&url)
+                        .header("Authorization", token.as_str())
+                        .multipart(form)
+                        .send()
+                        .map_err(|e| e.to_string())
+                        .and_then(|mut r| response_text(&mut r).map(|_| ()))
+                });
+                ::upload::finish(chunk, result, server, &sender);
+            }
+        });
     }
 }
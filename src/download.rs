@@ -0,0 +1,214 @@
+//! Saving files linked from messages to disk.
+//!
+//! Files shared on Slack can only be fetched with the workspace's token, so the connection that a
+//! URL came from makes the request. Everything after that, picking a file name, writing it out
+//! and telling the TUI how far along it is, is the same for every connection and lives here.
+use conn::{ConnEvent, Direction, ServerId, TransferStatus};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::SyncSender;
+use std::time::{Duration, Instant};
+
+/// The `[downloads]` section of the config file
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct DownloadConfig {
+    pub directory: PathBuf,
+    /// Open each file once it has been saved, with the `[urls]` open_command
+    pub open: bool,
+}
+
+impl Default for DownloadConfig {
+    fn default() -> Self {
+        DownloadConfig {
+            directory: ::dirs::download_dir()
+                .or_else(::dirs::home_dir)
+                .unwrap_or_else(|| PathBuf::from(".")),
+            open: false,
+        }
+    }
+}
+
+/// The last part of a URL's path, which is the file name for every file link we know of
+pub fn file_name(url: &str) -> String {
+    let path = url.split(&['?', '#'][..]).next().unwrap_or(url);
+    let path = path.splitn(2, "://").last().unwrap_or(path);
+    path.split('/')
+        .skip(1)
+        .filter(|segment| !segment.is_empty())
+        .last()
+        .map(|segment| segment.replace("%20", " "))
+        .unwrap_or_else(|| String::from("download"))
+}
+
+/// Creates a new file for `name` in `directory`, adding a number to the name instead of
+/// overwriting a file that's already there
+fn create_unique(directory: &Path, name: &str) -> io::Result<(PathBuf, File)> {
+    let (stem, extension) = match name.rfind('.') {
+        Some(i) if i > 0 => (&name[..i], &name[i..]),
+        _ => (name, ""),
+    };
+    for n in 0.. {
+        let path = if n == 0 {
+            directory.join(name)
+        } else {
+            directory.join(format!("{} ({}){}", stem, n, extension))
+        };
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => return Ok((path, file)),
+            Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    unreachable!()
+}
+
+/// Like 1.4 MiB
+pub fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// Sends `request` and saves what comes back in `directory`, reporting progress to the TUI as
+/// `ConnEvent::Transfer`s. Blocks until the download is done.
+pub fn fetch(
+    request: ::reqwest::RequestBuilder,
+    server: ServerId,
+    url: String,
+    directory: &Path,
+    sender: &SyncSender<ConnEvent>,
+) {
+    let name = file_name(&url);
+    let status = match save(request, &name, directory, server, &url, sender) {
+        Ok(path) => TransferStatus::Finished(path),
+        Err(e) => TransferStatus::Failed(e),
+    };
+    let _ = sender.send(ConnEvent::Transfer {
+        server,
+        id: url,
+        direction: Direction::Download,
+        status,
+    });
+}
+
+fn save(
+    request: ::reqwest::RequestBuilder,
+    name: &str,
+    directory: &Path,
+    server: ServerId,
+    url: &str,
+    sender: &SyncSender<ConnEvent>,
+) -> Result<PathBuf, String> {
+    let mut response = request
+        .send()
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| e.to_string())?;
+    let total = response
+        .headers()
+        .get(::reqwest::header::CONTENT_LENGTH)
+        .and_then(|length| length.to_str().ok())
+        .and_then(|length| length.parse().ok());
+
+    fs::create_dir_all(directory).map_err(|e| format!("{}: {}", directory.display(), e))?;
+    let (path, mut file) =
+        create_unique(directory, name).map_err(|e| format!("{}: {}", directory.display(), e))?;
+
+    let progress = |received| {
+        let _ = sender.send(ConnEvent::Transfer {
+            server,
+            id: url.to_string(),
+            direction: Direction::Download,
+            status: TransferStatus::Progress {
+                file: name.to_string(),
+                done: received,
+                total,
+            },
+        });
+    };
+    progress(0);
+
+    let mut buffer = [0; 16 * 1024];
+    let mut received = 0;
+    let mut last_update = Instant::now();
+    let result = loop {
+        match response.read(&mut buffer) {
+            Ok(0) => break Ok(()),
+            Ok(n) => {
+                if let Err(e) = file.write_all(&buffer[..n]) {
+                    break Err(format!("{}: {}", path.display(), e));
+                }
+                received += n as u64;
+                // Every chunk would be far more redraws than anyone can read
+                if last_update.elapsed() > Duration::from_millis(100) {
+                    progress(received);
+                    last_update = Instant::now();
+                }
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => break Err(e.to_string()),
+        }
+    };
+
+    match result {
+        Ok(()) => Ok(path),
+        Err(e) => {
+            let _ = fs::remove_file(&path);
+            Err(e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_names() {
+        assert_eq!(
+            file_name("https://files.slack.com/files-pri/T0-F0/report.pdf"),
+            "report.pdf"
+        );
+        assert_eq!(
+            file_name("https://cdn.discordapp.com/attachments/1/2/cat%20picture.png?ex=1&is=2"),
+            "cat picture.png"
+        );
+        assert_eq!(file_name("https://example.com/dir/"), "dir");
+        assert_eq!(file_name("https://example.com"), "download");
+        assert_eq!(file_name("https://example.com/#top"), "download");
+    }
+
+    #[test]
+    fn numbers_duplicate_names() {
+        let directory =
+            ::std::env::temp_dir().join(format!("omnichat-test-{}", ::std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let (first, _) = create_unique(&directory, "notes.txt").unwrap();
+        let (second, _) = create_unique(&directory, "notes.txt").unwrap();
+        let (third, _) = create_unique(&directory, "Makefile").unwrap();
+        let (fourth, _) = create_unique(&directory, "Makefile").unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(first, directory.join("notes.txt"));
+        assert_eq!(second, directory.join("notes (1).txt"));
+        assert_eq!(third, directory.join("Makefile"));
+        assert_eq!(fourth, directory.join("Makefile (1)"));
+    }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(human_size(0), "0 B");
+        assert_eq!(human_size(1023), "1023 B");
+        assert_eq!(human_size(1536), "1.5 KiB");
+        assert_eq!(human_size(5 * 1024 * 1024), "5.0 MiB");
+    }
+}
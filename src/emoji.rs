@@ -0,0 +1,157 @@
+//! Standard emoji shortcodes, as used by Slack.
+//!
+//! Shortcodes look like `:thumbsup:`, and Slack appends a skin tone as a second shortcode,
+//! `:thumbsup::skin-tone-3:`. Reaction names use the same syntax without the outer colons.
+use emojis::SkinTone;
+use std::borrow::Cow;
+
+lazy_static! {
+    static ref SHORTCODES: Vec<&'static str> = {
+        let mut codes: Vec<&'static str> = ::emojis::iter().flat_map(|e| e.shortcodes()).collect();
+        codes.sort();
+        codes
+    };
+}
+
+/// Slack numbers the skin tones from 2 (lightest) to 6 (darkest)
+fn skin_tone(name: &str) -> Option<SkinTone> {
+    match name {
+        "skin-tone-2" => Some(SkinTone::Light),
+        "skin-tone-3" => Some(SkinTone::MediumLight),
+        "skin-tone-4" => Some(SkinTone::Medium),
+        "skin-tone-5" => Some(SkinTone::MediumDark),
+        "skin-tone-6" => Some(SkinTone::Dark),
+        _ => None,
+    }
+}
+
+fn is_shortcode(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_+-'".contains(c))
+}
+
+fn lookup(name: &str, tone: Option<&str>) -> Option<&'static str> {
+    let emoji = ::emojis::get_by_shortcode(name)?;
+    Some(
+        tone.and_then(skin_tone)
+            .and_then(|tone| emoji.with_skin_tone(tone))
+            .unwrap_or(emoji)
+            .as_str(),
+    )
+}
+
+/// The glyph for a standard emoji name such as `smile` or `wave::skin-tone-2`, or None if this is
+/// not a standard emoji (it may well be a workspace's custom emoji)
+pub fn to_unicode(name: &str) -> Option<&'static str> {
+    match name.find("::") {
+        Some(i) => lookup(&name[..i], Some(&name[i + 2..])),
+        None => lookup(name, None),
+    }
+}
+
+/// Replaces every standard `:shortcode:` in `text` with its glyph, leaving anything else alone
+pub fn replace_shortcodes<'a>(text: &'a str) -> Cow<'a, str> {
+    if !text.contains(':') {
+        return Cow::Borrowed(text);
+    }
+
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(':') {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let glyph = after.find(':').and_then(|end| {
+            let name = &after[..end];
+            if !is_shortcode(name) {
+                return None;
+            }
+            let tail = &after[end + 1..];
+            // A skin tone is written as a second shortcode immediately after the first
+            let tone = tail
+                .strip_prefix(':')
+                .and_then(|t| t.find(':').map(|e| &t[..e]));
+            match tone.and_then(|t| skin_tone(t).map(|_| t)) {
+                Some(t) => lookup(name, Some(t)).map(|g| (g, end + t.len() + 3)),
+                None => lookup(name, None).map(|g| (g, end + 1)),
+            }
+        });
+        match glyph {
+            Some((glyph, consumed)) => {
+                output.push_str(glyph);
+                rest = &after[consumed..];
+            }
+            None => {
+                output.push(':');
+                rest = after;
+            }
+        }
+    }
+    output.push_str(rest);
+    Cow::Owned(output)
+}
+
+/// All standard shortcodes starting with `prefix`, in alphabetical order. A prefix that names
+/// an emoji followed by `::` completes to that emoji's skin tones.
+pub fn complete(prefix: &str) -> Vec<String> {
+    if let Some(i) = prefix.find("::") {
+        let name = &prefix[..i];
+        let has_tones = ::emojis::get_by_shortcode(name)
+            .and_then(|e| e.skin_tones())
+            .is_some();
+        if !has_tones {
+            return Vec::new();
+        }
+        return (2..=6)
+            .map(|n| format!("{}::skin-tone-{}", name, n))
+            .filter(|s| s.starts_with(prefix))
+            .collect();
+    }
+
+    let start = match SHORTCODES.binary_search(&prefix) {
+        Ok(i) | Err(i) => i,
+    };
+    SHORTCODES[start..]
+        .iter()
+        .take_while(|code| code.starts_with(prefix))
+        .map(|code| String::from(*code))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replaces_known_shortcodes() {
+        assert_eq!(replace_shortcodes("hi :wave:"), "hi \u{1F44B}");
+        assert_eq!(
+            replace_shortcodes(":+1::skin-tone-6: thanks"),
+            "\u{1F44D}\u{1F3FF} thanks"
+        );
+        assert_eq!(
+            replace_shortcodes("meet at 12:30:45 :not_an_emoji: :partyparrot:"),
+            "meet at 12:30:45 :not_an_emoji: :partyparrot:"
+        );
+        assert_eq!(replace_shortcodes("q:zz:smile:"), "q:zz\u{1F604}");
+        assert_eq!(replace_shortcodes(":smile::skin-tone-9:"), "\u{1F604}:skin-tone-9:");
+    }
+
+    #[test]
+    fn reaction_names() {
+        assert_eq!(to_unicode("thumbsup"), Some("\u{1F44D}"));
+        assert_eq!(to_unicode("wave::skin-tone-2"), Some("\u{1F44B}\u{1F3FB}"));
+        assert_eq!(to_unicode("custom_parrot"), None);
+    }
+
+    #[test]
+    fn completion() {
+        let completions = complete("thumbs");
+        assert!(completions.contains(&String::from("thumbsup")));
+        assert!(completions.contains(&String::from("thumbsdown")));
+        assert_eq!(complete("wave::skin-tone-")[0], "wave::skin-tone-2");
+        assert_eq!(complete("wave::skin-tone-").len(), 5);
+        assert!(complete("rocket::").is_empty());
+    }
+}
@@ -0,0 +1,160 @@
+//! Searching the messages that have already been loaded, in every server, with /find.
+use conn::{ChannelId, DateTime, IString, ServerId};
+use regex::Regex;
+use std::borrow::Cow;
+use termion::event::Key;
+use theme::{Style, RESET};
+
+/// What keys do while a search is active
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    /// `/find ...` is being typed, and the results update as it changes
+    Typing,
+    /// The results are open and have the keyboard
+    Browsing,
+    /// A result has been picked, and Ctrl-n and Ctrl-p go to the matches either side of it
+    Stepping,
+}
+
+/// A loaded message that matches
+pub struct Hit {
+    pub server: ServerId,
+    pub server_name: IString,
+    pub channel: ChannelId,
+    pub channel_name: IString,
+    pub sender: IString,
+    pub timestamp: DateTime,
+    /// The first line of the message that matches
+    pub line: String,
+}
+
+impl Hit {
+    /// The hit as a single line: when and where it was said, who said it, and what matched
+    pub fn row(&self) -> String {
+        let time = self
+            .timestamp
+            .as_chrono()
+            .with_timezone(&::chrono::Local)
+            .format("%Y-%m-%d %H:%M");
+        format!(
+            "{} {} #{} {}: {}",
+            time,
+            self.server_name,
+            self.channel_name,
+            self.sender,
+            self.line.trim()
+        )
+    }
+}
+
+pub struct Find {
+    pub regex: Regex,
+    /// Newest first
+    pub hits: Vec<Hit>,
+    pub selected: usize,
+    pub mode: Mode,
+}
+
+impl Find {
+    /// The line above the results, which says how to use them
+    pub fn title(&self) -> String {
+        let count = match self.hits.len() {
+            1 => String::from("1 loaded message matches"),
+            n => format!("{} loaded messages match", n),
+        };
+        match self.mode {
+            Mode::Typing => format!("{} /{}/, Enter lists them", count, self.regex),
+            _ => format!(
+                "{} /{}/. Enter shows one, then Ctrl-n and Ctrl-p go to older and newer matches, \
+                 Esc closes",
+                count, self.regex
+            ),
+        }
+    }
+}
+
+/// Whether `key` steps to the next older match or the next newer one, if it steps at all. These
+/// are Ctrl keys so that typing a message never steps instead, whatever it starts with.
+pub fn step_direction(mode: Mode, key: Key) -> Option<bool> {
+    match (mode, key) {
+        (Mode::Stepping, Key::Ctrl('n')) => Some(true),
+        (Mode::Stepping, Key::Ctrl('p')) => Some(false),
+        _ => None,
+    }
+}
+
+/// The first line of `texts` with a match for `regex` in it
+pub fn matching_line<'a, I>(regex: &Regex, texts: I) -> Option<&'a str>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    texts
+        .into_iter()
+        .flat_map(str::lines)
+        .find(|line| regex.is_match(line))
+}
+
+/// Draws every match for `regex` in `text` in `style`, going back to `resume` after each one
+pub fn highlight<'a>(
+    text: &'a str,
+    regex: Option<&Regex>,
+    style: &Style,
+    resume: &Style,
+) -> Cow<'a, str> {
+    let regex = match regex {
+        Some(regex) if regex.is_match(text) => regex,
+        _ => return Cow::Borrowed(text),
+    };
+    let mut highlighted = String::with_capacity(text.len());
+    let mut end = 0;
+    for m in regex.find_iter(text).filter(|m| !m.as_str().is_empty()) {
+        highlighted.push_str(&text[end..m.start()]);
+        highlighted.push_str(&format!("{}{}{}{}", style, m.as_str(), RESET, resume));
+        end = m.end();
+    }
+    highlighted.push_str(&text[end..]);
+    Cow::Owned(highlighted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_the_matching_line() {
+        let regex = Regex::new("(?i)lunch").unwrap();
+        let texts = vec!["hi\nwhere's Lunch?", "lunch is at noon"];
+        assert_eq!(matching_line(&regex, texts), Some("where's Lunch?"));
+        assert_eq!(matching_line(&regex, vec!["breakfast"]), None);
+    }
+
+    #[test]
+    fn typing_never_steps() {
+        assert_eq!(step_direction(Mode::Stepping, Key::Ctrl('n')), Some(true));
+        assert_eq!(step_direction(Mode::Stepping, Key::Ctrl('p')), Some(false));
+        for c in "nice Nope".chars() {
+            assert_eq!(step_direction(Mode::Stepping, Key::Char(c)), None);
+        }
+        assert_eq!(step_direction(Mode::Browsing, Key::Ctrl('n')), None);
+        assert_eq!(step_direction(Mode::Typing, Key::Ctrl('p')), None);
+    }
+
+    #[test]
+    fn highlights_every_match() {
+        let style: Style = "reverse".parse().unwrap();
+        let resume: Style = "red".parse().unwrap();
+        let regex = Regex::new("o+").unwrap();
+        assert_eq!(
+            highlight("foo boo", Some(&regex), &style, &resume),
+            format!(
+                "f{}oo{}{} b{}oo{}{}",
+                style, RESET, resume, style, RESET, resume
+            )
+        );
+        assert_eq!(highlight("bar", Some(&regex), &style, &resume), "bar");
+        assert_eq!(highlight("foo", None, &style, &resume), "foo");
+        // An empty match has nothing to draw
+        let empty = Regex::new("x*").unwrap();
+        assert_eq!(highlight("foo", Some(&empty), &style, &resume), "foo");
+    }
+}
@@ -1,6 +1,7 @@
 extern crate chrono;
 extern crate dirs;
 extern crate discord;
+extern crate emojis;
 extern crate futures;
 extern crate inlinable_string;
 #[macro_use]
@@ -20,26 +21,40 @@ extern crate serde_urlencoded;
 extern crate signal_hook;
 extern crate slack;
 extern crate termion;
-extern crate textwrap;
 extern crate tokio_core;
 extern crate toml;
+extern crate unicode_segmentation;
+extern crate unicode_width;
 extern crate websocket;
 
 #[macro_use]
 mod conn;
 mod bimap;
 mod chan_message;
+mod commands;
 mod cursor_vec;
 #[cfg(feature = "discord_support")]
 mod discord_conn;
+mod download;
+mod emoji;
+mod find;
 mod logger;
+mod search;
+mod slack_actions;
 mod slack_conn;
+mod slack_markup;
 //mod strvec;
+mod theme;
 mod tui;
+mod upload;
+mod urls;
+mod width;
 
 #[derive(Deserialize)]
 struct SlackConfig {
     token: String,
+    #[serde(default)]
+    user_names: slack_conn::UserNames,
 }
 
 #[cfg(feature = "discord_support")]
@@ -55,6 +70,14 @@ struct Config {
     discord_token: Option<String>,
     #[cfg(feature = "discord_support")]
     discord: Option<Vec<DiscordConfig>>,
+    #[serde(default)]
+    display: tui::DisplayConfig,
+    #[serde(default)]
+    theme: theme::ThemeConfig,
+    #[serde(default)]
+    urls: urls::UrlConfig,
+    #[serde(default)]
+    downloads: download::DownloadConfig,
 }
 
 fn main() {
@@ -92,7 +115,12 @@ fn main() {
         std::process::exit(1)
     });
 
-    let tui = tui::Tui::new();
+    let theme = theme::Theme::from_config(config.theme).unwrap_or_else(|e| {
+        println!("Invalid theme in {:?}: {}", &config_path, e);
+        std::process::exit(1)
+    });
+
+    let tui = tui::Tui::new(config.display, theme, config.urls, config.downloads);
 
     // Init the global logger
     log::set_boxed_logger(Box::new(logger::Logger::new(tui.sender())))
@@ -104,7 +132,7 @@ fn main() {
         for c in slack {
             let sender = tui.sender();
             thread::spawn(move || {
-                let _ = SlackConn::create_on(&c.token, sender.clone());
+                let _ = SlackConn::create_on(&c.token, c.user_names, sender.clone());
             });
         }
     }
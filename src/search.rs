@@ -0,0 +1,119 @@
+//! The results of a server-side search, which are browsed a page at a time in an overlay like the
+//! URL picker.
+use conn::{SearchHit, ServerId};
+
+pub struct Results {
+    pub server: ServerId,
+    pub query: String,
+    /// Counted from 1
+    pub page: u32,
+    pub pages: u32,
+    pub total: u32,
+    pub hits: Vec<SearchHit>,
+    pub selected: usize,
+}
+
+impl Results {
+    /// The line above the results, which says how to use them
+    pub fn title(&self) -> String {
+        if self.hits.is_empty() {
+            return format!("No messages match \"{}\". Esc closes", self.query);
+        }
+        format!(
+            "{} {} for \"{}\", page {} of {}. Enter jumps to one, \u{2190}/\u{2192} change page, \
+             Esc closes",
+            self.total,
+            if self.total == 1 { "match" } else { "matches" },
+            self.query,
+            self.page,
+            self.pages
+        )
+    }
+
+    pub fn has_previous_page(&self) -> bool {
+        self.page > 1
+    }
+
+    pub fn has_next_page(&self) -> bool {
+        self.page < self.pages
+    }
+}
+
+/// One result as a single line: when and where it was said, who said it, and how it starts
+pub fn row(hit: &SearchHit) -> String {
+    let time = hit
+        .timestamp
+        .as_chrono()
+        .with_timezone(&::chrono::Local)
+        .format("%Y-%m-%d %H:%M");
+    let text = hit
+        .text
+        .lines()
+        .find(|l| !l.trim().is_empty())
+        .unwrap_or("");
+    format!(
+        "{} #{} {}: {}",
+        time,
+        hit.channel_name,
+        hit.sender,
+        text.trim()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use conn::{ChannelId, DateTime};
+
+    fn hit(text: &str) -> SearchHit {
+        SearchHit {
+            channel: ChannelId::from("C1"),
+            channel_name: "general".into(),
+            sender: "alice".into(),
+            timestamp: DateTime::now(),
+            text: text.to_string(),
+        }
+    }
+
+    fn results(total: u32, page: u32, pages: u32) -> Results {
+        Results {
+            server: ServerId::unique(),
+            query: String::from("lunch"),
+            page,
+            pages,
+            total,
+            hits: (0..total).map(|_| hit("lunch?")).collect(),
+            selected: 0,
+        }
+    }
+
+    #[test]
+    fn rows_show_the_first_line() {
+        let row = row(&hit("\nwhere's lunch?\nI'm starving"));
+        assert!(row.ends_with(" #general alice: where's lunch?"), "{}", row);
+    }
+
+    #[test]
+    fn titles_count_the_matches() {
+        assert_eq!(
+            results(0, 1, 0).title(),
+            "No messages match \"lunch\". Esc closes"
+        );
+        assert!(results(1, 1, 1)
+            .title()
+            .starts_with("1 match for \"lunch\", page 1 of 1."));
+        assert!(results(3, 2, 3)
+            .title()
+            .starts_with("3 matches for \"lunch\", page 2 of 3."));
+    }
+
+    #[test]
+    fn pages_are_counted_from_one() {
+        let first = results(3, 1, 2);
+        assert!(!first.has_previous_page());
+        assert!(first.has_next_page());
+        let last = results(3, 2, 2);
+        assert!(last.has_previous_page());
+        assert!(!last.has_next_page());
+    }
+}
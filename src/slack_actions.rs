@@ -0,0 +1,311 @@
+//! The buttons and menus that apps put in their messages with Block Kit.
+//!
+//! Every option of a menu is an action of its own, so anything can be done with one click. Taking
+//! an action posts it to blocks.actions along with the message it's in, like Slack's own clients
+//! do, so the app that posted the message gets the same interaction payload either way.
+use conn;
+use serde_json::Value;
+use slack::blocks::{Action, Attachment, Block};
+use slack::{BotId, ConversationId, Timestamp};
+
+/// What's kept in `conn::Action::payload`. The message the action is in is handed back with it,
+/// so it isn't stored here.
+#[derive(Serialize, Deserialize)]
+struct Payload {
+    /// The bot that posted the message
+    service_id: BotId,
+    /// One element for the `actions` list of blocks.actions
+    action: Value,
+}
+
+fn push(
+    service_id: BotId,
+    label: String,
+    url: Option<String>,
+    action: Value,
+    out: &mut Vec<conn::Action>,
+) {
+    let payload = Payload { service_id, action };
+    out.push(conn::Action {
+        label,
+        url,
+        payload: ::serde_json::to_string(&payload).unwrap_or_default(),
+    });
+}
+
+fn element(service_id: BotId, block_id: &str, element: &Action, out: &mut Vec<conn::Action>) {
+    match element {
+        Action::Button {
+            action_id,
+            text,
+            value,
+            url,
+            style,
+        } => push(
+            service_id,
+            text.text.clone(),
+            url.clone(),
+            json!({
+                "type": "button",
+                "block_id": block_id,
+                "action_id": action_id,
+                "text": text,
+                "value": value,
+                "style": style,
+            }),
+            out,
+        ),
+        Action::Select {
+            action_id,
+            placeholder,
+            options,
+        } => {
+            for option in options {
+                let label = match placeholder {
+                    Some(placeholder) => format!("{}: {}", placeholder.text, option.text.text),
+                    None => option.text.text.clone(),
+                };
+                push(
+                    service_id,
+                    label,
+                    None,
+                    json!({
+                        "type": "static_select",
+                        "block_id": block_id,
+                        "action_id": action_id,
+                        "selected_option": option,
+                        "placeholder": placeholder,
+                    }),
+                    out,
+                );
+            }
+        }
+        Action::Unknown => {}
+    }
+}
+
+/// Every button and menu option in a message and its attachments. Only bots post them, and the
+/// bot's id is needed to take them.
+pub fn actions(
+    service_id: BotId,
+    blocks: &[Block],
+    attachments: &[Attachment],
+) -> Vec<conn::Action> {
+    let mut out = Vec::new();
+    let all_blocks = blocks
+        .iter()
+        .chain(attachments.iter().flat_map(|a| a.blocks.iter()));
+    for block in all_blocks {
+        match block {
+            Block::Section {
+                block_id,
+                accessory: Some(accessory),
+                ..
+            } => element(service_id, block_id, accessory, &mut out),
+            Block::Actions { block_id, elements } => {
+                for e in elements {
+                    element(service_id, block_id, e, &mut out);
+                }
+            }
+            _ => {}
+        }
+    }
+    out
+}
+
+/// Takes the action with `payload` in the message sent at `ts` in `channel`, through the Slack API
+/// at `api`. Errors are Slack's error code where there is one.
+pub fn take(
+    api: &str,
+    token: &str,
+    channel: ConversationId,
+    ts: Timestamp,
+    payload: &str,
+) -> Result<(), String> {
+    use slack::http::blocks::ActionsRequest;
+
+    let Payload {
+        service_id,
+        mut action,
+    } = ::serde_json::from_str(payload).map_err(|e| e.to_string())?;
+    let now = Timestamp::from(conn::DateTime::now()).to_string();
+    action["action_ts"] = Value::from(now.clone());
+    let container = json!({
+        "type": "message",
+        "message_ts": ts.to_string(),
+        "channel_id": channel,
+        "is_ephemeral": false,
+    });
+    let request = ActionsRequest::new(
+        service_id,
+        container.to_string(),
+        Value::from(vec![action]).to_string(),
+        now,
+    );
+
+    let body = ::slack_conn::CLIENT
+        .post(&format!("{}/blocks.actions?token={}", api, token))
+        .form(&request)
+        .send()
+        .and_then(|mut response| response.text())
+        .map_err(|e| format!("{:#?}", e))?;
+    match ::serde_json::from_str::<::slack::http::Error>(&body) {
+        Ok(::slack::http::Error { ok: true, .. }) => Ok(()),
+        Ok(::slack::http::Error { ok: false, error }) => {
+            Err(error.unwrap_or_else(|| "no error given".into()))
+        }
+        Err(e) => Err(format!("{}\n{:#?}", body, e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+
+    fn blocks() -> Vec<Block> {
+        ::serde_json::from_value(json!([
+            {"type": "section", "block_id": "ask",
+             "text": {"type": "mrkdwn", "text": "Deploy v1.2 to prod?"},
+             "accessory": {"type": "static_select", "action_id": "env",
+                "placeholder": {"type": "plain_text", "text": "Env"},
+                "options": [
+                    {"text": {"type": "plain_text", "text": "prod"}, "value": "p"},
+                    {"text": {"type": "plain_text", "text": "staging"}, "value": "s"},
+                ]}},
+            {"type": "actions", "block_id": "buttons", "elements": [
+                {"type": "button", "action_id": "approve", "style": "primary",
+                 "text": {"type": "plain_text", "text": "Approve"}, "value": "yes"},
+                {"type": "button", "action_id": "logs", "url": "https://ci.example.com/42",
+                 "text": {"type": "plain_text", "text": "Logs"}},
+                {"type": "datepicker", "action_id": "when"},
+            ]},
+        ]))
+        .unwrap()
+    }
+
+    fn payload(action: &conn::Action) -> Value {
+        ::serde_json::from_str(&action.payload).unwrap()
+    }
+
+    /// Answers one request with `response`, and sends back the request line and body
+    fn stand_in(response: &'static str) -> (String, mpsc::Receiver<(String, String)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let api = format!("http://{}/api", listener.local_addr().unwrap());
+        let (sender, receiver) = mpsc::channel();
+        ::std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut length = 0;
+            loop {
+                let mut header = String::new();
+                reader.read_line(&mut header).unwrap();
+                if header.trim().is_empty() {
+                    break;
+                }
+                let lower = header.to_lowercase();
+                if let Some(value) = lower.strip_prefix("content-length:") {
+                    length = value.trim().parse().unwrap();
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            let _ = write!(
+                reader.get_mut(),
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\
+                 Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                response.len(),
+                response
+            );
+            let _ = sender.send((request_line, String::from_utf8(body).unwrap()));
+        });
+        (api, receiver)
+    }
+
+    #[test]
+    fn finds_buttons_and_menu_options() {
+        let actions = actions(BotId::from("B01"), &blocks(), &[]);
+        let labels: Vec<&str> = actions.iter().map(|a| a.label.as_str()).collect();
+        assert_eq!(labels, vec!["Env: prod", "Env: staging", "Approve", "Logs"]);
+        assert_eq!(actions[3].url, Some("https://ci.example.com/42".into()));
+
+        let staging = payload(&actions[1]);
+        assert_eq!(staging["service_id"], "B01");
+        assert_eq!(staging["action"]["block_id"], "ask");
+        assert_eq!(staging["action"]["action_id"], "env");
+        assert_eq!(staging["action"]["selected_option"]["value"], "s");
+
+        let approve = payload(&actions[2]);
+        assert_eq!(approve["action"]["type"], "button");
+        assert_eq!(approve["action"]["block_id"], "buttons");
+        assert_eq!(approve["action"]["value"], "yes");
+    }
+
+    #[test]
+    fn finds_actions_in_attachments() {
+        let attachment: Attachment = ::serde_json::from_value(json!({
+            "color": "good",
+            "blocks": [{"type": "actions", "block_id": "b", "elements": [
+                {"type": "button", "action_id": "ack", "text": {"type": "plain_text", "text": "Ack"}},
+            ]}],
+        }))
+        .unwrap();
+        let actions = actions(BotId::from("B01"), &[], &[attachment]);
+        assert_eq!(actions.len(), 1);
+        assert_eq!(actions[0].label, "Ack");
+    }
+
+    #[test]
+    fn posts_the_interaction_payload() {
+        let (api, requests) = stand_in(r#"{"ok": true}"#);
+        let approve = actions(BotId::from("B01"), &blocks(), &[]).remove(2);
+        let channel = ConversationId::Channel("C01".into());
+        let ts = Timestamp {
+            microseconds: 1_392_734_382_000_100,
+        };
+        assert_eq!(take(&api, "xoxp-1", channel, ts, &approve.payload), Ok(()));
+
+        let (request_line, body) = requests.recv().unwrap();
+        assert!(request_line.starts_with("POST /api/blocks.actions?token=xoxp-1 "));
+        let form: HashMap<String, String> = ::serde_urlencoded::from_str(&body).unwrap();
+        assert_eq!(form["service_id"], "B01");
+        assert!(!form["client_token"].is_empty());
+
+        let container: Value = ::serde_json::from_str(&form["container"]).unwrap();
+        assert_eq!(
+            container,
+            json!({
+                "type": "message",
+                "message_ts": "1392734382.000100",
+                "channel_id": "C01",
+                "is_ephemeral": false,
+            })
+        );
+        let sent: Value = ::serde_json::from_str(&form["actions"]).unwrap();
+        assert_eq!(sent[0]["action_id"], "approve");
+        assert_eq!(sent[0]["value"], "yes");
+        assert!(sent[0]["action_ts"].is_string());
+    }
+
+    #[test]
+    fn reports_slack_errors() {
+        let (api, _requests) = stand_in(r#"{"ok": false, "error": "invalid_action"}"#);
+        let approve = actions(BotId::from("B01"), &blocks(), &[]).remove(2);
+        let channel = ConversationId::Channel("C01".into());
+        assert_eq!(
+            take(
+                &api,
+                "xoxp-1",
+                channel,
+                Timestamp::default(),
+                &approve.payload
+            ),
+            Err("invalid_action".into())
+        );
+    }
+}
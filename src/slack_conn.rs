@@ -1,19 +1,110 @@
 use bimap::BiMap;
 use conn;
-use conn::{Completer, ConnEvent, IString, Message, TuiEvent};
+use conn::{
+    ChannelId, ChannelInfo, Command, Completer, ConnEvent, Handler, IString, Message, Section,
+    ServerId, TuiEvent,
+};
 use futures::sync::mpsc;
 use futures::{Future, Sink, Stream};
 use regex::Regex;
+use slack::blocks::{Attachment, Block, File};
+use slack_markup::Names;
+use std::collections::HashMap;
 use std::sync::mpsc::SyncSender;
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Mutex, RwLock};
 use std::thread;
 
 lazy_static! {
-    pub static ref MENTION_REGEX: Regex = Regex::new(r"<@[A-Z0-9]{9}>").unwrap();
-    pub static ref CHANNEL_REGEX: Regex = Regex::new(r"<#[A-Z0-9]{9}\|(?P<n>.*?)>").unwrap();
+    pub static ref MENTION_REGEX: Regex = Regex::new(r"<@([UW][A-Z0-9]+)(\|[^>]*)?>").unwrap();
     pub static ref CLIENT: ::reqwest::Client = ::reqwest::Client::new();
 }
 
+/// Which of their names people are shown by, from the `user_names` key of a `[[slack]]` section
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UserNames {
+    /// The name people pick for themselves in their profile
+    #[default]
+    DisplayName,
+    RealName,
+    /// The unique name used for mentions, like jsmith
+    Handle,
+}
+
+/// How many group DMs to look up the members of at once when connecting
+const MEMBER_LOOKUPS: usize = 4;
+
+const SLACK_COMMANDS: &[Command] = &[
+    Command {
+        name: "join",
+        args: "#CHANNEL",
+        help: "Joins a public channel",
+        handler: Handler::Backend,
+    },
+    Command {
+        name: "leave",
+        args: "",
+        help: "Leaves this channel",
+        handler: Handler::Backend,
+    },
+    Command {
+        name: "create",
+        args: "[--private] NAME",
+        help: "Creates a channel and joins it",
+        handler: Handler::Backend,
+    },
+    Command {
+        name: "topic",
+        args: "[TEXT]",
+        help: "Sets this channel's topic, or shows it",
+        handler: Handler::Backend,
+    },
+    Command {
+        name: "purpose",
+        args: "[TEXT]",
+        help: "Sets this channel's purpose, or shows it",
+        handler: Handler::Backend,
+    },
+    Command {
+        name: "invite",
+        args: "@USER...",
+        help: "Invites people to this channel",
+        handler: Handler::Backend,
+    },
+    Command {
+        name: "archive",
+        args: "",
+        help: "Archives this channel, which also takes it out of the channel list",
+        handler: Handler::Backend,
+    },
+    Command {
+        name: "msg",
+        args: "@USER... [TEXT]",
+        help: "Opens a direct message with someone, or a group DM with several people, and \
+               sends TEXT there",
+        handler: Handler::Backend,
+    },
+    Command {
+        name: "query",
+        args: "@USER... [TEXT]",
+        help: "The same as /msg",
+        handler: Handler::Backend,
+    },
+    Command {
+        name: "browse",
+        args: "[FILTER]",
+        help: "Lists the public channels, or the ones with FILTER in their name",
+        handler: Handler::Backend,
+    },
+    Command {
+        name: "search",
+        args: "QUERY",
+        help: "Searches every channel for messages matching QUERY, including ones too old to be \
+               loaded. Slack's modifiers like in:#channel and from:@someone work.",
+        handler: Handler::Backend,
+    },
+];
+
 macro_rules! deserialize_or_log {
     ($response:expr, $type:ty) => {{
         if $response.status.is_success() {
@@ -52,35 +143,202 @@ where
     R: ::serde::de::DeserializeOwned + Send + 'static,
 {
     let token = token.to_string();
-    thread::spawn(move || {
-        let url = format!(
-            "https://slack.com/api/{}?token={}&{}",
-            endpoint,
-            token,
-            ::serde_urlencoded::to_string(request).unwrap_or_default()
-        );
+    thread::spawn(move || call_slack(endpoint, &token, request).map_err(|e| error!("{}", e)))
+}
 
-        CLIENT
-            .get(&url)
-            .send()
-            .map_err(|e| error!("{:#?}", e))
-            .and_then(|mut response| response.text().map_err(|e| error!("{:#?}", e)))
-            .and_then(|body| {
-                match ::serde_json::from_str::<::slack::http::Error>(&body)
-                    .map_err(|e| error!("{}\n{:#?}", format_json(&body), e))
-                {
-                    Ok(slack::http::Error { ok: true, .. }) => ::serde_json::from_str::<R>(&body)
-                        .map_err(|e| error!("{}\n{:#?}", format_json(&body), e)),
-                    Ok(slack::http::Error { ok: false, error }) => Err(error!(
-                        "{}",
-                        error.unwrap_or_else(|| "no error given".into())
-                    )),
-                    Err(e) => Err(e),
-                }
-            })
+/// Calls a Slack method and waits for the response. Errors are Slack's own error code where
+/// there is one, so they can be shown to the user.
+fn call_slack<T, R>(endpoint: &str, token: &str, request: T) -> Result<R, String>
+where
+    T: ::serde::Serialize,
+    R: ::serde::de::DeserializeOwned,
+{
+    let url = format!(
+        "https://slack.com/api/{}?token={}&{}",
+        endpoint,
+        token,
+        ::serde_urlencoded::to_string(request).unwrap_or_default()
+    );
+
+    let body = CLIENT
+        .get(&url)
+        .send()
+        .and_then(|mut response| response.text())
+        .map_err(|e| format!("{:#?}", e))?;
+    match ::serde_json::from_str::<::slack::http::Error>(&body) {
+        Ok(slack::http::Error { ok: true, .. }) => ::serde_json::from_str::<R>(&body)
+            .map_err(|e| format!("{}\n{:#?}", format_json(&body), e)),
+        Ok(slack::http::Error { ok: false, error }) => {
+            Err(error.unwrap_or_else(|| "no error given".into()))
+        }
+        Err(e) => Err(format!("{}\n{:#?}", format_json(&body), e)),
+    }
+}
+
+/// Follows a method's cursor through all of its pages. `page` makes the request for one page,
+/// and returns what was on it along with where the next one starts.
+fn all_pages<T, F>(mut page: F) -> Result<Vec<T>, String>
+where
+    F: FnMut(
+        Option<::slack::http::Cursor>,
+    ) -> Result<(Vec<T>, Option<::slack::http::ResponseMetadata>), String>,
+{
+    let mut items = Vec::new();
+    let mut cursor = None;
+    loop {
+        let (page_items, metadata) = page(cursor.take())?;
+        items.extend(page_items);
+        match metadata {
+            Some(ref metadata) if !metadata.next_cursor.is_empty() => {
+                cursor = Some(::slack::http::Cursor::from(metadata.next_cursor.clone()))
+            }
+            _ => return Ok(items),
+        }
+    }
+}
+
+/// Everyone in a conversation
+fn conversation_members(
+    token: &str,
+    id: ::slack::ConversationId,
+) -> Result<Vec<::slack::UserId>, String> {
+    use slack::http::conversations::{MembersRequest, MembersResponse};
+    all_pages(|cursor| {
+        let mut request = MembersRequest::new(id);
+        request.limit = Some(1000);
+        request.cursor = cursor;
+        let response: MembersResponse = call_slack("conversations.members", token, request)?;
+        Ok((response.members, response.response_metadata))
+    })
+}
+
+/// What a group DM is called in the channel list: everyone in it but us, like "alice, bob"
+fn mpim_name(
+    users: &BiMap<::slack::UserId, IString>,
+    me: ::slack::UserId,
+    members: &[::slack::UserId],
+) -> IString {
+    let mut names: Vec<String> = members
+        .iter()
+        .filter(|id| **id != me)
+        .map(|id| match users.get_right(id) {
+            Some(name) => name.to_string(),
+            None => id.to_string(),
+        })
+        .collect();
+    names.sort();
+    IString::from(names.join(", "))
+}
+
+/// Every public channel that isn't archived
+fn public_channels(
+    token: &str,
+) -> Result<Vec<::slack::http::conversations::ConversationSummary>, String> {
+    use slack::http::conversations::{ListRequest, SummaryListResponse};
+    all_pages(|cursor| {
+        let mut request = ListRequest::new();
+        request.exclude_archived = Some(true);
+        request.limit = Some(1000);
+        request.cursor = cursor;
+        let response: SummaryListResponse = call_slack("conversations.list", token, request)?;
+        Ok((response.channels, response.response_metadata))
+    })
+}
+
+/// Every user in the workspace
+fn all_users(token: &str) -> Result<Vec<::slack::http::users::User>, String> {
+    use slack::http::users::{ListRequest, ListResponse};
+    all_pages(|cursor| {
+        let mut request = ListRequest::new();
+        request.limit = Some(1000);
+        request.cursor = cursor;
+        let response: ListResponse = call_slack("users.list", token, request)?;
+        Ok((response.members, response.response_metadata))
     })
 }
 
+/// Every conversation we can see, of every type
+fn all_conversations(
+    token: &str,
+) -> Result<Vec<::slack::http::conversations::Conversation>, String> {
+    use slack::http::conversations::ChannelType::*;
+    use slack::http::conversations::{ListRequest, ListResponse};
+    all_pages(|cursor| {
+        let mut request = ListRequest::new();
+        request.types = vec![PublicChannel, PrivateChannel, Mpim, Im];
+        request.limit = Some(1000);
+        request.cursor = cursor;
+        let response: ListResponse = call_slack("conversations.list", token, request)?;
+        Ok((response.channels, response.response_metadata))
+    })
+}
+
+/// The name of the user, in the style from the config file. Display names and real names are
+/// optional, and empty when they aren't set, so this falls back to the handle everyone has.
+fn user_name(user: &::slack::http::users::User, style: UserNames) -> IString {
+    let profile = user.profile.as_ref();
+    let display_name = profile
+        .map(|p| p.display_name.as_str())
+        .filter(|n| !n.is_empty());
+    let real_name = profile
+        .map(|p| p.real_name.as_str())
+        .filter(|n| !n.is_empty())
+        .or(user.real_name.as_deref())
+        .filter(|n| !n.is_empty());
+    let chosen = match style {
+        UserNames::DisplayName => display_name.or(real_name),
+        UserNames::RealName => real_name,
+        UserNames::Handle => None,
+    };
+    IString::from(chosen.unwrap_or(&user.name))
+}
+
+/// Who a message is shown as from: the person who sent it, or else the name it was posted under,
+/// or the name of the bot that posted it
+fn sender_name(
+    users: &BiMap<::slack::UserId, IString>,
+    bots: &HashMap<::slack::BotId, IString>,
+    user: Option<::slack::UserId>,
+    username: Option<&str>,
+    bot_id: Option<::slack::BotId>,
+) -> Option<IString> {
+    user.and_then(|id| users.get_right(&id).cloned())
+        .or_else(|| username.map(IString::from))
+        .or_else(|| {
+            bot_id.map(|id| {
+                bots.get(&id)
+                    .cloned()
+                    .unwrap_or_else(|| IString::from(id.as_str()))
+            })
+        })
+}
+
+/// How the TUI refers to a conversation
+fn channel_id(id: ::slack::ConversationId) -> ChannelId {
+    ChannelId::from(id.as_str())
+}
+
+/// The conversation the TUI means, if `channel` is one of ours
+fn conversation_id(channel: &ChannelId) -> Option<::slack::ConversationId> {
+    ::slack::ConversationId::parse(channel.as_str())
+}
+
+/// What to add to the name of a conversation when another one already has it
+fn qualifier(id: ::slack::ConversationId) -> &'static str {
+    match id {
+        ::slack::ConversationId::Channel(_) => "channel",
+        ::slack::ConversationId::Group(_) => "private",
+        ::slack::ConversationId::DirectMessage(_) => "DM",
+    }
+}
+
+/// The users mentioned like <@U1234ABCD> in a message
+fn mentioned_users(text: &str) -> impl Iterator<Item = ::slack::UserId> + '_ {
+    MENTION_REGEX
+        .captures_iter(text)
+        .filter_map(|caps| ::slack::UserId::parse(&caps[1]))
+}
+
 #[derive(Deserialize)]
 #[serde(deny_unknown_fields)]
 struct MessageAck {
@@ -91,42 +349,82 @@ struct MessageAck {
     ts: ::slack::Timestamp,
 }
 
+/// What's left to do after an RTM event, once the connection is no longer locked
+enum FollowUp {
+    LoadHistory(::slack::ConversationId),
+    LookUp(::slack::ConversationId),
+    /// Find out who these people are, then process the event again
+    LookUpUsers(Vec<::slack::UserId>, String),
+    /// The same for bots
+    LookUpBots(Vec<::slack::BotId>, String),
+}
+
 struct PendingMessage {
     id: u32,
-    channel: IString,
+    channel: ChannelId,
 }
 
 impl SlackConn {
-    pub fn convert_mentions(&self, original: &str) -> String {
-        let text = MENTION_REGEX
-            .replace_all(original, |caps: &::regex::Captures| {
-                if let Some(name) = self.users.get_right(&caps[0][2..11].into()) {
-                    format!("@{}", name)
-                } else {
-                    format!("@{}", &caps[0][2..11])
-                }
-            }).into_owned();
+    fn names(&self) -> Names<'_> {
+        Names {
+            users: &self.users,
+            channels: &self.channels,
+            usergroups: &self.usergroups,
+        }
+    }
 
-        CHANNEL_REGEX.replace_all(&text, "#$n").into_owned()
+    /// The text of a Slack message as it's shown, with names in place of ids
+    pub fn decode_text(&self, text: &str) -> String {
+        ::slack_markup::decode(text, &self.names())
     }
 
-    pub fn to_slack(&self, mut text: String) -> String {
-        for (id, name) in self.users.iter() {
-            let name_mention = format!("@{}", name);
-            let slack_mention = format!("<@{}>", id);
-            text = text.replace(&name_mention, &slack_mention);
-        }
+    pub fn to_slack(&self, text: &str) -> String {
+        ::slack_markup::encode(text, &self.names())
+    }
 
-        for (id, name) in self.channels.iter() {
-            let name_mention = format!("#{}", name);
-            let slack_mention = format!("<#{}|{}>", id, name);
-            text = text.replace(&name_mention, &slack_mention);
+    fn sender(
+        &self,
+        user: Option<::slack::UserId>,
+        username: Option<&str>,
+        bot_id: Option<::slack::BotId>,
+    ) -> Option<IString> {
+        sender_name(&self.users, &self.bots, user, username, bot_id)
+    }
+
+    /// The text of a message and its attachments as they're shown. A message with blocks is laid
+    /// out from them, because its text is only what goes in notifications.
+    fn message_body(
+        &self,
+        text: &Option<String>,
+        blocks: &[Block],
+        attachments: &[Attachment],
+        files: &[File],
+    ) -> (String, Vec<conn::Attachment>) {
+        let names = self.names();
+        let mut lines = vec![::slack_markup::blocks(blocks, &names)];
+        if lines[0].is_empty() {
+            lines[0] = text
+                .as_ref()
+                .map_or_else(String::new, |t| ::slack_markup::decode(t, &names));
         }
+        lines.extend(files.iter().map(|f| f.url_private.clone()));
+        // Slack shows pretext above the attachment, so it goes with the message's own text
+        lines.extend(
+            attachments
+                .iter()
+                .filter_map(|a| a.pretext.as_ref())
+                .map(|p| ::slack_markup::decode(p, &names)),
+        );
 
-        text
+        let attachments = attachments
+            .iter()
+            .map(|a| ::slack_markup::attachment(a, &names))
+            .filter(|a| !a.contents.is_empty())
+            .collect();
+        (lines.join("\n").trim().to_string(), attachments)
     }
 
-    pub fn process_slack_message(&mut self, message: &str) {
+    fn process_slack_message(&mut self, message: &str) -> Option<FollowUp> {
         if let Ok(ack) = ::serde_json::from_str::<MessageAck>(&message) {
             // Remove the message from pending messages
             if let Some(index) = self
@@ -136,14 +434,18 @@ impl SlackConn {
             {
                 let _ = self.tui_sender.send(ConnEvent::Message(Message {
                     channel: self.pending_messages[index].channel.clone(),
-                    contents: self.convert_mentions(&ack.text),
+                    contents: self.decode_text(&ack.text),
+                    attachments: Vec::new(),
+                    actions: Vec::new(),
+                    files: Vec::new(),
+                    is_bot: false,
                     reactions: Vec::new(),
                     sender: self.my_name.clone(),
-                    server: self.team_name.clone(),
+                    server: self.server,
                     timestamp: ack.ts.into(),
                 }));
                 self.pending_messages.swap_remove(index);
-                return;
+                return None;
             }
         }
 
@@ -151,13 +453,13 @@ impl SlackConn {
         match ::serde_json::from_str::<::slack::rtm::Event>(&message) {
             Ok(rtm::Event::ReactionAdded { item, reaction, .. }) => {
                 use slack::rtm::Reactable;
-                let (channel_id, timestamp) = match item {
+                let (conversation, timestamp) = match item {
                     Reactable::Message { channel, ts } => (channel, ts),
                 };
-                if let Some(channel) = self.channels.get_right(&channel_id) {
+                if self.channels.get_right(&conversation).is_some() {
                     let _ = self.tui_sender.send(ConnEvent::ReactionAdded {
-                        server: self.team_name.clone(),
-                        channel: channel.clone(),
+                        server: self.server,
+                        channel: channel_id(conversation),
                         timestamp: timestamp.into(),
                         reaction: reaction.into(),
                     });
@@ -165,13 +467,13 @@ impl SlackConn {
             }
             Ok(rtm::Event::ReactionRemoved { item, reaction, .. }) => {
                 use slack::rtm::Reactable;
-                let (channel_id, timestamp) = match item {
+                let (conversation, timestamp) = match item {
                     Reactable::Message { channel, ts } => (channel, ts),
                 };
-                if let Some(channel) = self.channels.get_right(&channel_id) {
+                if self.channels.get_right(&conversation).is_some() {
                     let _ = self.tui_sender.send(ConnEvent::ReactionRemoved {
-                        server: self.team_name.clone(),
-                        channel: channel.clone(),
+                        server: self.server,
+                        channel: channel_id(conversation),
                         timestamp: timestamp.into(),
                         reaction: reaction.into(),
                     });
@@ -183,98 +485,143 @@ impl SlackConn {
                 channel,
                 text,
                 ts,
+                blocks,
                 attachments,
                 files,
                 bot_id,
             }) => {
-                if let Some(sender) = user
-                    .and_then(|id| self.users.get_right(&id))
-                    .cloned()
-                    .or_else(|| username.map(IString::from))
-                    .or_else(|| bot_id.map(|id| IString::from(id.as_str())))
-                {
-                    use std::fmt::Write;
-                    let mut body = match text {
-                        Some(ref t) => self.convert_mentions(t),
-                        None => String::new(),
-                    };
-
-                    for f in &files {
-                        write!(body, "\n{}", f.url_private);
-                    }
-
-                    for a in &attachments {
-                        if let Some(ref title) = a.title {
-                            write!(body, "\n{}", title);
-                        }
-                        if let Some(ref pretext) = a.pretext {
-                            write!(body, "\n{}", pretext);
-                        }
-                        if let Some(ref text) = a.text {
-                            write!(body, "\n{}", text);
-                        }
-                        for f in &a.files {
-                            write!(body, "\n{}", f.url_private);
-                        }
+                // Conversations we haven't seen yet are listed first, and their history includes
+                // this message
+                if self.channels.get_right(&channel).is_none() {
+                    return Some(FollowUp::LookUp(channel));
+                }
+                // Same for people, so the message can be shown with their names
+                let unknown: Vec<::slack::UserId> = user
+                    .into_iter()
+                    .chain(text.iter().flat_map(|t| mentioned_users(t)))
+                    .filter(|id| self.users.get_right(id).is_none())
+                    .collect();
+                if !unknown.is_empty() {
+                    return Some(FollowUp::LookUpUsers(unknown, message.to_string()));
+                }
+                // And for bots, when the message doesn't say who it's from
+                if let (None, None, Some(bot)) = (user, &username, bot_id) {
+                    if !self.bots.contains_key(&bot) {
+                        return Some(FollowUp::LookUpBots(vec![bot], message.to_string()));
                     }
-
-                    body = body.replace("&amp;", "&");
-                    body = body.replace("&lt;", "<");
-                    body = body.replace("&gt;", ">");
-
-                    let contents = body.trim().to_string();
+                }
+                if let Some(sender) = self.sender(user, username.as_deref(), bot_id) {
+                    let actions = bot_id.map_or_else(Vec::new, |bot| {
+                        ::slack_actions::actions(bot, &blocks, &attachments)
+                    });
+                    let (contents, attachments) =
+                        self.message_body(&text, &blocks, &attachments, &files);
 
                     let _ = self.tui_sender.send(ConnEvent::Message(Message {
-                        server: self.team_name.clone(),
-                        channel: self
-                            .channels
-                            .get_right(&channel)
-                            .cloned()
-                            .unwrap_or_else(|| channel.to_string().into()),
+                        server: self.server,
+                        channel: channel_id(channel),
                         sender,
                         timestamp: ts.into(),
                         reactions: Vec::new(),
                         contents,
+                        attachments,
+                        actions,
+                        files: files.into_iter().map(|f| f.url_private).collect(),
+                        is_bot: bot_id.is_some(),
                     }));
                 }
             }
+            Ok(rtm::Event::BotAdded { bot }) | Ok(rtm::Event::BotChanged { bot }) => {
+                self.bots.insert(bot.id, IString::from(bot.name));
+            }
             Ok(rtm::Event::ChannelMarked { channel, ts, .. }) => {
                 let _ = self.tui_sender.send(ConnEvent::MarkChannelRead {
-                    server: self.team_name.clone(),
-                    channel: self
-                        .channels
-                        .get_right(&channel.into())
-                        .unwrap_or(&channel.as_str().into())
-                        .clone(),
+                    server: self.server,
+                    channel: channel_id(channel.into()),
                     read_at: ts.into(),
                 });
             }
 
             Ok(::slack::rtm::Event::GroupMarked { channel, ts, .. }) => {
                 let _ = self.tui_sender.send(ConnEvent::MarkChannelRead {
-                    server: self.team_name.clone(),
-                    channel: self
-                        .channels
-                        .get_right(&channel.into())
-                        .unwrap_or(&IString::from(channel.as_str()))
-                        .clone(),
+                    server: self.server,
+                    channel: channel_id(channel.into()),
                     read_at: ts.into(),
                 });
             }
 
+            Ok(rtm::Event::ChannelJoined { channel })
+            | Ok(rtm::Event::GroupJoined { channel })
+            | Ok(rtm::Event::MpimJoined { channel }) => {
+                match self.describe_conversation(&channel) {
+                    Some((name, section)) => {
+                        if self.list_channel(channel.id, name, section) {
+                            return Some(FollowUp::LoadHistory(channel.id));
+                        }
+                    }
+                    None => return Some(FollowUp::LookUp(channel.id)),
+                }
+            }
+
+            Ok(rtm::Event::ImCreated { channel, user }) => {
+                return self.open_im(channel.id, user);
+            }
+
+            Ok(rtm::Event::ImOpen { channel, user }) => return self.open_im(channel, user),
+
+            Ok(rtm::Event::GroupOpen { channel }) | Ok(rtm::Event::MpimOpen { channel })
+                if self.channels.get_right(&channel).is_none() =>
+            {
+                return Some(FollowUp::LookUp(channel));
+            }
+
+            Ok(rtm::Event::ChannelRename { channel }) | Ok(rtm::Event::GroupRename { channel }) => {
+                if let Some(name) = channel.name {
+                    self.rename_channel(channel.id, name.into());
+                }
+            }
+
+            Ok(rtm::Event::ChannelLeft { channel })
+            | Ok(rtm::Event::ChannelArchive { channel })
+            | Ok(rtm::Event::GroupLeft { channel })
+            | Ok(rtm::Event::GroupArchive { channel }) => self.unlist_channel(channel),
+
             _ => {}
         }
+        None
+    }
+
+    /// Lists a direct message with `user`, which Slack tells us about when they first write
+    fn open_im(&mut self, id: ::slack::ConversationId, user: ::slack::UserId) -> Option<FollowUp> {
+        let name = match self.users.get_right(&user) {
+            Some(name) => name.clone(),
+            None => return Some(FollowUp::LookUp(id)),
+        };
+        if self.list_channel(id, name, Section::DirectMessages) {
+            Some(FollowUp::LoadHistory(id))
+        } else {
+            None
+        }
     }
 }
 
 pub struct SlackConn {
     token: String,
+    server: ServerId,
     team_name: IString,
     users: BiMap<::slack::UserId, IString>,
     channels: BiMap<::slack::ConversationId, IString>,
+    /// User groups by their handle, so they can be mentioned
+    usergroups: BiMap<::slack::UsergroupId, IString>,
+    /// The names of bots that have posted here, looked up as we see them
+    bots: HashMap<::slack::BotId, IString>,
+    /// Public channels we aren't in, for completing /join
+    joinable: Vec<IString>,
     emoji: Vec<IString>,
     last_typing_message: chrono::DateTime<chrono::Utc>,
     my_name: IString,
+    my_id: ::slack::UserId,
+    user_names: UserNames,
     input_sender: ::futures::sync::mpsc::Sender<::websocket::OwnedMessage>,
     tui_sender: SyncSender<ConnEvent>,
     pending_messages: Vec<PendingMessage>,
@@ -288,39 +635,109 @@ impl Completer for SlackCompleter {
     fn autocomplete(&self, word: &str) -> Vec<String> {
         self.inner.read().unwrap().autocomplete(word)
     }
+
+    fn complete_argument(&self, command: &str, argument: &str) -> Vec<String> {
+        self.inner
+            .read()
+            .unwrap()
+            .complete_argument(command, argument)
+    }
 }
 
 impl SlackConn {
-    pub fn create_on(token: &str, sender: SyncSender<ConnEvent>) -> Result<(), ()> {
+    pub fn create_on(
+        token: &str,
+        user_names: UserNames,
+        sender: SyncSender<ConnEvent>,
+    ) -> Result<(), ()> {
         // Launch all of the requests
-        use slack::http::{conversations, emoji, rtm, users};
+        use slack::http::{emoji, rtm, stars, usergroups};
         let emoji_recv = get_slack("emoji.list", &token, &());
         let connect_recv = get_slack("rtm.connect", &token, &());
-        let users_recv = get_slack("users.list", &token, users::ListRequest::new());
-
-        use slack::http::conversations::ChannelType::*;
-        let mut req = conversations::ListRequest::new();
-        req.types = vec![PublicChannel, PrivateChannel, Mpim, Im];
-        let conversations_recv = get_slack("conversations.list", &token, req);
+        let users_token = token.to_string();
+        let users_recv = thread::spawn(move || all_users(&users_token));
+        let mut stars_req = stars::ListRequest::new();
+        stars_req.count = Some(1000);
+        let stars_recv = get_slack("stars.list", &token, stars_req);
+        let usergroups_recv = get_slack("usergroups.list", &token, usergroups::ListRequest::new());
+        let conversations_token = token.to_string();
+        let conversations_recv = thread::spawn(move || all_conversations(&conversations_token));
 
         // We need to know about the users first so that we can digest the list of conversations
-        let users_response: users::ListResponse =
-            users_recv.join().map_err(|e| error!("{:#?}", e))??;
+        let users_response = users_recv
+            .join()
+            .map_err(|e| error!("{:#?}", e))?
+            .map_err(|e| error!("{}", e))?;
 
         let mut users: BiMap<::slack::UserId, IString> = BiMap::new();
-        for user in users_response.members {
-            users.insert(user.id, IString::from(user.name));
+        // People who have left are only named once they turn up in a DM or group DM, so they
+        // don't share names with the people who are still here for nothing
+        let mut departed = HashMap::new();
+        for user in users_response {
+            if user.deleted {
+                departed.insert(user.id, user);
+            } else {
+                users.insert_unique(user.id, user_name(&user, user_names), &user.name);
+            }
         }
 
-        let response_channels: conversations::ListResponse = conversations_recv
+        let response_channels = conversations_recv
+            .join()
+            .map_err(|e| error!("{:#?}", e))?
+            .map_err(|e| error!("{}", e))?;
+
+        // Starred channels are shown in their own section, but failing to find them is no reason
+        // not to connect
+        let starred: Vec<::slack::ConversationId> = stars_recv
             .join()
-            .map_err(|e| error!("{:#?}", e))??;
+            .map_err(|e| error!("{:#?}", e))
+            .and_then(|r: Result<stars::ListResponse, ()>| r)
+            .map(|response| {
+                response
+                    .items
+                    .into_iter()
+                    .filter_map(|item| match item {
+                        stars::ListResponseItem::Channel { channel } => Some(channel),
+                        stars::ListResponseItem::Group { group } => Some(group),
+                        stars::ListResponseItem::Im { channel } => Some(channel),
+                        stars::ListResponseItem::Other => None,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        // Workspaces on the free plan have no user groups, and the method fails
+        let usergroups = usergroups_recv
+            .join()
+            .map_err(|e| error!("{:#?}", e))
+            .and_then(|r: Result<usergroups::ListResponse, ()>| r)
+            .map(|response| {
+                let mut usergroups = BiMap::new();
+                for group in response.usergroups {
+                    usergroups.insert(group.id, group.handle.as_str());
+                }
+                usergroups
+            })
+            .unwrap_or_else(|_| BiMap::new());
 
         use slack::http::conversations::Conversation::*;
-        let mut channels = BiMap::new();
-        let mut channel_names: Vec<IString> = Vec::new();
-        for (id, name) in response_channels
-            .channels
+        let mut joinable: Vec<IString> = response_channels
+            .iter()
+            .filter_map(|channel| match channel {
+                Channel {
+                    name,
+                    is_member: false,
+                    is_archived: false,
+                    ..
+                } => Some(IString::from(name.as_str())),
+                _ => None,
+            })
+            .collect();
+        joinable.sort();
+
+        let mut dms = Vec::new();
+        let mut mpims = Vec::new();
+        let mut listed: Vec<(::slack::ConversationId, IString, Section)> = response_channels
             .into_iter()
             .filter_map(|channel| match channel {
                 Channel {
@@ -331,7 +748,7 @@ impl SlackConn {
                     is_mpim: false,
                     is_archived: false,
                     ..
-                } => Some((id, name.into())),
+                } => Some((id, name.into(), Section::Channels)),
                 Group {
                     id,
                     name,
@@ -340,26 +757,121 @@ impl SlackConn {
                     is_mpim: false,
                     is_archived: false,
                     ..
-                } => Some((id, name.into())),
+                } => Some((id, name.into(), Section::Private)),
                 DirectMessage { id, user, .. } => {
-                    users.get_right(&user).map(|name| (id, name.clone()))
+                    dms.push((id, user));
+                    None
+                }
+                Channel {
+                    id,
+                    name,
+                    is_member: true,
+                    is_mpim: true,
+                    is_archived: false,
+                    ..
+                }
+                | Group {
+                    id,
+                    name,
+                    is_member: true,
+                    is_mpim: true,
+                    is_archived: false,
+                    ..
+                } => {
+                    mpims.push((id, name));
+                    None
                 }
                 _ => None,
-            }) {
-            let name: IString = name;
-            channel_names.push(name.clone());
-            channels.insert(id, name);
-        }
-
-        channel_names.sort();
+            })
+            .collect();
 
         let connect_response: rtm::ConnectResponse =
             connect_recv.join().map_err(|e| error!("{:#?}", e))??;
+        let my_id = ::slack::UserId::from(connect_response.slf.id.as_str());
+
+        // Group DMs are named after the people in them, which takes a request for each one.
+        // A few threads share out the requests, rather than sending them all at once.
+        let mpims = Arc::new(Mutex::new(mpims));
+        let member_recvs: Vec<_> = (0..MEMBER_LOOKUPS)
+            .map(|_| {
+                let mpims = mpims.clone();
+                let token = token.to_string();
+                thread::spawn(move || {
+                    let mut found = Vec::new();
+                    loop {
+                        let next = mpims.lock().unwrap().pop();
+                        match next {
+                            Some((id, name)) => {
+                                found.push((id, name, conversation_members(&token, id)))
+                            }
+                            None => return found,
+                        }
+                    }
+                })
+            })
+            .collect();
+        let mut found = Vec::new();
+        for recv in member_recvs {
+            found.extend(
+                recv.join()
+                    .map_err(|e| error!("{:#?}", e))
+                    .unwrap_or_default(),
+            );
+        }
+
+        let in_dms = dms.iter().map(|(_, user)| user).chain(
+            found
+                .iter()
+                .filter_map(|(_, _, members)| members.as_ref().ok())
+                .flatten(),
+        );
+        for id in in_dms {
+            if let Some(user) = departed.remove(id) {
+                users.insert_unique(user.id, user_name(&user, user_names), &user.name);
+            }
+        }
+        for (id, user) in dms {
+            let name = users
+                .get_right(&user)
+                .cloned()
+                .unwrap_or_else(|| IString::from(user.as_str()));
+            listed.push((id, name, Section::DirectMessages));
+        }
+        for (id, name, members) in found {
+            let name = match members {
+                Ok(members) => mpim_name(&users, my_id, &members),
+                // Slack's own name for it lists everyone too, just less readably
+                Err(e) => {
+                    error!("Couldn't find who's in {}: {}", id, e);
+                    IString::from(name)
+                }
+            };
+            listed.push((id, name, Section::DirectMessages));
+        }
+
+        let mut channels = BiMap::new();
+        for (id, name, _) in &listed {
+            channels.insert_unique(*id, name.clone(), qualifier(*id));
+        }
+        // Labels are read once they're all in, since a later name can change an earlier label
+        let channel_names: Vec<ChannelInfo> = listed
+            .into_iter()
+            .map(|(id, name, section)| ChannelInfo {
+                id: channel_id(id),
+                name: channels.get_right(&id).cloned().unwrap_or(name),
+                section: if starred.contains(&id) {
+                    Section::Starred
+                } else {
+                    section
+                },
+            })
+            .collect();
 
         let websocket_url = connect_response.url.clone();
 
         let my_name = IString::from(connect_response.slf.name);
         let team_name = IString::from(connect_response.team.name);
+        let server = ServerId::unique();
         let (input_sender, input_channel) = mpsc::channel(0);
 
         // Give the emoji handle as long as possible to complete
@@ -375,12 +887,18 @@ impl SlackConn {
 
         let connection = Arc::new(RwLock::new(SlackConn {
             token: String::from(token),
+            server,
             users,
             channels: channels.clone(),
+            usergroups,
+            bots: HashMap::new(),
+            joinable,
             team_name: team_name.clone(),
             emoji,
             last_typing_message: chrono::Utc::now(),
             my_name: my_name.clone(),
+            my_id,
+            user_names,
             input_sender,
             tui_sender: sender.clone(),
             pending_messages: Vec::new(),
@@ -388,18 +906,28 @@ impl SlackConn {
 
         let (tui_send, tui_recv) = std::sync::mpsc::sync_channel(100);
         let _ = sender.send(ConnEvent::ServerConnected {
+            id: server,
             name: team_name.clone(),
-            channels: channel_names.clone(),
+            channels: channel_names,
             completer: Some(Box::new(SlackCompleter {
                 inner: connection.clone(),
             })),
             sender: tui_send,
+            commands: SLACK_COMMANDS,
         });
 
         let conn = connection.clone();
         // Create a background thread that will handle events from the TUI
         thread::spawn(move || {
             while let Ok(event) = tui_recv.recv() {
+                if event.server() != server {
+                    error!(
+                        "Slack connection {:?} got an event for {:?}",
+                        server,
+                        event.server()
+                    );
+                    continue;
+                }
                 match event {
                     TuiEvent::SendMessage {
                         channel, contents, ..
@@ -413,7 +941,7 @@ impl SlackConn {
                     TuiEvent::MarkRead { channel, .. } => conn.read().unwrap().mark_read(&channel),
                     TuiEvent::Command {
                         channel, command, ..
-                    } => conn.read().unwrap().handle_cmd(&channel, &command),
+                    } => SlackConn::handle_cmd(&conn, channel, command),
                     TuiEvent::AddReaction {
                         channel,
                         reaction,
@@ -423,6 +951,54 @@ impl SlackConn {
                         .read()
                         .unwrap()
                         .add_reaction(&channel, &reaction, timestamp),
+                    TuiEvent::ToggleReaction {
+                        channel,
+                        reaction,
+                        timestamp,
+                        ..
+                    } => conn
+                        .read()
+                        .unwrap()
+                        .toggle_reaction(&channel, &reaction, timestamp),
+                    TuiEvent::Search {
+                        channel,
+                        query,
+                        page,
+                        ..
+                    } => {
+                        let conn = conn.clone();
+                        thread::spawn(move || {
+                            if let Err(e) = SlackConn::search(&conn, &query, page) {
+                                conn.read().unwrap().notice(&channel, e);
+                            }
+                        });
+                    }
+                    TuiEvent::LoadAround {
+                        channel, timestamp, ..
+                    } => SlackConn::load_around(&conn, channel, timestamp),
+                    TuiEvent::TakeAction {
+                        channel,
+                        timestamp,
+                        action,
+                        ..
+                    } => conn
+                        .read()
+                        .unwrap()
+                        .take_action(&channel, timestamp, action),
+                    TuiEvent::Download { url, directory, .. } => {
+                        conn.read().unwrap().download(url, directory)
+                    }
+                    TuiEvent::Upload {
+                        channel,
+                        files,
+                        title,
+                        comment,
+                        thread,
+                        ..
+                    } => conn
+                        .read()
+                        .unwrap()
+                        .upload(&channel, files, title, comment, thread),
                 }
             }
         });
@@ -448,7 +1024,9 @@ impl SlackConn {
                             }
                             Ping(m) => Some(Pong(m)),
                             Text(text) => {
-                                thread_conn.write().unwrap().process_slack_message(&text);
+                                let follow_up =
+                                    thread_conn.write().unwrap().process_slack_message(&text);
+                                SlackConn::follow_up(&thread_conn, follow_up);
                                 None
                             }
                             _ => None,
@@ -458,96 +1036,242 @@ impl SlackConn {
             core.run(runner).unwrap();
         });
 
-        for (conversation_id, conversation_name) in channels.clone() {
-            let token = token.to_string();
-            let handler = connection.clone(); // TODO: Change this variable name
-            let sender = sender.clone();
-            let team_name = team_name.clone();
-
-            thread::spawn(move || {
-                use slack::http::conversations;
-
-                let url = format!(
-                    "https://slack.com/api/conversations.info?token={}&{}",
-                    token,
-                    ::serde_urlencoded::to_string(&conversations::InfoRequest::new(
-                        conversation_id
-                    )).unwrap_or_default()
-                );
-
-                let info_response = CLIENT
-                    .get(&url)
-                    .send()
-                    .map_err(|e| error!("{:#?}", e))
-                    .map(|mut r| Response {
-                        text: r.text().unwrap(),
-                        status: r.status(),
-                    }).unwrap();
-
-                let info = deserialize_or_log!(info_response, conversations::InfoResponse).unwrap();
-                use slack::http::conversations::ConversationInfo;
-                let read_at = match info.channel {
-                    ConversationInfo::Channel { last_read, .. } => last_read
-                        .map(|t| t.into())
-                        .unwrap_or_else(conn::DateTime::now),
-                    ConversationInfo::Group { last_read, .. } => last_read.into(),
-                    ConversationInfo::ClosedDirectMessage { .. } => conn::DateTime::now(),
-                    ConversationInfo::OpenDirectMessage { last_read, .. } => last_read.into(),
-                };
+        for (conversation_id, _) in channels.iter() {
+            SlackConn::load_history(&connection, *conversation_id);
+        }
 
-                let mut request = conversations::HistoryRequest::new(conversation_id);
-                request.limit = Some(1000);
-                let url = format!(
-                    "https://slack.com/api/conversations.history?token={}&{}",
-                    token,
-                    ::serde_urlencoded::to_string(request).unwrap_or_default()
-                );
+        Ok(())
+    }
 
-                let history_response = CLIENT
-                    .get(&url)
-                    .send()
-                    .map_err(|e| error!("{:#?}", e))
-                    .map(|mut r| Response {
-                        text: r.text().unwrap(),
-                        status: r.status(),
-                    }).unwrap();
+    /// Fetches a conversation's history in the background and sends it to the TUI
+    fn load_history(handler: &Arc<RwLock<SlackConn>>, conversation_id: ::slack::ConversationId) {
+        let (token, sender, server) = {
+            let conn = handler.read().unwrap();
+            (conn.token.clone(), conn.tui_sender.clone(), conn.server)
+        };
+        let handler = handler.clone(); // TODO: Change this variable name
 
-                let history = deserialize_or_log!(history_response, HistoryResponse).unwrap();
+        thread::spawn(move || {
+            use slack::http::conversations;
+
+            let url = format!(
+                "https://slack.com/api/conversations.info?token={}&{}",
+                token,
+                ::serde_urlencoded::to_string(conversations::InfoRequest::new(conversation_id))
+                    .unwrap_or_default()
+            );
+
+            let info_response = CLIENT
+                .get(&url)
+                .send()
+                .map_err(|e| error!("{:#?}", e))
+                .map(|mut r| Response {
+                    text: r.text().unwrap(),
+                    status: r.status(),
+                })
+                .unwrap();
+
+            let info = deserialize_or_log!(info_response, conversations::InfoResponse).unwrap();
+            use slack::http::conversations::ConversationInfo;
+            let read_at = match info.channel {
+                ConversationInfo::Channel { last_read, .. } => last_read
+                    .map(|t| t.into())
+                    .unwrap_or_else(conn::DateTime::now),
+                ConversationInfo::Group { last_read, .. } => last_read.into(),
+                ConversationInfo::ClosedDirectMessage { .. } => conn::DateTime::now(),
+                ConversationInfo::OpenDirectMessage { last_read, .. } => last_read.into(),
+            };
+
+            let mut request = conversations::HistoryRequest::new(conversation_id);
+            request.limit = Some(1000);
+            let url = format!(
+                "https://slack.com/api/conversations.history?token={}&{}",
+                token,
+                ::serde_urlencoded::to_string(request).unwrap_or_default()
+            );
+
+            let history_response = CLIENT
+                .get(&url)
+                .send()
+                .map_err(|e| error!("{:#?}", e))
+                .map(|mut r| Response {
+                    text: r.text().unwrap(),
+                    status: r.status(),
+                })
+                .unwrap();
+
+            let history = deserialize_or_log!(history_response, HistoryResponse).unwrap();
+
+            let messages = SlackConn::shown_messages(&handler, conversation_id, history.messages);
+
+            let _ = sender.send(ConnEvent::HistoryLoaded {
+                messages,
+                server,
+                channel: channel_id(conversation_id),
+                read_at: Some(read_at),
+            });
+        });
+    }
 
-                let handle = handler.read().unwrap();
-                let messages = history
-                    .messages
+    /// Turns messages from conversations.history into ones to show, after finding out who sent
+    /// them and who they mention
+    fn shown_messages(
+        handler: &Arc<RwLock<SlackConn>>,
+        conversation_id: ::slack::ConversationId,
+        history: Vec<HistoryMessage>,
+    ) -> Vec<Message> {
+        let mentioned: Vec<::slack::UserId> = history
+            .iter()
+            .flat_map(|m| {
+                m.user
                     .into_iter()
-                    .map(|msg| {
-                        let name = msg
-                            .user
-                            .and_then(|name| handle.users.get_right(&name).cloned())
-                            .or_else(|| msg.username.clone())
-                            .or_else(|| msg.bot_id.map(|b| IString::from(b.to_string())))
-                            .unwrap_or_else(|| "UNKNOWNUSER".into());
-                        Message {
-                            server: team_name.clone(),
-                            channel: conversation_name.clone(),
-                            sender: name.clone(),
-                            timestamp: msg.ts.into(),
-                            reactions: msg
-                                .reactions
-                                .iter()
-                                .map(|r| (r.name.clone(), r.count as usize))
-                                .collect(),
-                            contents: msg.to_omni(&handle),
-                        }
-                    }).collect();
-
-                let _ = sender.send(ConnEvent::HistoryLoaded {
-                    messages,
-                    server: team_name.clone(),
-                    channel: conversation_name,
-                    read_at,
+                    .chain(m.text.iter().flat_map(|t| mentioned_users(t)))
+            })
+            .collect();
+        SlackConn::look_up_users(handler, mentioned);
+        let bots: Vec<::slack::BotId> = history
+            .iter()
+            .filter(|m| m.user.is_none() && m.username.is_none())
+            .filter_map(|m| m.bot_id)
+            .collect();
+        SlackConn::look_up_bots(handler, bots);
+
+        let handle = handler.read().unwrap();
+        history
+            .into_iter()
+            .map(|msg| {
+                let name = handle
+                    .sender(msg.user, msg.username.as_deref(), msg.bot_id)
+                    .unwrap_or_else(|| "UNKNOWNUSER".into());
+                let (contents, attachments) = msg.to_omni(&handle);
+                let actions = msg.bot_id.map_or_else(Vec::new, |bot| {
+                    ::slack_actions::actions(bot, &msg.blocks, &msg.attachments)
                 });
+                Message {
+                    server: handle.server,
+                    channel: channel_id(conversation_id),
+                    sender: name,
+                    timestamp: msg.ts.into(),
+                    reactions: msg
+                        .reactions
+                        .iter()
+                        .map(|r| (r.name.clone(), r.count as usize))
+                        .collect(),
+                    contents,
+                    attachments,
+                    actions,
+                    files: msg.files.into_iter().map(|f| f.url_private).collect(),
+                    is_bot: msg.bot_id.is_some(),
+                }
+            })
+            .collect()
+    }
+
+    /// Loads a page of messages either side of `timestamp` in `channel`, which might be long
+    /// before the history we have, so a search result can be shown where it was said
+    fn load_around(
+        handler: &Arc<RwLock<SlackConn>>,
+        channel: ChannelId,
+        timestamp: conn::DateTime,
+    ) {
+        const EITHER_SIDE: u32 = 25;
+
+        let conversation = match conversation_id(&channel) {
+            Some(c) => c,
+            None => {
+                error!("Internal error, {} isn't a Slack ConversationId", channel);
+                return;
+            }
+        };
+        let handler = handler.clone();
+        thread::spawn(move || {
+            let (token, sender, server) = {
+                let conn = handler.read().unwrap();
+                (conn.token.clone(), conn.tui_sender.clone(), conn.server)
+            };
+            // Slack always sends the newest messages in a range first, so the ones just after the
+            // hit are on the last page of everything since it
+            let before = call_slack::<_, HistoryResponse>(
+                "conversations.history",
+                &token,
+                history_before(conversation, timestamp.into(), EITHER_SIDE),
+            );
+            let after = all_pages(|cursor| {
+                let request = history_after(conversation, timestamp.into(), cursor);
+                let response: HistoryResponse =
+                    call_slack("conversations.history", &token, request)?;
+                Ok((response.messages, response.response_metadata))
             });
-        }
+            let history = match (before, after) {
+                (Ok(before), Ok(after)) => {
+                    let newer = after.len().saturating_sub(EITHER_SIDE as usize);
+                    after
+                        .into_iter()
+                        .skip(newer)
+                        .chain(before.messages)
+                        .collect()
+                }
+                (Err(e), _) | (_, Err(e)) => {
+                    let text = format!("Couldn't load the messages around that one: {}", e);
+                    handler.read().unwrap().notice(&channel, text);
+                    return;
+                }
+            };
+            let messages = SlackConn::shown_messages(&handler, conversation, history);
+            let _ = sender.send(ConnEvent::HistoryLoaded {
+                messages,
+                server,
+                channel,
+                read_at: None,
+            });
+        });
+    }
 
+    /// Sends one page of the messages that match `query` to the TUI
+    fn search(handler: &Arc<RwLock<SlackConn>>, query: &str, page: u32) -> Result<(), String> {
+        use slack::http::search::{MessagesRequest, MessagesResponse};
+        const PER_PAGE: u32 = 50;
+
+        let token = handler.read().unwrap().token.clone();
+        let mut request = MessagesRequest::new(query);
+        request.count = Some(PER_PAGE);
+        request.page = Some(page);
+        let response: MessagesResponse = call_slack("search.messages", &token, request)?;
+        let found = response.messages;
+
+        let mentioned: Vec<::slack::UserId> = found
+            .matches
+            .iter()
+            .flat_map(|m| m.user.into_iter().chain(mentioned_users(&m.text)))
+            .collect();
+        SlackConn::look_up_users(handler, mentioned);
+
+        let conn = handler.read().unwrap();
+        let hits = found
+            .matches
+            .into_iter()
+            .map(|m| conn::SearchHit {
+                channel: channel_id(m.channel.id),
+                channel_name: conn
+                    .channels
+                    .get_right(&m.channel.id)
+                    .cloned()
+                    .unwrap_or_else(|| IString::from(m.channel.name.as_str())),
+                sender: conn
+                    .sender(m.user, m.username.as_deref(), None)
+                    .unwrap_or_else(|| "UNKNOWNUSER".into()),
+                timestamp: m.ts.into(),
+                text: conn.decode_text(&m.text),
+            })
+            .collect();
+        let _ = conn.tui_sender.send(ConnEvent::SearchResults {
+            server: conn.server,
+            query: query.to_string(),
+            page: found.paging.page.unwrap_or(page),
+            pages: found.paging.pages.unwrap_or(1),
+            total: found.total,
+            hits,
+        });
         Ok(())
     }
 
@@ -567,19 +1291,10 @@ impl SlackConn {
                 .filter(|name| name.starts_with(&word[1..]))
                 .map(|s| String::from("#") + s)
                 .collect(),
-            Some(':') => self
-                .emoji
-                .iter()
-                .filter(|name| name.starts_with(&word[1..]))
-                .map(|s| format!(":{}:", s))
-                .collect(),
+            Some(':') => self.complete_emoji(&word[1..], ":"),
             Some('+') => {
                 if word.chars().count() > 2 {
-                    self.emoji
-                        .iter()
-                        .filter(|name| name.starts_with(&word[2..]))
-                        .map(|s| format!("+:{}:", s))
-                        .collect()
+                    self.complete_emoji(&word[2..], "+:")
                 } else {
                     Vec::new()
                 }
@@ -588,15 +1303,32 @@ impl SlackConn {
         }
     }
 
-    fn send_typing(&mut self, channel: &str) {
+    // Custom emoji for this workspace and the standard set, merged in alphabetical order
+    fn complete_emoji(&self, prefix: &str, lead: &str) -> Vec<String> {
+        let mut names = ::emoji::complete(prefix);
+        names.extend(
+            self.emoji
+                .iter()
+                .filter(|name| name.starts_with(prefix))
+                .map(|name| String::from(name.as_ref())),
+        );
+        names.sort();
+        names.dedup();
+        names
+            .into_iter()
+            .map(|name| format!("{}{}:", lead, name))
+            .collect()
+    }
+
+    fn send_typing(&mut self, channel: &ChannelId) {
         let now = chrono::Utc::now();
         if (now - self.last_typing_message) < chrono::Duration::seconds(3) {
             return;
         } else {
             self.last_typing_message = chrono::Utc::now();
         }
-        let channel_id = match self.channels.get_left(channel) {
-            Some(id) => *id,
+        let conversation = match conversation_id(channel) {
+            Some(id) => id,
             None => {
                 error!("Unknown channel: {}", channel);
                 return;
@@ -608,14 +1340,14 @@ impl SlackConn {
             id += 1;
         }
         self.pending_messages.push(PendingMessage {
-            channel: IString::from(channel),
+            channel: channel.clone(),
             id,
         });
 
         let message = json!({
             "id": id,
             "type": "typing",
-            "channel": channel_id,
+            "channel": conversation,
         });
 
         let _ = ::serde_json::to_string(&message)
@@ -629,10 +1361,10 @@ impl SlackConn {
             });
     }
 
-    fn send_channel_message(&mut self, channel: &str, contents: &str) {
-        let contents = self.to_slack(contents.to_string());
-        let channel_id = match self.channels.get_left(channel) {
-            Some(id) => *id,
+    fn send_channel_message(&mut self, channel: &ChannelId, contents: &str) {
+        let contents = self.to_slack(contents);
+        let conversation = match conversation_id(channel) {
+            Some(id) => id,
             None => {
                 error!("Unknown channel: {}", channel);
                 return;
@@ -644,14 +1376,14 @@ impl SlackConn {
             id += 1;
         }
         self.pending_messages.push(PendingMessage {
-            channel: IString::from(channel),
+            channel: channel.clone(),
             id,
         });
 
         let message = json!({
             "id": id,
             "type": "message",
-            "channel": channel_id,
+            "channel": conversation,
             "text": contents,
         });
 
@@ -666,11 +1398,11 @@ impl SlackConn {
             });
     }
 
-    fn mark_read(&self, channel: &str) {
+    fn mark_read(&self, channel: &ChannelId) {
         use slack::http::{channels, groups, im};
 
-        let channel_or_group_id = match self.channels.get_left(channel) {
-            Some(s) => *s,
+        let channel_or_group_id = match conversation_id(channel) {
+            Some(s) => s,
             None => {
                 error!(
                     "Tried to mark unread for channel {} in server {} but channel does not exist",
@@ -701,75 +1433,683 @@ impl SlackConn {
         }
     }
 
-    fn add_reaction(&self, channel: &str, reaction: &str, timestamp: conn::DateTime) {
+    /// The URLs to add and to remove a reaction on a message
+    fn reaction_urls(
+        &self,
+        channel: &ChannelId,
+        reaction: &str,
+        timestamp: conn::DateTime,
+    ) -> Option<(String, String)> {
+        let channel = match conversation_id(channel) {
+            Some(c) => c,
+            None => {
+                error!("Internal error, {} isn't a Slack ConversationId", channel);
+                return None;
+            }
+        };
+
+        use slack::http::reactions::{AddRequest, Reactable, RemoveRequest};
+        let item = Reactable::Message {
+            channel,
+            timestamp: timestamp.into(),
+        };
+        let url = |endpoint: &str, query: String| {
+            format!(
+                "https://slack.com/api/{}?token={}&{}",
+                endpoint, self.token, query
+            )
+        };
+        Some((
+            url(
+                "reactions.add",
+                ::serde_urlencoded::to_string(AddRequest::new(reaction, item.clone()))
+                    .unwrap_or_default(),
+            ),
+            url(
+                "reactions.remove",
+                ::serde_urlencoded::to_string(RemoveRequest::new(reaction, item))
+                    .unwrap_or_default(),
+            ),
+        ))
+    }
+
+    fn add_reaction(&self, channel: &ChannelId, reaction: &str, timestamp: conn::DateTime) {
+        if let Some((url, _)) = self.reaction_urls(channel, reaction, timestamp) {
+            thread::spawn(move || {
+                let _ = CLIENT.post(&url).send().map_err(|e| error!("{:#?}", e));
+            });
+        }
+    }
+
+    /// Slack will tell us if we've already reacted, and then we take the reaction away instead
+    fn toggle_reaction(&self, channel: &ChannelId, reaction: &str, timestamp: conn::DateTime) {
+        if let Some((add_url, remove_url)) = self.reaction_urls(channel, reaction, timestamp) {
+            thread::spawn(move || {
+                let error = CLIENT
+                    .post(&add_url)
+                    .send()
+                    .and_then(|mut r| r.text())
+                    .map_err(|e| error!("{:#?}", e))
+                    .ok()
+                    .and_then(|text| ::serde_json::from_str::<::slack::http::Error>(&text).ok())
+                    .and_then(|e| e.error);
+                if error.as_deref() == Some("already_reacted") {
+                    let _ = CLIENT
+                        .post(&remove_url)
+                        .send()
+                        .map_err(|e| error!("{:#?}", e));
+                }
+            });
+        }
+    }
+
+    /// The app that posted the message answers by posting or updating a message of its own, so
+    /// all we show is whether Slack took the action
+    fn take_action(&self, channel: &ChannelId, timestamp: conn::DateTime, action: conn::Action) {
+        let conversation = match conversation_id(channel) {
+            Some(c) => c,
+            None => {
+                error!("Internal error, {} isn't a Slack ConversationId", channel);
+                return;
+            }
+        };
         let token = self.token.clone();
-        let name = IString::from(reaction);
+        let server = self.server;
+        let channel = channel.clone();
+        let sender = self.tui_sender.clone();
+        thread::spawn(move || {
+            let text = match ::slack_actions::take(
+                "https://slack.com/api",
+                &token,
+                conversation,
+                timestamp.into(),
+                &action.payload,
+            ) {
+                Ok(()) => format!("Sent \"{}\"", action.label),
+                Err(e) => format!("Couldn't send \"{}\": {}", action.label, e),
+            };
+            let _ = sender.send(ConnEvent::Notice {
+                server,
+                channel,
+                text,
+            });
+        });
+    }
+
+    /// Files shared in Slack are only visible to members of the workspace, so those requests
+    /// carry the token. It isn't sent anywhere else.
+    fn download(&self, url: String, directory: ::std::path::PathBuf) {
+        let from_slack = ::reqwest::Url::parse(&url)
+            .ok()
+            .and_then(|u| {
+                u.host_str()
+                    .map(|h| h == "slack.com" || h.ends_with(".slack.com"))
+            })
+            .unwrap_or(false);
+        let mut request = CLIENT.get(&url);
+        if from_slack {
+            request = request.header("Authorization", format!("Bearer {}", self.token));
+        }
+        let server = self.server;
+        let sender = self.tui_sender.clone();
+        thread::spawn(move || ::download::fetch(request, server, url, &directory, &sender));
+    }
 
-        let channel = match self.channels.get_left(channel) {
-            Some(c) => *c,
+    /// Each file goes to a URL that files.getUploadURLExternal gives out for it, then
+    /// files.completeUploadExternal shares all of them at once, under the comment if there is one.
+    fn upload(
+        &self,
+        channel: &ChannelId,
+        files: Vec<::std::path::PathBuf>,
+        title: Option<String>,
+        comment: Option<String>,
+        thread: Option<conn::DateTime>,
+    ) {
+        use reqwest::multipart::Form;
+        use serde::de::IgnoredAny;
+        use slack::http::files::*;
+        use upload::ProgressReader;
+
+        let channel_id = match conversation_id(channel) {
+            Some(id) => id,
             None => {
                 error!(
-                    "Internal error, no known Slack ConversationId for channel name {}",
-                    channel
+                    "Tried to upload to channel {} in server {} but channel does not exist",
+                    channel, self.team_name
                 );
                 return;
             }
         };
+        let token = self.token.clone();
+        let server = self.server;
+        let sender = self.tui_sender.clone();
 
-        use slack::http::reactions::Reactable;
-        let req = ::slack::http::reactions::AddRequest::new(
-            &name,
-            Reactable::Message {
-                channel,
-                timestamp: timestamp.into(),
-            },
-        );
+        thread::spawn(move || {
+            let mut sent = Vec::new();
+            let mut uploaded = Vec::new();
+            for path in files {
+                let result = ProgressReader::open(&path, server, sender.clone())
+                    .map_err(|e| e.to_string())
+                    .and_then(|reader| {
+                        let name = ::upload::file_name(&path);
+                        let request = GetUploadUrlExternalRequest::new(&name, reader.size());
+                        let response: GetUploadUrlExternalResponse =
+                            call_slack("files.getUploadURLExternal", &token, request)?;
+                        let status = CLIENT
+                            .post(&response.upload_url)
+                            .multipart(Form::new().part("file", reader.into_part()))
+                            .send()
+                            .map_err(|e| e.to_string())?
+                            .status();
+                        if status.is_success() {
+                            Ok(response.file_id)
+                        } else {
+                            Err(status.to_string())
+                        }
+                    });
+                match result {
+                    Ok(id) => {
+                        uploaded.push(match title {
+                            Some(ref title) => json!({ "id": id, "title": title }),
+                            None => json!({ "id": id }),
+                        });
+                        sent.push(path);
+                    }
+                    Err(e) => ::upload::finish(&[path], Err(e), server, &sender),
+                }
+            }
+            if sent.is_empty() {
+                return;
+            }
 
-        let url = format!(
-            "https://slack.com/api/{}?token={}&{}",
-            "reactions.add",
-            token,
-            ::serde_urlencoded::to_string(req).unwrap_or_default()
-        );
+            let uploaded = ::serde_json::Value::Array(uploaded).to_string();
+            let mut request = CompleteUploadExternalRequest::new(&uploaded);
+            request.channel_id = Some(channel_id);
+            request.initial_comment = comment.as_deref();
+            request.thread_ts = thread.map(::slack::Timestamp::from);
+            let result =
+                call_slack::<_, IgnoredAny>("files.completeUploadExternal", &token, request)
+                    .map(|_| ());
+            ::upload::finish(&sent, result, server, &sender);
+        });
+    }
+
+    fn complete_argument(&self, command: &str, argument: &str) -> Vec<String> {
+        match command {
+            "join" => {
+                let prefix = argument.trim_start_matches('#');
+                self.joinable
+                    .iter()
+                    .filter(|name| name.starts_with(prefix))
+                    .map(|name| format!("#{}", name))
+                    .collect()
+            }
+            "invite" | "msg" | "query" => {
+                self.autocomplete(&format!("@{}", argument.trim_start_matches('@')))
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn user_ids(&self, names: &[&str]) -> Result<Vec<::slack::UserId>, String> {
+        names
+            .iter()
+            .map(|name| {
+                self.users
+                    .get_left(*name)
+                    .cloned()
+                    .ok_or_else(|| format!("There's nobody named @{}", name))
+            })
+            .collect()
+    }
 
+    /// Runs one of `SLACK_COMMANDS` in the background, and says what went wrong in the channel
+    /// it was typed in if it fails
+    fn handle_cmd(handler: &Arc<RwLock<SlackConn>>, channel: ChannelId, cmd: IString) {
+        let handler = handler.clone();
         thread::spawn(move || {
-            let _ = CLIENT.post(&url).send().map_err(|e| error!("{:#?}", e));
+            if let Err(e) = SlackConn::run_command(&handler, &channel, &cmd) {
+                handler.read().unwrap().notice(&channel, e);
+            }
         });
     }
 
-    fn handle_cmd(&self, channel: &str, cmd: &str) {
-        let args: Vec<_> = cmd.split_whitespace().collect();
-        match args.as_slice() {
-            ["upload", path] => {
-                let url = self.channels.get_left(channel).map(|id| {
-                    format!(
-                        "https://slack.com/api/files.upload?token={}&channels={}",
-                        self.token, id
-                    )
+    fn run_command(
+        handler: &Arc<RwLock<SlackConn>>,
+        channel: &ChannelId,
+        cmd: &str,
+    ) -> Result<(), String> {
+        use serde::de::IgnoredAny;
+        use slack::http::conversations::*;
+
+        let (name, args) = match cmd.find(char::is_whitespace) {
+            Some(i) => (&cmd[..i], cmd[i..].trim()),
+            None => (cmd, ""),
+        };
+        let (token, listed, channel_name) = {
+            let conn = handler.read().unwrap();
+            let listed =
+                conversation_id(channel).filter(|id| conn.channels.get_right(id).is_some());
+            let name = listed
+                .and_then(|id| conn.channels.get_right(&id).cloned())
+                .unwrap_or_else(|| IString::from(channel.as_str()));
+            (conn.token.clone(), listed, name)
+        };
+        let this_channel =
+            || listed.ok_or_else(|| format!("{} isn't a channel in Slack", channel_name));
+        let notice = |text: String| handler.read().unwrap().notice(channel, text);
+
+        match name {
+            "join" => {
+                let wanted = ::commands::join_target(args)?;
+                if handler.read().unwrap().channels.get_left(wanted).is_some() {
+                    return Err(format!("You're already in #{}", wanted));
+                }
+                let found = public_channels(&token)?
+                    .into_iter()
+                    .find(|c| c.name.as_deref() == Some(wanted))
+                    .ok_or_else(|| format!("There's no public channel named #{}", wanted))?;
+                let response: SummaryResponse =
+                    call_slack("conversations.join", &token, JoinRequest::new(found.id))?;
+                SlackConn::add_channel(handler, response.channel.id, wanted, Section::Channels);
+            }
+            "create" => {
+                let (new_name, private) = ::commands::create_target(args)?;
+                let mut request = CreateRequest::new(new_name);
+                request.is_private = Some(private);
+                let response: SummaryResponse =
+                    call_slack("conversations.create", &token, request)?;
+                // Slack lowercases names and swaps out characters it doesn't allow
+                let created = response
+                    .channel
+                    .name
+                    .unwrap_or_else(|| String::from(new_name));
+                let section = if private {
+                    Section::Private
+                } else {
+                    Section::Channels
+                };
+                SlackConn::add_channel(handler, response.channel.id, &created, section);
+            }
+            "leave" | "archive" => {
+                let id = this_channel()?;
+                if name == "leave" {
+                    call_slack::<_, IgnoredAny>(
+                        "conversations.leave",
+                        &token,
+                        LeaveRequest::new(id),
+                    )?;
+                } else {
+                    call_slack::<_, IgnoredAny>(
+                        "conversations.archive",
+                        &token,
+                        ArchiveRequest::new(id),
+                    )?;
+                }
+                let mut conn = handler.write().unwrap();
+                // /join wants Slack's name for it, not the label it had in the channel list
+                let slack_name = conn.channels.name_of(&id).cloned();
+                conn.unlist_channel(id);
+                if let ("leave", ::slack::ConversationId::Channel(_), Some(slack_name)) =
+                    (name, id, slack_name)
+                {
+                    conn.joinable.push(slack_name);
+                    conn.joinable.sort();
+                }
+            }
+            "topic" | "purpose" => {
+                let id = this_channel()?;
+                if args.is_empty() {
+                    let response: SummaryResponse =
+                        call_slack("conversations.info", &token, InfoRequest::new(id))?;
+                    let current = if name == "topic" {
+                        response.channel.topic.map(|t| t.value)
+                    } else {
+                        response.channel.purpose.map(|p| p.value)
+                    };
+                    match current {
+                        Some(ref text) if !text.is_empty() => {
+                            notice(format!("The {} of {} is: {}", name, channel_name, text))
+                        }
+                        _ => notice(format!("{} has no {}", channel_name, name)),
+                    }
+                } else if name == "topic" {
+                    call_slack::<_, IgnoredAny>(
+                        "conversations.setTopic",
+                        &token,
+                        SetTopicRequest::new(id, args),
+                    )?;
+                } else {
+                    call_slack::<_, IgnoredAny>(
+                        "conversations.setPurpose",
+                        &token,
+                        SetPurposeRequest::new(id, args),
+                    )?;
+                }
+            }
+            "invite" => {
+                let id = this_channel()?;
+                let names = ::commands::invitees(args)?;
+                let users = handler.read().unwrap().user_ids(&names)?;
+                call_slack::<_, IgnoredAny>(
+                    "conversations.invite",
+                    &token,
+                    InviteRequest::new(id, users),
+                )?;
+                notice(format!(
+                    "Invited @{} to {}",
+                    names.join(", @"),
+                    channel_name
+                ));
+            }
+            "msg" | "query" => {
+                let (names, text) = ::commands::split_recipients(args);
+                if names.is_empty() {
+                    return Err(format!("Usage: /{} @USER... [TEXT]", name));
+                }
+                let users = handler.read().unwrap().user_ids(&names)?;
+                let response: SummaryResponse = call_slack(
+                    "conversations.open",
+                    &token,
+                    OpenRequest::new(users.clone()),
+                )?;
+                let id = response.channel.id;
+                let listed = handler.read().unwrap().channels.get_right(&id).is_some();
+                if !listed {
+                    let opened = if users.len() == 1 {
+                        IString::from(names[0])
+                    } else {
+                        let conn = handler.read().unwrap();
+                        mpim_name(&conn.users, conn.my_id, &users)
+                    };
+                    SlackConn::add_channel(handler, id, &opened, Section::DirectMessages);
+                }
+                let opened = channel_id(id);
+                let mut conn = handler.write().unwrap();
+                let _ = conn.tui_sender.send(ConnEvent::ShowChannel {
+                    server: conn.server,
+                    channel: opened.clone(),
                 });
-                let form = reqwest::multipart::Form::new()
-                    .text("filename", "Image from omnichat")
-                    .file("file", path)
-                    .map_err(|e| error!("{:#?}", e));
+                if !text.is_empty() {
+                    conn.send_channel_message(&opened, text);
+                }
+            }
+            "browse" => {
+                let channels = public_channels(&token)?;
+                let mut conn = handler.write().unwrap();
+                // What /join completes comes from every channel, not just the ones listed here
+                conn.joinable = channels
+                    .iter()
+                    .filter(|c| !c.is_member)
+                    .filter_map(|c| c.name.as_ref().map(|n| IString::from(n.as_str())))
+                    .collect();
+                conn.joinable.sort();
+                let mut found: Vec<ConversationSummary> = channels
+                    .into_iter()
+                    .filter(|c| c.name.as_ref().is_some_and(|n| n.contains(args)))
+                    .collect();
+                found.sort_by(|a, b| a.name.cmp(&b.name));
+                if found.is_empty() {
+                    return Err(format!("No public channels have {} in their name", args));
+                }
+                let mut text = String::from("Public channels:");
+                for c in found {
+                    text.push_str(&format!(
+                        "\n#{}  {} members{}",
+                        c.name.unwrap_or_default(),
+                        c.num_members.unwrap_or(0),
+                        if c.is_member { " (joined)" } else { "" }
+                    ));
+                    if let Some(topic) = c.topic.filter(|t| !t.value.is_empty()) {
+                        text.push_str(&format!("  {}", topic.value));
+                    }
+                }
+                conn.notice(channel, text);
+            }
+            "search" => {
+                if args.is_empty() {
+                    return Err(String::from("Usage: /search QUERY"));
+                }
+                SlackConn::search(handler, args, 1)?;
+            }
+            _ => return Err(format!("Slack doesn't know the command /{}", name)),
+        }
+        Ok(())
+    }
 
+    /// Lists a channel we've just joined and fetches its history
+    fn add_channel(
+        handler: &Arc<RwLock<SlackConn>>,
+        id: ::slack::ConversationId,
+        name: &str,
+        section: Section,
+    ) {
+        let added = handler
+            .write()
+            .unwrap()
+            .list_channel(id, IString::from(name), section);
+        if added {
+            SlackConn::load_history(handler, id);
+        }
+    }
+
+    fn follow_up(handler: &Arc<RwLock<SlackConn>>, follow_up: Option<FollowUp>) {
+        match follow_up {
+            Some(FollowUp::LoadHistory(id)) => SlackConn::load_history(handler, id),
+            Some(FollowUp::LookUp(id)) => SlackConn::look_up_channel(handler, id),
+            Some(FollowUp::LookUpUsers(users, event)) => {
+                let handler = handler.clone();
                 thread::spawn(move || {
-                    if let (Some(url), Ok(form)) = (url, form) {
-                        CLIENT
-                            .post(&url)
-                            .multipart(form)
-                            .send()
-                            .map(|r| {
-                                if !r.status().is_success() {
-                                    error!("{:#?}", r);
-                                }
-                            }).unwrap_or_else(|e| error!("{:#?}", e));
-                    }
+                    SlackConn::look_up_users(&handler, users);
+                    let follow_up = handler.write().unwrap().process_slack_message(&event);
+                    SlackConn::follow_up(&handler, follow_up);
                 });
             }
-            _ => {
-                error!("unsupported command: {}", cmd);
+            Some(FollowUp::LookUpBots(bots, event)) => {
+                let handler = handler.clone();
+                thread::spawn(move || {
+                    SlackConn::look_up_bots(&handler, bots);
+                    let follow_up = handler.write().unwrap().process_slack_message(&event);
+                    SlackConn::follow_up(&handler, follow_up);
+                });
+            }
+            None => {}
+        }
+    }
+
+    /// Fetches the names of users who weren't in users.list, like people from other workspaces
+    /// in a shared channel. Blocks until they've all been looked up.
+    fn look_up_users(handler: &Arc<RwLock<SlackConn>>, mut ids: Vec<::slack::UserId>) {
+        use slack::http::users::{InfoRequest, InfoResponse};
+        let (token, style) = {
+            let conn = handler.read().unwrap();
+            ids.retain(|id| conn.users.get_right(id).is_none());
+            (conn.token.clone(), conn.user_names)
+        };
+        let mut seen = Vec::new();
+        for id in ids {
+            if seen.contains(&id) {
+                continue;
+            }
+            seen.push(id);
+            let (name, handle) =
+                match call_slack::<_, InfoResponse>("users.info", &token, InfoRequest::new(id)) {
+                    Ok(response) => (user_name(&response.user, style), response.user.name),
+                    Err(e) => {
+                        // Remembering the id as their name stops us asking again for every message
+                        error!("Couldn't look up user {}: {}", id, e);
+                        (IString::from(id.as_str()), id.to_string())
+                    }
+                };
+            handler
+                .write()
+                .unwrap()
+                .users
+                .insert_unique(id, name, &handle);
+        }
+    }
+
+    /// Fetches the names of bots that have posted without giving a name. They're kept for as long
+    /// as we're connected, and updated when Slack tells us a bot has changed.
+    fn look_up_bots(handler: &Arc<RwLock<SlackConn>>, mut ids: Vec<::slack::BotId>) {
+        use slack::http::bots::{InfoRequest, InfoResponse};
+        let token = {
+            let conn = handler.read().unwrap();
+            ids.retain(|id| !conn.bots.contains_key(id));
+            conn.token.clone()
+        };
+        ids.sort_by_key(|id| id.to_string());
+        ids.dedup();
+        for id in ids {
+            let name =
+                match call_slack::<_, InfoResponse>("bots.info", &token, InfoRequest::new(id)) {
+                    Ok(response) => IString::from(response.bot.name),
+                    Err(e) => {
+                        // Like users, the id stands in for the name so we don't keep asking
+                        error!("Couldn't look up bot {}: {}", id, e);
+                        IString::from(id.as_str())
+                    }
+                };
+            handler.write().unwrap().bots.insert(id, name);
+        }
+    }
+
+    /// Finds out what a conversation we've only seen the id of is called, then lists it
+    fn look_up_channel(handler: &Arc<RwLock<SlackConn>>, id: ::slack::ConversationId) {
+        let handler = handler.clone();
+        thread::spawn(move || {
+            use slack::http::conversations::{InfoRequest, SummaryResponse};
+            let token = handler.read().unwrap().token.clone();
+            let response: SummaryResponse =
+                match call_slack("conversations.info", &token, InfoRequest::new(id)) {
+                    Ok(response) => response,
+                    Err(e) => return error!("Couldn't look up conversation {}: {}", id, e),
+                };
+            let found = if response.channel.is_mpim {
+                conversation_members(&token, id).map(|members| {
+                    let conn = handler.read().unwrap();
+                    Some((
+                        mpim_name(&conn.users, conn.my_id, &members),
+                        Section::DirectMessages,
+                    ))
+                })
+            } else {
+                Ok(handler
+                    .read()
+                    .unwrap()
+                    .describe_conversation(&response.channel))
+            };
+            match found {
+                Ok(Some((name, section))) => {
+                    SlackConn::add_channel(&handler, id, &name, section);
+                }
+                Ok(None) => error!("Don't know what to call conversation {}", id),
+                Err(e) => error!("Couldn't find who's in {}: {}", id, e),
             }
+        });
+    }
+
+    /// The name and section a conversation is listed under, if that can be worked out without
+    /// asking Slack. Group DMs are named after their members, which have to be looked up.
+    fn describe_conversation(
+        &self,
+        conversation: &::slack::http::conversations::ConversationSummary,
+    ) -> Option<(IString, Section)> {
+        if conversation.is_im {
+            return conversation
+                .user
+                .and_then(|user| self.users.get_right(&user))
+                .map(|name| (name.clone(), Section::DirectMessages));
+        }
+        if conversation.is_mpim {
+            return None;
+        }
+        let section = if conversation.is_private {
+            Section::Private
+        } else {
+            Section::Channels
+        };
+        conversation
+            .name
+            .as_ref()
+            .map(|name| (IString::from(name.as_str()), section))
+    }
+
+    /// Adds a conversation to the channel list, returning false if it was already there
+    fn list_channel(
+        &mut self,
+        id: ::slack::ConversationId,
+        name: IString,
+        section: Section,
+    ) -> bool {
+        if self.channels.get_right(&id).is_some() {
+            return false;
+        }
+        self.joinable.retain(|n| *n != name);
+        let displaced = self.channels.get_left(&name).cloned();
+        let name = self.channels.insert_unique(id, name, qualifier(id));
+        self.relabel(displaced);
+        let _ = self.tui_sender.send(ConnEvent::ChannelAdded {
+            server: self.server,
+            channel: ChannelInfo {
+                id: channel_id(id),
+                name,
+                section,
+            },
+        });
+        true
+    }
+
+    fn unlist_channel(&mut self, id: ::slack::ConversationId) {
+        if let Some(name) = self.channels.get_right(&id).cloned() {
+            self.channels.remove_right(&name);
+            let _ = self.tui_sender.send(ConnEvent::ChannelRemoved {
+                server: self.server,
+                channel: channel_id(id),
+            });
+        }
+    }
+
+    fn rename_channel(&mut self, id: ::slack::ConversationId, new: IString) {
+        let old = match self.channels.get_right(&id) {
+            Some(old) if *old != new => old.clone(),
+            _ => return,
+        };
+        let displaced = self.channels.get_left(&new).cloned();
+        let new = self.channels.insert_unique(id, new, qualifier(id));
+        self.relabel(displaced);
+        if old == new {
+            return;
         }
+        let _ = self.tui_sender.send(ConnEvent::ChannelRenamed {
+            server: self.server,
+            channel: channel_id(id),
+            name: new,
+        });
+    }
+
+    /// Tells the TUI about a conversation that was relabelled when another took the same name
+    fn relabel(&self, displaced: Option<::slack::ConversationId>) {
+        let id = match displaced {
+            Some(id) => id,
+            None => return,
+        };
+        if let Some(name) = self.channels.get_right(&id) {
+            let _ = self.tui_sender.send(ConnEvent::ChannelRenamed {
+                server: self.server,
+                channel: channel_id(id),
+                name: name.clone(),
+            });
+        }
+    }
+
+    /// Shows `text` in `channel`, for things the user is waiting on like the result of a command
+    fn notice(&self, channel: &ChannelId, text: String) {
+        let _ = self.tui_sender.send(ConnEvent::Notice {
+            server: self.server,
+            channel: channel.clone(),
+            text,
+        });
     }
 }
 
@@ -779,20 +2119,6 @@ struct Reaction {
     count: u32,
 }
 
-#[derive(Deserialize)]
-struct Attachment {
-    pretext: Option<String>,
-    text: Option<String>,
-    title: Option<String>,
-    #[serde(default)]
-    files: Vec<File>,
-}
-
-#[derive(Deserialize)]
-struct File {
-    url_private: String,
-}
-
 #[derive(Deserialize)]
 struct HistoryMessage {
     text: Option<String>,
@@ -803,47 +2129,192 @@ struct HistoryMessage {
     #[serde(default)]
     reactions: Vec<Reaction>,
     #[serde(default)]
+    blocks: Vec<Block>,
+    #[serde(default)]
     attachments: Vec<Attachment>,
     #[serde(default)]
     files: Vec<File>,
 }
 
 impl HistoryMessage {
-    fn to_omni(&self, handler: &SlackConn) -> String {
-        use std::fmt::Write;
-        let mut body = match self.text {
-            Some(ref t) => handler.convert_mentions(t),
-            None => String::new(),
-        };
+    fn to_omni(&self, handler: &SlackConn) -> (String, Vec<conn::Attachment>) {
+        handler.message_body(&self.text, &self.blocks, &self.attachments, &self.files)
+    }
+}
 
-        for f in &self.files {
-            write!(body, "\n{}", f.url_private);
-        }
+#[derive(Deserialize)]
+struct HistoryResponse {
+    messages: Vec<HistoryMessage>,
+    response_metadata: Option<::slack::http::ResponseMetadata>,
+}
 
-        for a in &self.attachments {
-            if let Some(ref title) = a.title {
-                write!(body, "\n{}", title);
-            }
-            if let Some(ref pretext) = a.pretext {
-                write!(body, "\n{}", pretext);
-            }
-            if let Some(ref text) = a.text {
-                write!(body, "\n{}", text);
-            }
-            for f in &a.files {
-                write!(body, "\n{}", f.url_private);
-            }
+/// Asks for the `limit` messages up to and including `timestamp`
+fn history_before(
+    conversation: ::slack::ConversationId,
+    timestamp: ::slack::Timestamp,
+    limit: u32,
+) -> ::slack::http::conversations::HistoryRequest {
+    let mut request = ::slack::http::conversations::HistoryRequest::new(conversation);
+    request.latest = Some(timestamp);
+    request.inclusive = Some(true);
+    request.limit = Some(limit);
+    request
+}
+
+/// Asks for a page of the messages after `timestamp`, up to the newest
+fn history_after(
+    conversation: ::slack::ConversationId,
+    timestamp: ::slack::Timestamp,
+    cursor: Option<::slack::http::Cursor>,
+) -> ::slack::http::conversations::HistoryRequest {
+    let mut request = ::slack::http::conversations::HistoryRequest::new(conversation);
+    request.oldest = Some(timestamp);
+    request.inclusive = Some(false);
+    request.limit = Some(200);
+    request.cursor = cursor;
+    request
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use slack::http::users::{Profile, User};
+    use slack::http::{Cursor, ResponseMetadata};
+
+    fn user(display_name: &str, profile_real_name: &str, real_name: Option<&str>) -> User {
+        User {
+            id: ::slack::UserId::from("U1"),
+            name: String::from("jsmith"),
+            real_name: real_name.map(String::from),
+            deleted: false,
+            profile: Some(Profile {
+                display_name: display_name.to_string(),
+                real_name: profile_real_name.to_string(),
+            }),
         }
+    }
 
-        body = body.replace("&amp;", "&");
-        body = body.replace("&lt;", "<");
-        body = body.replace("&gt;", ">");
+    #[test]
+    fn user_names_fall_back_to_the_handle() {
+        use self::UserNames::*;
+        let full = user("Jo", "Jo Smith", Some("Joanna Smith"));
+        assert_eq!(user_name(&full, DisplayName), "Jo");
+        assert_eq!(user_name(&full, RealName), "Jo Smith");
+        assert_eq!(user_name(&full, Handle), "jsmith");
+
+        let no_display_name = user("", "Jo Smith", None);
+        assert_eq!(user_name(&no_display_name, DisplayName), "Jo Smith");
+
+        let real_name_outside_profile = user("", "", Some("Joanna Smith"));
+        assert_eq!(
+            user_name(&real_name_outside_profile, DisplayName),
+            "Joanna Smith"
+        );
+        assert_eq!(
+            user_name(&real_name_outside_profile, RealName),
+            "Joanna Smith"
+        );
 
-        body.trim().to_string()
+        let nothing_set = user("", "", Some(""));
+        assert_eq!(user_name(&nothing_set, DisplayName), "jsmith");
+        assert_eq!(user_name(&nothing_set, RealName), "jsmith");
+        let no_profile = User {
+            profile: None,
+            ..user("", "", None)
+        };
+        assert_eq!(user_name(&no_profile, DisplayName), "jsmith");
     }
-}
 
-#[derive(Deserialize)]
-struct HistoryResponse {
-    messages: Vec<HistoryMessage>,
+    #[test]
+    fn senders_fall_back_to_the_bot_id() {
+        let alice = ::slack::UserId::from("U1");
+        let bot = ::slack::BotId::from("B1");
+        let mut users = BiMap::new();
+        users.insert_unique(alice, "alice".into(), "alice");
+        let mut bots = HashMap::new();
+        let sender = |user, username, bot_id, bots: &HashMap<_, _>| {
+            sender_name(&users, bots, user, username, bot_id).map(|n| n.to_string())
+        };
+
+        assert_eq!(
+            sender(Some(alice), Some("deploys"), Some(bot), &bots),
+            Some(String::from("alice"))
+        );
+        // Someone we haven't heard of yet is passed over
+        let unknown = Some(::slack::UserId::from("U2"));
+        assert_eq!(
+            sender(unknown, Some("deploys"), Some(bot), &bots),
+            Some(String::from("deploys"))
+        );
+        assert_eq!(
+            sender(None, None, Some(bot), &bots),
+            Some(String::from("B1"))
+        );
+        bots.insert(bot, IString::from("Jenkins"));
+        assert_eq!(
+            sender(None, None, Some(bot), &bots),
+            Some(String::from("Jenkins"))
+        );
+        assert_eq!(sender(unknown, None, None, &bots), None);
+    }
+
+    #[test]
+    fn history_around_brackets_the_hit() {
+        let conversation = ::slack::ConversationId::parse("C1").unwrap();
+        let hit: ::slack::Timestamp = ::serde_json::from_str("\"1500000000.000200\"").unwrap();
+        let query = |request| ::serde_urlencoded::to_string(request).unwrap();
+
+        assert_eq!(
+            query(history_before(conversation, hit, 25)),
+            "channel=C1&inclusive=true&latest=1500000000.000200&limit=25"
+        );
+        assert_eq!(
+            query(history_after(conversation, hit, None)),
+            "channel=C1&inclusive=false&limit=200&oldest=1500000000.000200"
+        );
+        let cursor: Cursor = ::serde_json::from_str("\"next\"").unwrap();
+        assert_eq!(
+            query(history_after(conversation, hit, Some(cursor))),
+            "channel=C1&cursor=next&inclusive=false&limit=200&oldest=1500000000.000200"
+        );
+    }
+
+    #[test]
+    fn all_pages_follows_the_cursor() {
+        let mut asked_for = Vec::new();
+        let items = all_pages(|cursor: Option<Cursor>| {
+            asked_for.push(cursor.map(|c| ::serde_json::to_string(&c).unwrap()));
+            let next_cursor = match asked_for.len() {
+                1 => "b",
+                2 => "c",
+                _ => "",
+            };
+            let metadata = ResponseMetadata {
+                next_cursor: next_cursor.to_string(),
+            };
+            Ok((vec![asked_for.len()], Some(metadata)))
+        })
+        .unwrap();
+        assert_eq!(items, vec![1, 2, 3]);
+        assert_eq!(
+            asked_for,
+            vec![
+                None,
+                Some(String::from("\"b\"")),
+                Some(String::from("\"c\""))
+            ]
+        );
+
+        // Methods that fit everything on one page might not send a cursor at all
+        let mut pages = 0;
+        let items = all_pages(|_| {
+            pages += 1;
+            Ok((vec!["a"], None))
+        })
+        .unwrap();
+        assert_eq!((items, pages), (vec!["a"], 1));
+
+        let failed: Result<Vec<u32>, String> = all_pages(|_| Err(String::from("ratelimited")));
+        assert_eq!(failed, Err(String::from("ratelimited")));
+    }
 }
@@ -0,0 +1,937 @@
+//! Slack's message format.
+//!
+//! Slack wants mentions spelled out with ids, like `<@U012AB3CD>` or `<#C012AB3CD>`, and `&`,
+//! `<` and `>` escaped everywhere else. What the user types is split into tokens first, so a name
+//! is only turned into a mention where it stands on its own, and never inside a code span or a URL.
+//!
+//! Messages coming the other way have the same escaping, and everything between `<` and `>` is
+//! a mention, a link or one of Slack's special commands like `<!here>` or `<!date^...>`. The
+//! blocks and attachments that apps post are laid out as lines of text in the same style.
+use bimap::BiMap;
+use conn;
+use conn::IString;
+use slack::blocks::*;
+use slack::{ConversationId, UserId, UsergroupId};
+use tui::URL_REGEX;
+
+/// The names people, channels and groups are shown as, and the ids Slack knows them by
+pub struct Names<'a> {
+    pub users: &'a BiMap<UserId, IString>,
+    pub channels: &'a BiMap<ConversationId, IString>,
+    pub usergroups: &'a BiMap<UsergroupId, IString>,
+}
+
+/// A piece of a message on its way to Slack
+#[derive(Debug, PartialEq)]
+enum Token<'a> {
+    Text(&'a str),
+    /// Inline code or a code block, with its backticks
+    Code(&'a str),
+    Url(&'a str),
+    User(UserId),
+    Channel(ConversationId),
+    Usergroup(UsergroupId),
+    /// @here, @channel or @everyone, without the @
+    Special(&'a str),
+}
+
+/// The longest name a mention is looked for in. Display names are at most 80 characters, and
+/// the labels that tell apart people with the same name add a little to that.
+const LONGEST_NAME: usize = 256;
+
+/// Whether `c` can be part of a name, so a mention can't start straight after it or stop just
+/// before it. This is what keeps @bob out of @bobby and alice@example.com.
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-'
+}
+
+/// Splits `text` into plain and code parts, true for code. An unclosed backtick is just text,
+/// and inline code doesn't carry on past the end of a line.
+fn code_spans(text: &str) -> Vec<(bool, &str)> {
+    let mut parts = Vec::new();
+    let mut plain_start = 0;
+    let mut from = 0;
+    while let Some(offset) = text[from..].find('`') {
+        let start = from + offset;
+        let fence = if text[start..].starts_with("```") {
+            "```"
+        } else {
+            "`"
+        };
+        let inside = start + fence.len();
+        match text[inside..].find(fence) {
+            Some(len)
+                if len > 0 && (fence == "```" || !text[inside..inside + len].contains('\n')) =>
+            {
+                let end = inside + len + fence.len();
+                if start > plain_start {
+                    parts.push((false, &text[plain_start..start]));
+                }
+                parts.push((true, &text[start..end]));
+                plain_start = end;
+                from = end;
+            }
+            _ => from = inside,
+        }
+    }
+    if plain_start < text.len() {
+        parts.push((false, &text[plain_start..]));
+    }
+    parts
+}
+
+/// The mention at the start of `text`, which starts with @ or #, and how long it is. The longest
+/// name that fits wins, so @alex (asmith) isn't taken for @alex.
+fn mention<'a>(text: &'a str, names: &Names) -> Option<(Token<'a>, usize)> {
+    let sigil = text.chars().next()?;
+    let body = &text[1..];
+    let mut ends: Vec<usize> = body
+        .char_indices()
+        .map(|(i, _)| i)
+        .skip(1)
+        .chain(Some(body.len()))
+        .take_while(|end| *end <= LONGEST_NAME)
+        .filter(|end| !body[*end..].chars().next().is_some_and(is_name_char))
+        .collect();
+    ends.reverse();
+
+    ends.into_iter().find_map(|end| {
+        let name = &body[..end];
+        let token = match sigil {
+            '@' => match name {
+                "here" | "channel" | "everyone" => Some(Token::Special(name)),
+                _ => names
+                    .users
+                    .get_left(name)
+                    .map(|id| Token::User(*id))
+                    .or_else(|| {
+                        names
+                            .usergroups
+                            .get_left(name)
+                            .map(|id| Token::Usergroup(*id))
+                    }),
+            },
+            // Direct messages are listed under people's names, but they can't be linked to
+            _ => match names.channels.get_left(name) {
+                Some(ConversationId::DirectMessage(_)) | None => None,
+                Some(id) => Some(Token::Channel(*id)),
+            },
+        };
+        token.map(|token| (token, 1 + end))
+    })
+}
+
+/// Finds the mentions in text that has no code or URLs in it
+fn mentions<'a>(text: &'a str, names: &Names, tokens: &mut Vec<Token<'a>>) {
+    let mut plain_start = 0;
+    let mut previous = None;
+    let mut i = 0;
+    while let Some(c) = text[i..].chars().next() {
+        if (c == '@' || c == '#') && !previous.is_some_and(is_name_char) {
+            if let Some((token, len)) = mention(&text[i..], names) {
+                if i > plain_start {
+                    tokens.push(Token::Text(&text[plain_start..i]));
+                }
+                tokens.push(token);
+                i += len;
+                plain_start = i;
+                previous = text[..i].chars().next_back();
+                continue;
+            }
+        }
+        previous = Some(c);
+        i += c.len_utf8();
+    }
+    if plain_start < text.len() {
+        tokens.push(Token::Text(&text[plain_start..]));
+    }
+}
+
+fn tokenize<'a>(text: &'a str, names: &Names) -> Vec<Token<'a>> {
+    let mut tokens = Vec::new();
+    for (code, part) in code_spans(text) {
+        if code {
+            tokens.push(Token::Code(part));
+            continue;
+        }
+        let mut last = 0;
+        for url in URL_REGEX.find_iter(part) {
+            mentions(&part[last..url.start()], names, &mut tokens);
+            tokens.push(Token::Url(url.as_str()));
+            last = url.end();
+        }
+        mentions(&part[last..], names, &mut tokens);
+    }
+    tokens
+}
+
+/// Escapes the characters Slack uses for its own markup
+fn escape(text: &str, out: &mut String) {
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            c => out.push(c),
+        }
+    }
+}
+
+/// Turns what the user typed into the text of a Slack message
+pub fn encode(text: &str, names: &Names) -> String {
+    let mut out = String::with_capacity(text.len());
+    for token in tokenize(text, names) {
+        match token {
+            Token::Text(text) | Token::Code(text) | Token::Url(text) => escape(text, &mut out),
+            Token::User(id) => out.push_str(&format!("<@{}>", id)),
+            Token::Channel(id) => out.push_str(&format!("<#{}>", id)),
+            Token::Usergroup(id) => out.push_str(&format!("<!subteam^{}>", id)),
+            Token::Special(name) => out.push_str(&format!("<!{}>", name)),
+        }
+    }
+    out
+}
+
+/// Undoes `escape`
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+/// Fills in the `{date}` style tokens of a `<!date^...>` with the local time. None if the
+/// timestamp isn't one.
+fn format_date(timestamp: &str, format: &str) -> Option<String> {
+    use chrono::TimeZone;
+    const TOKENS: [(&str, &str); 9] = [
+        ("{date_num}", "%Y-%m-%d"),
+        ("{date_short_pretty}", "%b %-d, %Y"),
+        ("{date_long_pretty}", "%A, %B %-d, %Y"),
+        ("{date_pretty}", "%B %-d, %Y"),
+        ("{date_short}", "%b %-d, %Y"),
+        ("{date_long}", "%A, %B %-d, %Y"),
+        ("{date}", "%B %-d, %Y"),
+        ("{time_secs}", "%H:%M:%S"),
+        ("{time}", "%H:%M"),
+    ];
+    let date = ::chrono::Local
+        .timestamp_opt(timestamp.parse().ok()?, 0)
+        .single()?;
+    let mut text = String::from(format);
+    for (token, pattern) in &TOKENS {
+        if text.contains(token) {
+            text = text.replace(token, &date.format(pattern).to_string());
+        }
+    }
+    Some(text)
+}
+
+/// What to show for the `<...>` that has `inside` between its brackets. Names we know are used
+/// over the labels Slack sends, so people and channels look the same as everywhere else.
+fn decode_link(inside: &str, names: &Names) -> String {
+    let (target, label) = match inside.find('|') {
+        Some(i) => (&inside[..i], Some(unescape(&inside[i + 1..]))),
+        None => (inside, None),
+    };
+    let known_or = |name: Option<&IString>, id: &str| {
+        name.map(|n| n.to_string())
+            .or_else(|| label.clone())
+            .unwrap_or_else(|| String::from(id))
+    };
+
+    if let Some(id) = target.strip_prefix('@') {
+        let name = UserId::parse(id).and_then(|id| names.users.get_right(&id));
+        return format!("@{}", known_or(name, id));
+    }
+    if let Some(id) = target.strip_prefix('#') {
+        let name = ConversationId::parse(id).and_then(|id| names.channels.get_right(&id));
+        return format!("#{}", known_or(name, id));
+    }
+    if let Some(command) = target.strip_prefix('!') {
+        let mut parts = command.split('^');
+        return match parts.next().unwrap_or_default() {
+            "subteam" => {
+                let id = parts.next().unwrap_or_default();
+                match UsergroupId::parse(id).and_then(|id| names.usergroups.get_right(&id)) {
+                    Some(handle) => format!("@{}", handle),
+                    None => label.unwrap_or_else(|| format!("@{}", id)),
+                }
+            }
+            "date" => {
+                let date = match (parts.next(), parts.next()) {
+                    (Some(timestamp), Some(format)) => format_date(timestamp, &unescape(format)),
+                    _ => None,
+                };
+                date.or(label).unwrap_or_default()
+            }
+            special @ "here" | special @ "channel" | special @ "everyone" => {
+                format!("@{}", special)
+            }
+            other => label.unwrap_or_else(|| format!("@{}", other)),
+        };
+    }
+
+    // Anything else is a link
+    link(&unescape(target), label.as_deref())
+}
+
+/// Shows a link with its label, if it has one worth showing
+fn link(url: &str, label: Option<&str>) -> String {
+    let address = url.trim_start_matches("mailto:");
+    let without_scheme = url.splitn(2, "://").last().unwrap_or(url);
+    match label {
+        Some(label) if !label.is_empty() && label != address && label != without_scheme => {
+            format!("{} ({})", label, address)
+        }
+        _ => String::from(address),
+    }
+}
+
+/// Turns the text of a message from Slack into what's shown
+pub fn decode(text: &str, names: &Names) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('<') {
+        let end = match rest[start..].find('>') {
+            Some(len) => start + len,
+            None => break,
+        };
+        out.push_str(&unescape(&rest[..start]));
+        out.push_str(&decode_link(&rest[start + 1..end], names));
+        rest = &rest[end + 1..];
+    }
+    out.push_str(&unescape(rest));
+    out
+}
+
+/// Drawn for a divider block, which doesn't know how wide the screen is
+const DIVIDER: &str = "────────────────────";
+
+/// The color of an attachment's bar, from one of Slack's names for them or a hex code
+pub fn color(name: &str) -> Option<(u8, u8, u8)> {
+    match name {
+        "good" => Some((0x2e, 0xb6, 0x7d)),
+        "warning" => Some((0xec, 0xb2, 0x2e)),
+        "danger" => Some((0xe0, 0x1e, 0x5a)),
+        _ => {
+            let hex = name.trim_start_matches('#');
+            if hex.len() != 6 || !hex.is_ascii() {
+                return None;
+            }
+            let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+            Some((channel(0)?, channel(2)?, channel(4)?))
+        }
+    }
+}
+
+fn text_object(text: &Text, names: &Names) -> String {
+    match text.kind {
+        TextKind::Mrkdwn => decode(&text.text, names),
+        TextKind::PlainText => text.text.clone(),
+    }
+}
+
+/// Wraps `text` in the markup for `style`, leaving any whitespace around it outside
+fn styled(text: &str, style: RichTextStyle, out: &mut String) {
+    let start = text.len() - text.trim_start().len();
+    let end = text.trim_end().len().max(start);
+    let mut marks = String::new();
+    for (on, mark) in &[
+        (style.code, '`'),
+        (style.bold, '*'),
+        (style.italic, '_'),
+        (style.strike, '~'),
+    ] {
+        if *on {
+            marks.push(*mark);
+        }
+    }
+    out.push_str(&text[..start]);
+    if start < end {
+        out.push_str(&marks);
+        out.push_str(&text[start..end]);
+        out.extend(marks.chars().rev());
+    }
+    out.push_str(&text[end..]);
+}
+
+fn rich_text_elements(elements: &[RichTextElement], names: &Names) -> String {
+    let mut out = String::new();
+    for element in elements {
+        match element {
+            RichTextElement::Text { text, style } => styled(text, *style, &mut out),
+            RichTextElement::Link { url, text } => out.push_str(&link(url, text.as_deref())),
+            RichTextElement::User { user_id } => {
+                out.push('@');
+                match names.users.get_right(user_id) {
+                    Some(name) => out.push_str(name),
+                    None => out.push_str(user_id.as_str()),
+                }
+            }
+            RichTextElement::Channel { channel_id } => {
+                out.push('#');
+                match names.channels.get_right(channel_id) {
+                    Some(name) => out.push_str(name),
+                    None => out.push_str(channel_id.as_str()),
+                }
+            }
+            RichTextElement::Usergroup { usergroup_id } => {
+                out.push('@');
+                match names.usergroups.get_right(usergroup_id) {
+                    Some(handle) => out.push_str(handle),
+                    None => out.push_str(usergroup_id.as_str()),
+                }
+            }
+            RichTextElement::Broadcast { range } => {
+                out.push('@');
+                out.push_str(range);
+            }
+            RichTextElement::Emoji { name } => {
+                out.push(':');
+                out.push_str(name);
+                out.push(':');
+            }
+            RichTextElement::Date {
+                timestamp,
+                format,
+                fallback,
+            } => {
+                let date = format_date(&timestamp.to_string(), format).or_else(|| fallback.clone());
+                out.push_str(&date.unwrap_or_default());
+            }
+            RichTextElement::Unknown => {}
+        }
+    }
+    out
+}
+
+fn rich_text(part: &RichText, names: &Names) -> String {
+    match part {
+        RichText::RichTextSection { elements } => rich_text_elements(elements, names),
+        RichText::RichTextList {
+            style,
+            indent,
+            elements,
+        } => {
+            let mut lines = Vec::new();
+            for (i, item) in elements.iter().enumerate() {
+                let bullet = match style {
+                    ListStyle::Bullet => String::from("•"),
+                    ListStyle::Ordered => format!("{}.", i + 1),
+                };
+                lines.push(format!(
+                    "{}{} {}",
+                    "  ".repeat(*indent),
+                    bullet,
+                    rich_text(item, names)
+                ));
+            }
+            lines.join("\n")
+        }
+        RichText::RichTextPreformatted { elements } => {
+            format!("```\n{}\n```", rich_text_elements(elements, names))
+        }
+        RichText::RichTextQuote { elements } => rich_text_elements(elements, names)
+            .lines()
+            .map(|line| format!("> {}", line))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        RichText::Unknown => String::new(),
+    }
+}
+
+fn block(block: &Block, names: &Names) -> String {
+    match block {
+        Block::Section { text, fields, .. } => text
+            .iter()
+            .chain(fields)
+            .map(|text| text_object(text, names))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        Block::Context { elements } => elements
+            .iter()
+            .filter_map(|element| match element {
+                ContextElement::PlainText { text } => Some(text.clone()),
+                ContextElement::Mrkdwn { text } => Some(decode(text, names)),
+                // Context images are tiny icons, which aren't worth a URL
+                ContextElement::Image { .. } | ContextElement::Unknown => None,
+            })
+            .collect::<Vec<_>>()
+            .join("  "),
+        Block::Divider {} => String::from(DIVIDER),
+        Block::Header { text } => text_object(text, names),
+        Block::Image {
+            image_url,
+            alt_text,
+            title,
+        } => {
+            let label = title
+                .as_ref()
+                .map_or_else(|| alt_text.clone(), |t| t.text.clone());
+            link(image_url, Some(&label))
+        }
+        Block::RichText { elements } => elements
+            .iter()
+            .map(|part| rich_text(part, names))
+            .filter(|text| !text.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n"),
+        // Buttons and menus are shown apart from the text, so they can be clicked on
+        Block::Actions { .. } | Block::Unknown => String::new(),
+    }
+}
+
+/// Lays out the blocks of a message or attachment as lines of text
+pub fn blocks(blocks: &[Block], names: &Names) -> String {
+    blocks
+        .iter()
+        .map(|b| block(b, names))
+        .filter(|text| !text.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Lays out everything in an attachment except its pretext, which goes above it
+pub fn attachment(attachment: &Attachment, names: &Names) -> conn::Attachment {
+    let mut lines = Vec::new();
+    let mut labelled = |text: &Option<String>, url: &Option<String>| {
+        if let Some(ref text) = *text {
+            let text = decode(text, names);
+            lines.push(match *url {
+                Some(ref url) => link(url, Some(&text)),
+                None => text,
+            });
+        }
+    };
+    labelled(&attachment.author_name, &attachment.author_link);
+    labelled(&attachment.title, &attachment.title_link);
+    labelled(&attachment.text, &None);
+
+    for field in &attachment.fields {
+        let title = field.title.as_ref().map(|t| decode(t, names));
+        let value = field.value.as_ref().map(|v| decode(v, names));
+        lines.push(match (title, value) {
+            (Some(title), Some(ref value)) if !value.contains('\n') => {
+                format!("{}: {}", title, value)
+            }
+            (Some(title), Some(value)) => format!("{}:\n{}", title, value),
+            (Some(title), None) => title,
+            (None, Some(value)) => value,
+            (None, None) => continue,
+        });
+    }
+
+    lines.push(blocks(&attachment.blocks, names));
+    lines.extend(attachment.image_url.clone());
+    lines.extend(attachment.files.iter().map(|f| f.url_private.clone()));
+
+    let time = attachment.ts.and_then(|ts| {
+        format_date(
+            &(ts.microseconds / 1_000_000).to_string(),
+            "{date_short} {time}",
+        )
+    });
+    let footer = attachment.footer.as_ref().map(|f| decode(f, names));
+    lines.push(match (footer, time) {
+        (Some(footer), Some(time)) => format!("{} | {}", footer, time),
+        (footer, time) => footer.or(time).unwrap_or_default(),
+    });
+
+    lines.retain(|line| !line.is_empty());
+    if lines.is_empty() {
+        lines.extend(attachment.fallback.as_ref().map(|f| decode(f, names)));
+    }
+
+    conn::Attachment {
+        color: attachment.color.as_ref().and_then(|c| color(c)),
+        contents: lines.join("\n"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Fixture {
+        users: BiMap<UserId, IString>,
+        channels: BiMap<ConversationId, IString>,
+        usergroups: BiMap<UsergroupId, IString>,
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            let mut users = BiMap::new();
+            users.insert(UserId::from("U01"), "bob");
+            users.insert(UserId::from("U02"), "bobby");
+            users.insert(UserId::from("U03"), "Alice Smith");
+            users.insert(UserId::from("U04"), "alex");
+            users.insert(UserId::from("U05"), "alex (asmith)");
+            users.insert(UserId::from("U06"), "here");
+            users.insert(UserId::from("W0123456789"), "carol");
+            let mut channels = BiMap::new();
+            channels.insert(ConversationId::Channel("C01".into()), "general");
+            channels.insert(ConversationId::Channel("C02".into()), "dev-ops");
+            channels.insert(ConversationId::Group("G01".into()), "secret");
+            channels.insert(ConversationId::DirectMessage("D01".into()), "bob");
+            let mut usergroups = BiMap::new();
+            usergroups.insert(UsergroupId::from("S01"), "design");
+            Fixture {
+                users,
+                channels,
+                usergroups,
+            }
+        }
+
+        fn names(&self) -> Names<'_> {
+            Names {
+                users: &self.users,
+                channels: &self.channels,
+                usergroups: &self.usergroups,
+            }
+        }
+
+        fn encode(&self, text: &str) -> String {
+            encode(text, &self.names())
+        }
+
+        fn decode(&self, text: &str) -> String {
+            decode(text, &self.names())
+        }
+    }
+
+    #[test]
+    fn mentions_people_channels_and_groups() {
+        let f = Fixture::new();
+        assert_eq!(f.encode("hi @bob"), "hi <@U01>");
+        assert_eq!(f.encode("@bob, @bobby!"), "<@U01>, <@U02>!");
+        assert_eq!(f.encode("ask @Alice Smith."), "ask <@U03>.");
+        assert_eq!(
+            f.encode("see #general and #secret"),
+            "see <#C01> and <#G01>"
+        );
+        assert_eq!(f.encode("@design please"), "<!subteam^S01> please");
+        assert_eq!(f.encode("(@bob)"), "(<@U01>)");
+        assert_eq!(f.encode("@bob\n@bob"), "<@U01>\n<@U01>");
+    }
+
+    #[test]
+    fn specials_beat_people() {
+        let f = Fixture::new();
+        assert_eq!(
+            f.encode("@here @channel @everyone"),
+            "<!here> <!channel> <!everyone>"
+        );
+    }
+
+    #[test]
+    fn longest_name_wins() {
+        let f = Fixture::new();
+        assert_eq!(f.encode("@alex (asmith) hi"), "<@U05> hi");
+        assert_eq!(f.encode("@alex hi"), "<@U04> hi");
+        assert_eq!(f.encode("#dev-ops"), "<#C02>");
+    }
+
+    #[test]
+    fn names_only_match_whole_words() {
+        let f = Fixture::new();
+        assert_eq!(f.encode("@bobcat"), "@bobcat");
+        assert_eq!(f.encode("mail bob@example.com"), "mail bob@example.com");
+        assert_eq!(f.encode("#dev"), "#dev");
+        assert_eq!(f.encode("@bob@bob"), "<@U01>@bob");
+        assert_eq!(f.encode("@nobody"), "@nobody");
+        assert_eq!(f.encode("@"), "@");
+        assert_eq!(f.encode("#"), "#");
+    }
+
+    #[test]
+    fn direct_messages_are_not_channels() {
+        let f = Fixture::new();
+        assert_eq!(f.encode("#bob"), "#bob");
+    }
+
+    #[test]
+    fn escapes_markup() {
+        let f = Fixture::new();
+        assert_eq!(f.encode("a < b && c > d"), "a &lt; b &amp;&amp; c &gt; d");
+        assert_eq!(f.encode("<@U01>"), "&lt;@U01&gt;");
+        assert_eq!(f.encode("&amp;"), "&amp;amp;");
+    }
+
+    #[test]
+    fn leaves_code_alone() {
+        let f = Fixture::new();
+        assert_eq!(f.encode("`@bob` @bob"), "`@bob` <@U01>");
+        assert_eq!(
+            f.encode("```\nif a < b { @bob }\n``` #general"),
+            "```\nif a &lt; b { @bob }\n``` <#C01>"
+        );
+        // Unclosed backticks and inline code across lines are just text
+        assert_eq!(f.encode("`@bob"), "`<@U01>");
+        assert_eq!(f.encode("`a\n@bob`"), "`a\n<@U01>`");
+        assert_eq!(f.encode("`` @bob"), "`` <@U01>");
+    }
+
+    #[test]
+    fn leaves_urls_alone() {
+        let f = Fixture::new();
+        assert_eq!(
+            f.encode("https://example.com/#general?a=1&b=@bob @bob"),
+            "https://example.com/#general?a=1&amp;b=@bob <@U01>"
+        );
+    }
+
+    #[test]
+    fn tokens() {
+        let f = Fixture::new();
+        assert_eq!(
+            tokenize(
+                "hi @bob, see `#general` at https://example.com/",
+                &f.names()
+            ),
+            vec![
+                Token::Text("hi "),
+                Token::User(UserId::from("U01")),
+                Token::Text(", see "),
+                Token::Code("`#general`"),
+                Token::Text(" at "),
+                Token::Url("https://example.com/"),
+            ]
+        );
+        assert_eq!(tokenize("", &f.names()), vec![]);
+    }
+
+    #[test]
+    fn multibyte_text() {
+        let f = Fixture::new();
+        assert_eq!(f.encode("こんにちは @bob さん"), "こんにちは <@U01> さん");
+        assert_eq!(f.encode("é@bob"), "é@bob");
+    }
+
+    #[test]
+    fn decodes() {
+        let f = Fixture::new();
+        let cases = [
+            // People, by the name we know them by, then Slack's label, then their id
+            ("hi <@U01>", "hi @bob"),
+            ("<@U01|bobsmith> and <@W0123456789>", "@bob and @carol"),
+            ("<@U99|dave>", "@dave"),
+            ("<@U99>", "@U99"),
+            // Channels
+            ("<#C01>", "#general"),
+            ("<#C01|old-name>", "#general"),
+            ("<#G01|secret>", "#secret"),
+            ("<#C99|random>", "#random"),
+            ("<#C99>", "#C99"),
+            // Special mentions
+            (
+                "<!here> <!channel|channel> <!everyone>",
+                "@here @channel @everyone",
+            ),
+            ("<!here|here>", "@here"),
+            ("<!subteam^S01|@design-team>", "@design"),
+            ("<!subteam^S99|@ops>", "@ops"),
+            ("<!subteam^S99>", "@S99"),
+            ("<!something|Something>", "Something"),
+            ("<!something>", "@something"),
+            // Dates fall back to their label when they can't be read
+            ("<!date^nope^{date}|Feb 18th>", "Feb 18th"),
+            ("<!date^123|Feb 18th>", "Feb 18th"),
+            ("<!date^nope^{date}>", ""),
+            // Links
+            ("<https://example.com>", "https://example.com"),
+            ("<https://example.com|example.com>", "https://example.com"),
+            (
+                "<https://example.com|https://example.com>",
+                "https://example.com",
+            ),
+            (
+                "<https://example.com|the site>",
+                "the site (https://example.com)",
+            ),
+            (
+                "<https://example.com/?a=1&amp;b=2>",
+                "https://example.com/?a=1&b=2",
+            ),
+            ("<mailto:a@example.com|a@example.com>", "a@example.com"),
+            ("<mailto:a@example.com|Alice>", "Alice (a@example.com)"),
+            // Escaping
+            ("1 &lt; 2 &amp;&amp; 3 &gt; 2", "1 < 2 && 3 > 2"),
+            ("&amp;lt;", "&lt;"),
+            (
+                "<https://example.com|a &amp; b>",
+                "a & b (https://example.com)",
+            ),
+            ("unclosed <@U01", "unclosed <@U01"),
+            ("", ""),
+            ("plain", "plain"),
+        ];
+        for (slack, shown) in cases.iter() {
+            assert_eq!(f.decode(slack), *shown, "decoding {}", slack);
+        }
+    }
+
+    #[test]
+    fn decodes_dates_in_local_time() {
+        use chrono::TimeZone;
+        let f = Fixture::new();
+        let local = ::chrono::Local.timestamp_opt(1392734382, 0).unwrap();
+        assert_eq!(
+            f.decode("<!date^1392734382^Posted {date_num} at {time_secs}|Feb 18th>"),
+            format!(
+                "Posted {} at {}",
+                local.format("%Y-%m-%d"),
+                local.format("%H:%M:%S")
+            )
+        );
+        assert_eq!(
+            f.decode("<!date^1392734382^{date_short}^https://example.com/|Feb 18th>"),
+            local.format("%b %-d, %Y").to_string()
+        );
+    }
+
+    #[test]
+    fn round_trips() {
+        let f = Fixture::new();
+        for text in [
+            "@bob and @Alice Smith in #general",
+            "@here a < b && `c > d`",
+            "see https://example.com/?a=1&b=2 @design",
+            "mail bob@example.com",
+        ]
+        .iter()
+        {
+            assert_eq!(f.decode(&f.encode(text)), *text);
+        }
+    }
+
+    #[test]
+    fn lays_out_blocks() {
+        let f = Fixture::new();
+        let parsed: Vec<Block> = ::serde_json::from_value(json!([
+            {"type": "header", "text": {"type": "plain_text", "text": "Deploy <finished>"}},
+            {"type": "section", "text": {"type": "mrkdwn", "text": "by <@U01> in <#C01>"},
+             "fields": [
+                {"type": "mrkdwn", "text": "*Env*\nprod"},
+                {"type": "plain_text", "text": "v1.2"},
+            ]},
+            {"type": "divider"},
+            {"type": "context", "elements": [
+                {"type": "image", "image_url": "https://example.com/i.png", "alt_text": "icon"},
+                {"type": "mrkdwn", "text": "took 3m"},
+                {"type": "plain_text", "text": "build 42"},
+            ]},
+            {"type": "image", "image_url": "https://example.com/graph.png", "alt_text": "graph"},
+            {"type": "actions", "elements": []},
+        ]))
+        .unwrap();
+        assert_eq!(
+            blocks(&parsed, &f.names()),
+            format!(
+                "Deploy <finished>\nby @bob in #general\n*Env*\nprod\nv1.2\n{}\n\
+                 took 3m  build 42\ngraph (https://example.com/graph.png)",
+                DIVIDER
+            )
+        );
+    }
+
+    #[test]
+    fn lays_out_rich_text() {
+        let f = Fixture::new();
+        let parsed: Vec<Block> = ::serde_json::from_value(json!([{
+            "type": "rich_text",
+            "elements": [
+                {"type": "rich_text_section", "elements": [
+                    {"type": "text", "text": "hi "},
+                    {"type": "user", "user_id": "U01"},
+                    {"type": "text", "text": " see "},
+                    {"type": "text", "text": "this ", "style": {"bold": true}},
+                    {"type": "link", "url": "https://example.com", "text": "site"},
+                    {"type": "text", "text": " "},
+                    {"type": "emoji", "name": "tada"},
+                    {"type": "broadcast", "range": "here"},
+                    {"type": "channel", "channel_id": "C99"},
+                    {"type": "usergroup", "usergroup_id": "S01"},
+                ]},
+                {"type": "rich_text_list", "style": "ordered", "elements": [
+                    {"type": "rich_text_section", "elements": [{"type": "text", "text": "one"}]},
+                    {"type": "rich_text_section", "elements": [{"type": "text", "text": "two"}]},
+                ]},
+                {"type": "rich_text_list", "style": "bullet", "indent": 1, "elements": [
+                    {"type": "rich_text_section", "elements": [
+                        {"type": "text", "text": "x", "style": {"code": true, "italic": true}},
+                    ]},
+                ]},
+                {"type": "rich_text_preformatted", "elements": [{"type": "text", "text": "a < b"}]},
+                {"type": "rich_text_quote", "elements": [{"type": "text", "text": "said\nthis"}]},
+                {"type": "something_new"},
+            ]
+        }]))
+        .unwrap();
+        assert_eq!(
+            blocks(&parsed, &f.names()),
+            "hi @bob see *this* site (https://example.com) :tada:@here#C99@design\n\
+             1. one\n2. two\n  • `_x_`\n```\na < b\n```\n> said\n> this"
+        );
+    }
+
+    #[test]
+    fn lays_out_attachments() {
+        let f = Fixture::new();
+        let attachment: Attachment = ::serde_json::from_value(json!({
+            "fallback": "Build failed",
+            "color": "danger",
+            "pretext": "CI says",
+            "author_name": "ci-bot",
+            "title": "Build #42 failed",
+            "title_link": "https://ci.example.com/42",
+            "text": "<@U01> broke it",
+            "fields": [
+                {"title": "Branch", "value": "main", "short": true},
+                {"title": "Log", "value": "line 1\nline 2"},
+            ],
+            "footer": "CI",
+            "ts": 1392734382,
+        }))
+        .unwrap();
+        let time = {
+            use chrono::TimeZone;
+            ::chrono::Local
+                .timestamp_opt(1392734382, 0)
+                .unwrap()
+                .format("%b %-d, %Y %H:%M")
+                .to_string()
+        };
+        assert_eq!(
+            super::attachment(&attachment, &f.names()),
+            conn::Attachment {
+                color: Some((0xe0, 0x1e, 0x5a)),
+                contents: format!(
+                    "ci-bot\nBuild #42 failed (https://ci.example.com/42)\n@bob broke it\n\
+                     Branch: main\nLog:\nline 1\nline 2\nCI | {}",
+                    time
+                ),
+            }
+        );
+
+        let empty: Attachment =
+            ::serde_json::from_value(json!({"fallback": "Build failed", "color": "#36a64f"}))
+                .unwrap();
+        assert_eq!(
+            super::attachment(&empty, &f.names()),
+            conn::Attachment {
+                color: Some((0x36, 0xa6, 0x4f)),
+                contents: String::from("Build failed"),
+            }
+        );
+    }
+
+    #[test]
+    fn colors() {
+        assert_eq!(color("good"), Some((0x2e, 0xb6, 0x7d)));
+        assert_eq!(color("#FF0000"), Some((0xff, 0, 0)));
+        assert_eq!(color("00ff00"), Some((0, 0xff, 0)));
+        assert_eq!(color("#fff"), None);
+        assert_eq!(color("#gggggg"), None);
+        assert_eq!(color("é1234"), None);
+    }
+}
@@ -0,0 +1,391 @@
+//! Colors and text styles for everything the TUI draws.
+//!
+//! A style is written in the config file as a space-separated list of attributes and colors, like
+//! `"bold red"`, `"245"`, `"italic #ff8800 on black"`. A color can be one of the 16 terminal
+//! colors by name (`red`, `bright-red`, ...), a 256-color palette index, or `#rrggbb` truecolor.
+//! We write the escape sequences ourselves so that the 16 basic colors use the plain SGR codes
+//! that every terminal supports.
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Turns off every color and attribute
+pub const RESET: &str = "\x1b[m";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Color {
+    /// One of the 16 terminal colors, 0-7 normal and 8-15 bright
+    Basic(u8),
+    /// An index into the 256-color palette
+    Ansi(u8),
+    Rgb(u8, u8, u8),
+}
+
+const BASIC_NAMES: [&str; 8] = [
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+impl Color {
+    fn sgr(self, background: bool) -> String {
+        let (basic, bright, extended) = if background {
+            (40, 100, 48)
+        } else {
+            (30, 90, 38)
+        };
+        match self {
+            Color::Basic(n) if n < 8 => (basic + u32::from(n)).to_string(),
+            Color::Basic(n) => (bright + u32::from(n) - 8).to_string(),
+            Color::Ansi(n) => format!("{};5;{}", extended, n),
+            Color::Rgb(r, g, b) => format!("{};2;{};{};{}", extended, r, g, b),
+        }
+    }
+}
+
+impl FromStr for Color {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (bright, name) = match s.find("bright-") {
+            Some(0) => (true, &s[7..]),
+            _ => (false, s),
+        };
+        if let Some(n) = BASIC_NAMES.iter().position(|c| *c == name) {
+            return Ok(Color::Basic(n as u8 + if bright { 8 } else { 0 }));
+        }
+        if s.starts_with('#') && s.len() == 7 {
+            let channel = |i: usize| u8::from_str_radix(&s[i..i + 2], 16);
+            if let (Ok(r), Ok(g), Ok(b)) = (channel(1), channel(3), channel(5)) {
+                return Ok(Color::Rgb(r, g, b));
+            }
+        }
+        s.parse::<u8>()
+            .map(Color::Ansi)
+            .map_err(|_| format!("unknown color {:?}", s))
+    }
+}
+
+/// Colors and attributes for one kind of thing on the screen
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Style {
+    fg: Option<Color>,
+    bg: Option<Color>,
+    bold: bool,
+    dim: bool,
+    italic: bool,
+    underline: bool,
+    reverse: bool,
+}
+
+impl FromStr for Style {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut style = Style::default();
+        let mut words = s.split_whitespace();
+        while let Some(word) = words.next() {
+            match word {
+                "default" | "plain" => {}
+                "bold" => style.bold = true,
+                "dim" => style.dim = true,
+                "italic" => style.italic = true,
+                "underline" => style.underline = true,
+                "reverse" => style.reverse = true,
+                "on" => {
+                    let color = words
+                        .next()
+                        .ok_or_else(|| format!("expected a color after \"on\" in {:?}", s))?;
+                    style.bg = Some(color.parse()?);
+                }
+                color => style.fg = Some(color.parse()?),
+            }
+        }
+        Ok(style)
+    }
+}
+
+impl<'de> ::serde::Deserialize<'de> for Style {
+    fn deserialize<D>(deserializer: D) -> Result<Style, D::Error>
+    where
+        D: ::serde::Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(::serde::de::Error::custom)
+    }
+}
+
+impl Style {
+    /// Just a truecolor foreground, for colors that come from messages rather than the config
+    pub fn rgb(r: u8, g: u8, b: u8) -> Style {
+        fg(Color::Rgb(r, g, b))
+    }
+}
+
+/// Writes the escape sequence that turns this style on, or nothing for the terminal's default
+impl fmt::Display for Style {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut codes = Vec::new();
+        for (on, code) in &[
+            (self.bold, "1"),
+            (self.dim, "2"),
+            (self.italic, "3"),
+            (self.underline, "4"),
+            (self.reverse, "7"),
+        ] {
+            if *on {
+                codes.push(code.to_string());
+            }
+        }
+        codes.extend(self.fg.map(|c| c.sgr(false)));
+        codes.extend(self.bg.map(|c| c.sgr(true)));
+        if codes.is_empty() {
+            Ok(())
+        } else {
+            write!(f, "\x1b[{}m", codes.join(";"))
+        }
+    }
+}
+
+/// The `[theme]` section of the config file. `name` picks a built-in theme, and every other
+/// field overrides one part of it.
+#[derive(Deserialize, Default)]
+pub struct ThemeConfig {
+    name: Option<String>,
+    selected: Option<Style>,
+    unread: Option<Style>,
+    inactive: Option<Style>,
+    border: Option<Style>,
+    timestamp: Option<Style>,
+    reactions: Option<Style>,
+    date: Option<Style>,
+    text: Option<Style>,
+    input: Option<Style>,
+    highlight: Option<Style>,
+    found: Option<Style>,
+    nick_colors: Option<Vec<Style>>,
+    #[serde(default)]
+    nicks: HashMap<String, Style>,
+}
+
+pub struct Theme {
+    /// The current server and channel
+    pub selected: Style,
+    /// Servers and channels with unread messages, and the unread marker
+    pub unread: Style,
+    /// All other server and channel names
+    pub inactive: Style,
+    /// The line between the channel list and the messages
+    pub border: Style,
+    pub timestamp: Style,
+    pub reactions: Style,
+    /// The separator between messages from different days
+    pub date: Style,
+    /// Message bodies
+    pub text: Style,
+    /// The message being typed
+    pub input: Style,
+    /// The message that has been clicked on
+    pub highlight: Style,
+    /// Text matching /find
+    pub found: Style,
+    /// Senders are assigned one of these by hashing their name
+    pub nick_colors: Vec<Style>,
+    /// Specific senders can be given their own style
+    pub nicks: HashMap<String, Style>,
+}
+
+fn djb2(input: &str) -> u64 {
+    let mut hash: u64 = 5381;
+
+    for c in input.bytes() {
+        hash = (hash << 5).wrapping_add(hash).wrapping_add(u64::from(c));
+    }
+    hash
+}
+
+fn palette(keep: fn(u8, u8, u8) -> bool) -> Vec<Style> {
+    let mut colors = Vec::new();
+    for r in 0..6 {
+        for g in 0..6 {
+            for b in 0..6 {
+                if keep(r, g, b) {
+                    colors.push(Style {
+                        fg: Some(Color::Ansi(16 + 36 * r + 6 * g + b)),
+                        ..Style::default()
+                    });
+                }
+            }
+        }
+    }
+    colors
+}
+
+fn fg(color: Color) -> Style {
+    Style {
+        fg: Some(color),
+        ..Style::default()
+    }
+}
+
+fn bg(color: Color) -> Style {
+    Style {
+        bg: Some(color),
+        ..Style::default()
+    }
+}
+
+impl Theme {
+    /// Light text on a dark background
+    pub fn dark() -> Self {
+        Theme {
+            selected: Style {
+                bold: true,
+                ..Style::default()
+            },
+            unread: fg(Color::Basic(1)),
+            inactive: fg(Color::Ansi(145)),
+            border: Style::default(),
+            timestamp: fg(Color::Ansi(240)),
+            reactions: fg(Color::Ansi(242)),
+            date: fg(Color::Ansi(240)),
+            text: Style::default(),
+            input: Style::default(),
+            highlight: bg(Color::Ansi(236)),
+            found: Style {
+                fg: Some(Color::Basic(0)),
+                ..bg(Color::Basic(3))
+            },
+            // Bright colors, any with a dark component
+            nick_colors: palette(|r, g, b| r > 0 && g > 0 && b > 0 && (r < 2 || g < 2 || b < 2)),
+            nicks: HashMap::new(),
+        }
+    }
+
+    /// Dark text on a light background
+    pub fn light() -> Self {
+        Theme {
+            selected: Style {
+                bold: true,
+                ..Style::default()
+            },
+            unread: Style {
+                bold: true,
+                ..fg(Color::Basic(1))
+            },
+            inactive: fg(Color::Ansi(243)),
+            border: fg(Color::Ansi(248)),
+            timestamp: fg(Color::Ansi(245)),
+            reactions: fg(Color::Ansi(243)),
+            date: fg(Color::Ansi(245)),
+            text: Style::default(),
+            input: Style::default(),
+            highlight: bg(Color::Ansi(254)),
+            found: bg(Color::Basic(11)),
+            // Saturated colors that are dark enough to read on white
+            nick_colors: palette(|r, g, b| {
+                r + g + b <= 6 && (r > 2 || g > 2 || b > 2) && !(r == g && g == b)
+            }),
+            nicks: HashMap::new(),
+        }
+    }
+
+    pub fn from_config(config: ThemeConfig) -> Result<Self, String> {
+        let mut theme = match config.name.as_deref() {
+            None | Some("dark") => Theme::dark(),
+            Some("light") => Theme::light(),
+            Some(other) => {
+                return Err(format!(
+                    "unknown theme {:?}, the built-in themes are dark and light",
+                    other
+                ))
+            }
+        };
+
+        macro_rules! set {
+            ($($role:ident),*) => {
+                $(if let Some(style) = config.$role {
+                    theme.$role = style;
+                })*
+            };
+        }
+        set!(
+            selected, unread, inactive, border, timestamp, reactions, date, text, input, highlight,
+            found
+        );
+
+        if let Some(colors) = config.nick_colors {
+            if colors.is_empty() {
+                return Err(String::from("nick_colors must contain at least one style"));
+            }
+            theme.nick_colors = colors;
+        }
+        theme.nicks = config.nicks;
+        Ok(theme)
+    }
+
+    /// The style a sender's name is drawn in
+    pub fn nick(&self, name: &str) -> &Style {
+        self.nicks
+            .get(name)
+            .unwrap_or_else(|| &self.nick_colors[djb2(name) as usize % self.nick_colors.len()])
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::dark()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_styles() {
+        assert_eq!("red".parse::<Style>().unwrap().to_string(), "\x1b[31m");
+        assert_eq!(
+            "bold bright-blue".parse::<Style>().unwrap().to_string(),
+            "\x1b[1;94m"
+        );
+        assert_eq!(
+            "245 on black".parse::<Style>().unwrap().to_string(),
+            "\x1b[38;5;245;40m"
+        );
+        assert_eq!(
+            "italic #ff8000".parse::<Style>().unwrap().to_string(),
+            "\x1b[3;38;2;255;128;0m"
+        );
+        assert_eq!("default".parse::<Style>().unwrap().to_string(), "");
+        assert!("purple".parse::<Style>().is_err());
+        assert!("256".parse::<Style>().is_err());
+        assert!("red on".parse::<Style>().is_err());
+    }
+
+    #[test]
+    fn config_overrides() {
+        let config: ThemeConfig = ::toml::from_str(
+            r##"
+            name = "light"
+            unread = "bold #cc0000"
+            nick_colors = ["blue", "green"]
+            [nicks]
+            alice = "underline magenta"
+            "##,
+        )
+        .unwrap();
+        let theme = Theme::from_config(config).unwrap();
+        assert_eq!(theme.unread.to_string(), "\x1b[1;38;2;204;0;0m");
+        assert_eq!(theme.inactive, Theme::light().inactive);
+        assert_eq!(theme.nick("alice").to_string(), "\x1b[4;35m");
+        assert!(["\x1b[34m", "\x1b[32m"].contains(&theme.nick("bob").to_string().as_str()));
+
+        let config: ThemeConfig = ::toml::from_str("name = \"solarized\"").unwrap();
+        assert!(Theme::from_config(config).is_err());
+    }
+
+    #[test]
+    fn builtin_palettes() {
+        assert_eq!(Theme::dark().nick_colors.len(), 61);
+        assert!(!Theme::light().nick_colors.is_empty());
+    }
+}
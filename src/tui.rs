@@ -1,9 +1,17 @@
-use chan_message::ChanMessage;
-use conn::{Completer, ConnEvent, DateTime, IString, Message, TuiEvent};
+use chan_message::{self, ChanMessage, SpanTarget, TimeFormat};
+use commands::CLIENT_COMMANDS;
+use conn::{
+    ChannelId, ChannelInfo, Command, Completer, ConnEvent, DateTime, Direction, Handler, IString,
+    Message, Section, ServerId, TransferStatus, TuiEvent,
+};
 use cursor_vec::CursorVec;
+use download::DownloadConfig;
 use regex::Regex;
 use std::cmp::{max, min};
+use std::collections::HashMap;
 use std::sync::mpsc::{sync_channel, Receiver, RecvTimeoutError, SyncSender};
+use theme::{Theme, RESET};
+use urls::UrlConfig;
 
 lazy_static! {
     // https://daringfireball.net/2010/07/improved_regex_for_matching_urls
@@ -11,50 +19,446 @@ lazy_static! {
     pub static ref URL_REGEX: Regex = Regex::new(r#"(?i)\b((?:[a-z][\w-]+:(?:/{1,3}|[a-z0-9%])|www\d{0,3}[.]|[a-z0-9.\-]+[.][a-z]{2,4}/)(?:[^\s()<>]+|\(([^\s()<>]+|(\([^\s()<>]+\)))*\))+(?:\(([^\s()<>]+|(\([^\s()<>]+\)))*\)|[^\s`!()\[\]{};:'".,<>?«»“”‘’]))"#).unwrap();
 }
 
-const CHAN_WIDTH: u16 = 20;
+// The channel list can't be made narrower than this, or squeeze the messages narrower than this
+const MIN_SIDEBAR_WIDTH: u16 = 6;
+const MIN_MESSAGE_WIDTH: u16 = 20;
+
+/// Settings for how things are drawn, from the `[display]` section of the config file
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct DisplayConfig {
+    /// Render emoji shortcodes as glyphs, turn this off if your terminal has no emoji font
+    pub emoji: bool,
+    pub time_format: TimeFormat,
+    /// Leave out the sender and time of consecutive messages from the same sender
+    pub compact: bool,
+    /// How far apart two messages can be and still be shown as one run in compact mode
+    pub compact_minutes: i64,
+    /// Let the server bar take a second row when the names don't fit on one
+    pub wrap_server_names: bool,
+    /// Columns taken up by the channel list, including the border
+    pub sidebar_width: u16,
+    /// Size the channel list to fit the longest channel name instead
+    pub sidebar_auto_width: bool,
+    /// Take over the mouse so things can be clicked on. The terminal's own text selection will
+    /// usually still work with shift held down.
+    pub mouse: bool,
+}
+
+impl Default for DisplayConfig {
+    fn default() -> Self {
+        Self {
+            emoji: true,
+            time_format: TimeFormat::TwentyFourHour,
+            compact: false,
+            compact_minutes: 5,
+            wrap_server_names: false,
+            sidebar_width: 20,
+            sidebar_auto_width: false,
+            mouse: true,
+        }
+    }
+}
+
+const SERVER_SEPARATOR: &str = " • ";
+
+/// The list of URLs opened by /url
+struct UrlPicker {
+    urls: Vec<String>,
+    selected: usize,
+}
+
+/// A list drawn over the messages, such as the URL picker
+struct Overlay {
+    title: String,
+    rows: Vec<(String, ClickTarget)>,
+    selected: Option<usize>,
+}
+
+/// A file a connection is uploading or saving for us, shown in the status line until it's done
+struct Transfer {
+    server: ServerId,
+    id: String,
+    direction: Direction,
+    file: String,
+    done: u64,
+    total: Option<u64>,
+}
+
+/// Something that does something when it's clicked on
+#[derive(Clone)]
+enum ClickTarget {
+    Server(usize),
+    Section(Section),
+    Channel(usize),
+    Message(DateTime),
+    Reaction {
+        timestamp: DateTime,
+        reaction: IString,
+    },
+    Url(String),
+    Action {
+        timestamp: DateTime,
+        index: usize,
+    },
+    /// A row of the search results, by its index in the page
+    SearchHit(usize),
+    /// A row of the /find results
+    FindHit(usize),
+}
+
+/// Where a `ClickTarget` was drawn, recorded by `draw` so clicks can be matched up with it
+struct ClickRegion {
+    row: u16,
+    columns: ::std::ops::Range<u16>,
+    target: ClickTarget,
+}
+
+/// A server name placed in the server bar
+#[derive(Debug, PartialEq)]
+struct BarEntry {
+    server: usize,
+    row: usize,
+    column: usize,
+    name: String,
+}
+
+/// Width of the marker for `hidden` servers that are scrolled out of the server bar, like "<2 "
+fn overflow_width(hidden: usize) -> usize {
+    if hidden == 0 {
+        0
+    } else {
+        hidden.to_string().len() + 2
+    }
+}
+
+/// Lays out as many server names as fit in `rows` rows of `width` columns, starting from server
+/// `first`. Room is left for the overflow markers on either side, and a name that doesn't fit on
+/// a row by itself is shortened.
+fn layout_server_bar(names: &[&str], first: usize, width: usize, rows: usize) -> Vec<BarEntry> {
+    use width::{shorten, str_width};
+
+    let pack = |right_reserve: usize| {
+        let mut entries = Vec::new();
+        let mut row = 0;
+        let mut row_start = overflow_width(first);
+        let mut column = row_start;
+        for (server, name) in names.iter().enumerate().skip(first) {
+            loop {
+                let limit = if row + 1 == rows {
+                    width.saturating_sub(right_reserve)
+                } else {
+                    width
+                };
+                let separator = if column > row_start {
+                    str_width(SERVER_SEPARATOR)
+                } else {
+                    0
+                };
+                if column + separator + str_width(name) <= limit || separator == 0 {
+                    if column >= limit {
+                        return entries;
+                    }
+                    let name = shorten(name, limit - column - separator);
+                    column += separator;
+                    let name_width = str_width(&name);
+                    entries.push(BarEntry {
+                        server,
+                        row,
+                        column,
+                        name,
+                    });
+                    column += name_width;
+                    break;
+                }
+                if row + 1 == rows {
+                    return entries;
+                }
+                row += 1;
+                row_start = 0;
+                column = 0;
+            }
+        }
+        entries
+    };
+
+    let everything = pack(0);
+    if everything.last().map(|e| e.server + 1) == Some(names.len()) {
+        everything
+    } else {
+        pack(overflow_width(names.len() - first))
+    }
+}
+
+/// The first server to show so that `selected` is visible, moving as little as possible from
+/// `first`, and not leaving empty space at the end of the bar while servers are hidden before it
+fn scroll_server_bar(
+    names: &[&str],
+    mut first: usize,
+    selected: usize,
+    width: usize,
+    rows: usize,
+) -> usize {
+    let last_shown = |first: usize| {
+        layout_server_bar(names, first, width, rows)
+            .last()
+            .map(|e| e.server)
+    };
+
+    first = ::std::cmp::min(first, selected);
+    while first < selected && last_shown(first) < Some(selected) {
+        first += 1;
+    }
+    while first > 0 && last_shown(first - 1) >= last_shown(first) {
+        first -= 1;
+    }
+    first
+}
 
 pub struct Tui {
+    config: DisplayConfig,
+    theme: Theme,
+    urls: UrlConfig,
+    url_picker: Option<UrlPicker>,
+    /// The results of /search, which cover the messages like the URL picker does
+    search: Option<::search::Results>,
+    /// What /find found in the loaded messages
+    find: Option<::find::Find>,
+    downloads: DownloadConfig,
+    transfers: Vec<Transfer>,
+    /// Shown below the messages until the next key press
+    status: Option<String>,
     servers: CursorVec<Server>,
+    /// Where each server is in `servers`, kept up to date as they're added and sorted
+    server_indices: HashMap<ServerId, usize>,
     longest_channel_name: u16,
+    sidebar_width: u16,
+    sidebar_auto_width: bool,
+    sidebar_hidden: bool,
     shutdown: bool,
     events: Receiver<ConnEvent>,
     sender: SyncSender<ConnEvent>, // Tui can send events to itself, this is also cloned and sent to backend connections
     server_scroll_offset: usize,
     autocompletions: Vec<String>,
     autocomplete_index: usize,
+    /// Where in the message buffer the word being completed starts
+    autocomplete_start: usize,
     cursor_pos: usize,
+    click_regions: Vec<ClickRegion>,
     _guards: (
         ::termion::screen::AlternateScreen<::std::io::Stdout>,
         ::termion::raw::RawTerminal<::std::io::Stdout>,
+        Option<::termion::input::MouseTerminal<::std::io::Stdout>>,
     ),
-    previous_terminal_height: u16,
+    previous_frame_size: (u16, u16),
     truncate_buffer_to: usize,
 }
 
 struct Server {
     channels: Vec<Channel>,
+    /// Where each channel is in `channels`, kept up to date as they're added, removed and sorted
+    channel_indices: HashMap<ChannelId, usize>,
     completer: Option<Box<Completer>>,
+    id: ServerId,
     name: IString,
     current_channel: usize,
     channel_scroll_offset: usize,
+    collapsed: Vec<Section>,
     sender: SyncSender<TuiEvent>,
+    /// The commands this server's connection handles
+    commands: &'static [Command],
+}
+
+/// One line of the channel list
+#[derive(Debug, PartialEq)]
+enum SidebarRow {
+    Header(Section),
+    Channel(usize),
 }
 
 impl Server {
+    fn channel_mut(&mut self, id: &ChannelId) -> Option<&mut Channel> {
+        let index = *self.channel_indices.get(id)?;
+        self.channels.get_mut(index)
+    }
+
+    fn index_channels(&mut self) {
+        self.channel_indices = self
+            .channels
+            .iter()
+            .enumerate()
+            .map(|(i, c)| (c.id.clone(), i))
+            .collect();
+    }
+
+    fn remove_channel(&mut self, id: &ChannelId) {
+        let index = match self.channel_indices.get(id) {
+            Some(i) => *i,
+            None => return,
+        };
+        // Everything else assumes that a server has a channel to show
+        if self.channels.len() == 1 {
+            return;
+        }
+        self.channels.remove(index);
+        self.index_channels();
+        if self.current_channel > index || self.current_channel == self.channels.len() {
+            self.current_channel -= 1;
+        }
+    }
+
+    /// Renames a channel, and moves it to where its new name goes in the list
+    fn rename_channel(&mut self, id: &ChannelId, name: IString) {
+        if let Some(channel) = self.channel_mut(id) {
+            channel.name = name;
+        }
+        self.sort_channels();
+    }
+
+    /// Puts the channels in the order they're listed in, keeping the same one current
+    fn sort_channels(&mut self) {
+        let current = self.channels[self.current_channel].id.clone();
+        self.channels
+            .sort_by(|a, b| (a.section, &a.name).cmp(&(b.section, &b.name)));
+        self.index_channels();
+        self.current_channel = self.channel_indices.get(&current).cloned().unwrap_or(0);
+    }
+
     fn has_unreads(&self) -> bool {
         self.channels.iter().any(|c| c.num_unreads() > 0)
     }
+
+    /// Channels in a collapsed section are hidden, apart from the current one
+    fn is_hidden(&self, channel: usize) -> bool {
+        channel != self.current_channel && self.collapsed.contains(&self.channels[channel].section)
+    }
+
+    /// The channel list, with a header above each section if there is more than one. Channels
+    /// are kept sorted by section so each section's channels are together.
+    fn sidebar_rows(&self) -> Vec<SidebarRow> {
+        let show_headers = self
+            .channels
+            .iter()
+            .any(|c| c.section != self.channels[0].section);
+        let mut rows = Vec::new();
+        for (c, channel) in self.channels.iter().enumerate() {
+            if show_headers && (c == 0 || self.channels[c - 1].section != channel.section) {
+                rows.push(SidebarRow::Header(channel.section));
+            }
+            if !self.is_hidden(c) {
+                rows.push(SidebarRow::Channel(c));
+            }
+        }
+        rows
+    }
+
+    fn toggle_section(&mut self, section: Section) {
+        if let Some(i) = self.collapsed.iter().position(|s| *s == section) {
+            self.collapsed.remove(i);
+        } else {
+            self.collapsed.push(section);
+        }
+    }
+
+    /// The next channel up or down the list which isn't in a collapsed section
+    fn step_channel(&self, forward: bool) -> usize {
+        let len = self.channels.len();
+        (1..len)
+            .map(|i| {
+                if forward {
+                    (self.current_channel + i) % len
+                } else {
+                    (self.current_channel + len - i) % len
+                }
+            })
+            .find(|c| !self.is_hidden(*c))
+            .unwrap_or(self.current_channel)
+    }
+}
+
+/// Adds `server` to `servers`, which are kept in order of name with the same one current, and
+/// returns where each of them is now. Servers are told apart by id, so two with the same name are
+/// both kept.
+fn insert_server(servers: &mut CursorVec<Server>, server: Server) -> HashMap<ServerId, usize> {
+    let previous_server = servers.get().id;
+    servers.push(server);
+    servers.sort_by_key(|s| s.name.clone());
+    while servers.get().id != previous_server {
+        servers.next();
+    }
+    servers.iter().enumerate().map(|(i, s)| (s.id, i)).collect()
+}
+
+/// Where a channel is, as its server's index in `servers` and its own index in that server's
+/// channels
+fn locate(
+    servers: &CursorVec<Server>,
+    indices: &HashMap<ServerId, usize>,
+    server: ServerId,
+    channel: &ChannelId,
+) -> Option<(usize, usize)> {
+    let s = *indices.get(&server)?;
+    let c = *servers[s].channel_indices.get(channel)?;
+    Some((s, c))
 }
 
 struct Channel {
     messages: Vec<ChanMessage>,
+    id: ChannelId,
     name: IString,
+    section: Section,
     read_at: DateTime,
     message_scroll_offset: usize,
     message_buffer: String,
+    /// The message that was clicked on, which reactions go to instead of the latest message
+    selected: Option<DateTime>,
+    /// A message to scroll to as soon as it's been loaded, such as a search result
+    scroll_to: Option<DateTime>,
 }
 
 impl Channel {
+    fn new(id: ChannelId, name: IString, section: Section) -> Self {
+        Channel {
+            messages: Vec::new(),
+            id,
+            name,
+            section,
+            read_at: DateTime::now(),
+            message_scroll_offset: 0,
+            message_buffer: String::new(),
+            selected: None,
+            scroll_to: None,
+        }
+    }
+
+    /// Scrolls `scroll_to` into the middle of the `height` rows the messages are drawn in, if it's
+    /// been loaded yet
+    fn scroll_to_target(
+        &mut self,
+        width: usize,
+        height: usize,
+        config: &DisplayConfig,
+        theme: &Theme,
+    ) {
+        let index = match self
+            .scroll_to
+            .and_then(|target| self.messages.iter().rposition(|m| *m.timestamp() == target))
+        {
+            Some(index) => index,
+            None => return,
+        };
+        let mut below = 0;
+        for message in &mut self.messages[index + 1..] {
+            below += message.formatted_to(width, config, theme).lines().count();
+        }
+        let own = self.messages[index]
+            .formatted_to(width, config, theme)
+            .lines()
+            .count();
+        self.message_scroll_offset = (below + own / 2).saturating_sub(height / 2);
+        self.scroll_to = None;
+    }
+
     fn num_unreads(&self) -> usize {
         self.messages
             .iter()
@@ -65,7 +469,12 @@ impl Channel {
 }
 
 impl Tui {
-    pub fn new() -> Self {
+    pub fn new(
+        config: DisplayConfig,
+        theme: Theme,
+        urls: UrlConfig,
+        downloads: DownloadConfig,
+    ) -> Self {
         use std::thread;
         use termion::input::TermRead;
         use termion::raw::IntoRawMode;
@@ -74,6 +483,11 @@ impl Tui {
         let rawguard = ::std::io::stdout()
             .into_raw_mode()
             .expect("Couldn't put the terminal in raw mode");
+        let mouseguard = if config.mouse {
+            Some(::termion::input::MouseTerminal::from(::std::io::stdout()))
+        } else {
+            None
+        };
 
         let (sender, reciever) = sync_channel(100);
 
@@ -104,6 +518,7 @@ impl Tui {
         // tab in other parts of the code
         let to_tui = sender.clone();
         let (to_client, from_client) = sync_channel(100);
+        let client_id = ServerId::unique();
         thread::spawn(move || {
             while let Ok(ev) = from_client.recv() {
                 if let TuiEvent::SendMessage {
@@ -111,10 +526,14 @@ impl Tui {
                 } = ev
                 {
                     let _ = to_tui.send(ConnEvent::Message(Message {
-                        server: "Client".into(),
+                        server: client_id,
                         channel,
                         sender: "You".into(),
                         contents,
+                        attachments: Vec::new(),
+                        actions: Vec::new(),
+                        files: Vec::new(),
+                        is_bot: false,
                         timestamp: DateTime::now(),
                         reactions: Vec::new(),
                     }));
@@ -123,23 +542,39 @@ impl Tui {
         });
 
         // Initialize with the Client's server which displays an error log
-        let client = Server {
-            channels: vec![Channel {
-                messages: Vec::new(),
-                name: "Errors".into(),
-                read_at: DateTime::now(),
-                message_scroll_offset: 0,
-                message_buffer: String::new(),
-            }],
+        let mut client = Server {
+            channels: vec![Channel::new(
+                "errors".into(),
+                "Errors".into(),
+                Section::Channels,
+            )],
+            channel_indices: HashMap::new(),
             completer: None,
             channel_scroll_offset: 0,
             current_channel: 0,
+            collapsed: Vec::new(),
+            id: client_id,
             name: "Client".into(),
             sender: to_client,
+            commands: &[],
         };
+        client.index_channels();
 
         Self {
+            sidebar_width: config.sidebar_width,
+            sidebar_auto_width: config.sidebar_auto_width,
+            sidebar_hidden: false,
+            config,
+            theme,
+            urls,
+            url_picker: None,
+            search: None,
+            find: None,
+            downloads,
+            transfers: Vec::new(),
+            status: None,
             servers: CursorVec::new(client),
+            server_indices: vec![(client_id, 0)].into_iter().collect(),
             longest_channel_name: 6, // "Client"
             shutdown: false,
             events: reciever,
@@ -147,10 +582,12 @@ impl Tui {
             server_scroll_offset: 0,
             autocompletions: Vec::new(),
             autocomplete_index: 0,
+            autocomplete_start: 0,
             cursor_pos: 0,
-            _guards: (screenguard, rawguard),
+            click_regions: Vec::new(),
+            _guards: (screenguard, rawguard, mouseguard),
             truncate_buffer_to: 0,
-            previous_terminal_height: 0,
+            previous_frame_size: (0, 0),
         }
     }
 
@@ -158,6 +595,47 @@ impl Tui {
         self.sender.clone()
     }
 
+    /// Columns taken up by the channel list including its border, or 0 if it's hidden. It's
+    /// hidden automatically when the terminal is too narrow for it.
+    fn sidebar_columns(&self, terminal_width: u16) -> u16 {
+        if self.sidebar_hidden {
+            return 0;
+        }
+        let width = if self.sidebar_auto_width {
+            self.longest_channel_name + 1
+        } else {
+            self.sidebar_width
+        };
+        let width = min(
+            max(width, MIN_SIDEBAR_WIDTH),
+            terminal_width.saturating_sub(MIN_MESSAGE_WIDTH),
+        );
+        if width < MIN_SIDEBAR_WIDTH {
+            0
+        } else {
+            width
+        }
+    }
+
+    fn resize_sidebar(&mut self, wider: bool) {
+        let (terminal_width, _) =
+            ::termion::terminal_size().expect("TUI couldn't get terminal dimensions");
+        let current = self.sidebar_columns(terminal_width);
+        self.sidebar_hidden = false;
+        self.sidebar_auto_width = false;
+        self.sidebar_width = if wider {
+            current + 1
+        } else {
+            max(current.saturating_sub(1), MIN_SIDEBAR_WIDTH)
+        };
+    }
+
+    fn toggle_current_section(&mut self) {
+        let server = self.servers.get_mut();
+        let section = server.channels[server.current_channel].section;
+        server.toggle_section(section);
+    }
+
     fn current_channel(&self) -> &Channel {
         let server = self.servers.get();
         &server.channels[server.current_channel]
@@ -168,6 +646,12 @@ impl Tui {
         &mut server.channels[server.current_channel]
     }
 
+    // The cursor is a byte offset into the message buffer, and must stay on a grapheme boundary
+    fn clamp_cursor(&mut self) {
+        self.cursor_pos =
+            ::width::snap_to_boundary(&self.current_channel().message_buffer, self.cursor_pos);
+    }
+
     fn reset_current_unreads(&mut self) {
         let server = self.servers.get_mut();
         if server.channels[server.current_channel].num_unreads() > 0 {
@@ -175,8 +659,8 @@ impl Tui {
             let current_channel = &server.channels[server.current_channel];
 
             let _ = server.sender.send(TuiEvent::MarkRead {
-                server: server.name.clone(),
-                channel: current_channel.name.clone(),
+                server: server.id,
+                channel: current_channel.id.clone(),
             });
         }
     }
@@ -184,13 +668,13 @@ impl Tui {
     fn next_server(&mut self) {
         self.reset_current_unreads();
         self.servers.next();
-        self.cursor_pos = min(self.cursor_pos, self.current_channel().message_buffer.len());
+        self.clamp_cursor();
     }
 
     fn previous_server(&mut self) {
         self.reset_current_unreads();
         self.servers.prev();
-        self.cursor_pos = min(self.cursor_pos, self.current_channel().message_buffer.len());
+        self.clamp_cursor();
     }
 
     fn next_channel_unread(&mut self) {
@@ -208,7 +692,7 @@ impl Tui {
                 self.servers.get_mut().current_channel = index;
             }
         }
-        self.cursor_pos = min(self.cursor_pos, self.current_channel().message_buffer.len());
+        self.clamp_cursor();
     }
 
     fn previous_channel_unread(&mut self) {
@@ -227,7 +711,7 @@ impl Tui {
                 self.servers.get_mut().current_channel = index;
             }
         }
-        self.cursor_pos = min(self.cursor_pos, self.current_channel().message_buffer.len());
+        self.clamp_cursor();
     }
 
     fn next_channel(&mut self) {
@@ -235,101 +719,143 @@ impl Tui {
         // NLL HACK
         {
             let server = self.servers.get_mut();
-            server.current_channel += 1;
-            if server.current_channel >= server.channels.len() {
-                server.current_channel = 0;
-            }
+            server.current_channel = server.step_channel(true);
         }
-        self.cursor_pos = min(self.cursor_pos, self.current_channel().message_buffer.len());
+        self.clamp_cursor();
     }
 
     fn previous_channel(&mut self) {
         self.reset_current_unreads();
         // NLL HACK
         {
-            let server = &mut self.servers.get_mut();
-            if server.current_channel > 0 {
-                server.current_channel -= 1;
-            } else {
-                server.current_channel = server.channels.len() - 1;
-            }
+            let server = self.servers.get_mut();
+            server.current_channel = server.step_channel(false);
         }
-        self.cursor_pos = min(self.cursor_pos, self.current_channel().message_buffer.len());
+        self.clamp_cursor();
     }
 
     // Take by value because we need to own the allocation
     fn add_client_message(&mut self, message: String) {
-        self.servers.get_first_mut().channels[0]
-            .messages
-            .push(ChanMessage::from(Message {
-                server: "Client".into(),
-                channel: "Errors".into(),
-                contents: message,
-                timestamp: ::chrono::Utc::now().into(),
-                sender: "Client".into(),
-                reactions: Vec::new(),
-            }));
+        let client = self.servers.get_first_mut();
+        let message = ChanMessage::from(Message {
+            server: client.id,
+            channel: client.channels[0].id.clone(),
+            contents: message,
+            attachments: Vec::new(),
+            actions: Vec::new(),
+            files: Vec::new(),
+            is_bot: false,
+            timestamp: ::chrono::Utc::now().into(),
+            sender: "Client".into(),
+            reactions: Vec::new(),
+        });
+        let messages = &mut client.channels[0].messages;
+        messages.push(message);
+        let last = messages.len() - 1;
+        chan_message::regroup(messages, last, &self.config);
     }
 
     pub fn add_server(
         &mut self,
+        id: ServerId,
         name: IString,
-        mut channels: Vec<IString>,
+        mut channels: Vec<ChannelInfo>,
         completer: Option<Box<Completer>>,
         sender: SyncSender<TuiEvent>,
+        commands: &'static [Command],
     ) {
-        channels.sort();
+        channels.sort_by(|a, b| (a.section, &a.name).cmp(&(b.section, &b.name)));
 
-        self.servers.push(Server {
+        let mut server = Server {
             channels: channels
                 .into_iter()
-                .map(|name| Channel {
-                    messages: Vec::new(),
-                    name,
-                    read_at: DateTime::now(),
-                    message_scroll_offset: 0,
-                    message_buffer: String::new(),
-                }).collect(),
+                .map(|ChannelInfo { id, name, section }| Channel::new(id, name, section))
+                .collect(),
+            channel_indices: HashMap::new(),
+            id,
             name,
             completer,
             current_channel: 0,
             channel_scroll_offset: 0,
+            collapsed: Vec::new(),
             sender,
-        });
+            commands,
+        };
+        server.index_channels();
+        self.server_indices = insert_server(&mut self.servers, server);
+        self.update_longest_channel_name();
+    }
+
+    /// Where the channel an event is about is
+    fn locate(&self, server: ServerId, channel: &ChannelId) -> Option<(usize, usize)> {
+        locate(&self.servers, &self.server_indices, server, channel)
+    }
 
+    fn server_mut(&mut self, id: ServerId) -> Option<&mut Server> {
+        let index = *self.server_indices.get(&id)?;
+        Some(&mut self.servers[index])
+    }
+
+    fn update_longest_channel_name(&mut self) {
         self.longest_channel_name = self
             .servers
             .iter()
-            .flat_map(|s| s.channels.iter().map(|c| c.name.len()))
+            .flat_map(|s| s.channels.iter().map(|c| ::width::str_width(&c.name)))
             .max()
             .unwrap_or(0) as u16
             + 1;
+    }
+
+    fn add_channel(&mut self, server: ServerId, channel: ChannelInfo) {
+        let server = match self.server_mut(server) {
+            Some(s) => s,
+            None => {
+                error!("Can't add channel {}, no server {:?}", channel.name, server);
+                return;
+            }
+        };
+        if server.channel_indices.contains_key(&channel.id) {
+            return;
+        }
+        server
+            .channels
+            .push(Channel::new(channel.id, channel.name, channel.section));
+        server.sort_channels();
+        self.update_longest_channel_name();
+    }
+
+    fn show_channel(&mut self, server: ServerId, channel: &ChannelId) {
+        let (server_index, channel_index) = match self.locate(server, channel) {
+            Some(found) => found,
+            None => return,
+        };
+        self.reset_current_unreads();
+        self.servers.seek(server_index);
+        self.servers.get_mut().current_channel = channel_index;
+        self.clamp_cursor();
+    }
+
+    fn remove_channel(&mut self, server: ServerId, channel: &ChannelId) {
+        if let Some(server) = self.server_mut(server) {
+            server.remove_channel(channel);
+        }
+        self.update_longest_channel_name();
+        self.clamp_cursor();
+    }
 
-        let previous_server_name = self.servers.get().name.clone();
-        self.servers.sort_by_key(|s| s.name.clone());
-        while self.servers.get().name != previous_server_name {
-            self.servers.next();
+    fn rename_channel(&mut self, server: ServerId, channel: &ChannelId, name: IString) {
+        if let Some(server) = self.server_mut(server) {
+            server.rename_channel(channel, name);
         }
+        self.update_longest_channel_name();
     }
 
     fn add_message(&mut self, message: Message) {
-        let channel = match self
-            .servers
-            .iter_mut()
-            .find(|s| s.name == message.server)
-            .or_else(|| {
-                error!("Unable to add message, no server named {}", message.server);
-                None
-            }).and_then(|server| {
-                server
-                    .channels
-                    .iter_mut()
-                    .find(|c| c.name == message.channel)
-            }) {
-            Some(c) => c,
+        let channel = match self.locate(message.server, &message.channel) {
+            Some((s, c)) => &mut self.servers[s].channels[c],
             None => {
                 error!(
-                    "Unable to add message, no channel named {} in server {}",
+                    "Unable to add message, no channel {} in server {:?}",
                     message.channel, message.server
                 );
                 return;
@@ -345,167 +871,479 @@ impl Tui {
 
         channel.messages.push(message.into());
 
-        if needs_sort {
+        let regroup_from = if needs_sort {
             channel
                 .messages
                 .sort_unstable_by(|m1, m2| m1.timestamp().cmp(&m2.timestamp()));
-        }
+            0
+        } else {
+            channel.messages.len() - 1
+        };
+        chan_message::regroup(&mut channel.messages, regroup_from, &self.config);
     }
 
     fn send_message(&mut self) {
         let contents = self.current_channel().message_buffer.clone();
+        if let Some((name, args)) = ::commands::split(&contents) {
+            self.run_command(name, args);
+            return;
+        }
         if self.servers.tell() == 0 {
             self.add_client_message(contents);
             return;
         }
-        let current_server_name = self.servers.get().name.clone();
-        let current_channel_name = self.current_channel().name.clone();
+        let current_server = self.servers.get().id;
+        let current_channel = self.current_channel().id.clone();
         if contents.starts_with("+:") {
-            if let Some(ts) = self
-                .current_channel()
-                .messages
-                .last()
-                .map(|m| *m.timestamp())
+            let channel = self.current_channel();
+            if let Some(ts) = channel
+                .selected
+                .or_else(|| channel.messages.last().map(|m| *m.timestamp()))
             {
                 let reaction = &contents[2..contents.len() - 1];
                 let _ = self.servers.get().sender.send(TuiEvent::AddReaction {
                     reaction: reaction.into(),
-                    server: current_server_name,
-                    channel: current_channel_name,
+                    server: current_server,
+                    channel: current_channel,
                     timestamp: ts,
                 });
             } else {
-                self.add_client_message(
+                self.notice(
                     "Can't react to most recent message if there are no messages in this channel!"
                         .to_string(),
                 );
             }
-        // The /url command searches for a URL mentioned in the current channel and
-        // copies it to the clipboard if one is found
-        } else if contents == "/url" {
-            use std::io::Write;
-            use std::process::{Command, Stdio};
-            self.current_channel()
-                .messages
-                .iter()
-                .rev()
-                .filter_map(|message| URL_REGEX.find(&message.raw))
-                .next()
-                .map(|url| {
-                    let _ = Command::new("xclip")
-                        .arg("-selection")
-                        .arg("clipboard")
-                        .stdin(Stdio::piped())
-                        .spawn()
-                        .and_then(|mut child| {
-                            child
-                                .stdin
-                                .as_mut()
-                                .unwrap()
-                                .write_all(url.as_str().as_bytes())
-                        }).map_err(|e| error!("{:#?}", e));
-                });
-        } else if contents.starts_with('/') {
-            let _ = self.servers.get_mut().sender.send(TuiEvent::Command {
-                server: current_server_name,
-                channel: current_channel_name,
-                command: IString::from(&contents[1..]),
-            });
         } else {
             let _ = self.servers.get_mut().sender.send(TuiEvent::SendMessage {
-                server: current_server_name,
-                channel: current_channel_name,
+                server: current_server,
+                channel: current_channel,
                 contents,
             });
         }
     }
 
+    /// The client's commands and the current server's
+    fn commands(&self) -> impl Iterator<Item = &Command> {
+        CLIENT_COMMANDS.iter().chain(self.servers.get().commands)
+    }
+
+    fn run_command(&mut self, name: &str, args: &str) {
+        let found = self.commands().find(|c| c.name == name).cloned();
+        let command = match found {
+            Some(command) => command,
+            None => {
+                self.notice(format!(
+                    "Unknown command /{}, /help lists the commands",
+                    name
+                ));
+                return;
+            }
+        };
+
+        if command.handler == Handler::Backend {
+            let server = self.servers.get();
+            let _ = server.sender.send(TuiEvent::Command {
+                server: server.id,
+                channel: self.current_channel().id.clone(),
+                command: IString::from(format!("{}{}", name, args)),
+            });
+            return;
+        }
+
+        match command.name {
+            "help" => {
+                let text = ::commands::help(self.commands(), args).unwrap_or_else(|e| e);
+                self.notice(text);
+            }
+            // /url lists every URL in the current channel to be opened, copied or downloaded
+            "url" => {
+                let urls = ::urls::collect(&self.current_channel().messages);
+                if urls.is_empty() {
+                    self.notice(format!("No URLs in {}", self.current_channel().name));
+                } else {
+                    self.url_picker = Some(UrlPicker { urls, selected: 0 });
+                }
+            }
+            "find" => self.find(args.trim()),
+            "download" => {
+                let urls: Vec<String> = args.split_whitespace().map(String::from).collect();
+                if urls.is_empty() {
+                    self.download_message();
+                } else {
+                    self.download(urls);
+                }
+            }
+            "upload" => self.upload(args),
+            _ => error!("/{} is listed as a client command but isn't handled", name),
+        }
+    }
+
+    /// Completions for the arguments of a command, the last of which is `argument`
+    fn complete_command(&self, name: &str, argument: &str) -> Vec<String> {
+        let command = match self.commands().find(|c| c.name == name) {
+            Some(command) => command,
+            None => return Vec::new(),
+        };
+        match (command.handler, command.name) {
+            (Handler::Backend, _) => self
+                .servers
+                .get()
+                .completer
+                .as_ref()
+                .map(|c| c.complete_argument(name, argument))
+                .unwrap_or_default(),
+            (Handler::Client, "help") => ::commands::complete_name(self.commands(), argument),
+            (Handler::Client, "upload") => ::upload::complete_path(argument),
+            (Handler::Client, "download") => ::urls::collect(&self.current_channel().messages)
+                .into_iter()
+                .filter(|url| url.starts_with(argument))
+                .collect(),
+            (Handler::Client, _) => Vec::new(),
+        }
+    }
+
+    /// Shows `text` in the current channel, for things like why a command didn't work
+    fn notice(&mut self, text: String) {
+        let message = Message {
+            server: self.servers.get().id,
+            channel: self.current_channel().id.clone(),
+            sender: "Client".into(),
+            contents: text,
+            attachments: Vec::new(),
+            actions: Vec::new(),
+            files: Vec::new(),
+            is_bot: false,
+            timestamp: DateTime::now(),
+            reactions: Vec::new(),
+        };
+        self.add_message(message);
+    }
+
+    /// Downloads the files posted with the selected message, or with the newest message that has
+    /// any. Other links could be any web page, so they're only saved when asked for by URL.
+    fn download_message(&mut self) {
+        let urls = {
+            let channel = self.current_channel();
+            let message = match channel.selected {
+                Some(ts) => channel.messages.iter().rev().find(|m| *m.timestamp() == ts),
+                None => channel
+                    .messages
+                    .iter()
+                    .rev()
+                    .find(|m| !m.files().is_empty()),
+            };
+            message.map_or_else(Vec::new, |m| m.files().to_vec())
+        };
+        if urls.is_empty() {
+            self.notice(format!(
+                "No files to download in {}",
+                self.current_channel().name
+            ));
+        } else {
+            self.download(urls);
+        }
+    }
+
+    fn download(&mut self, urls: Vec<String>) {
+        let server = self.servers.get();
+        for url in urls {
+            let _ = server.sender.send(TuiEvent::Download {
+                server: server.id,
+                url,
+                directory: self.downloads.directory.clone(),
+            });
+        }
+    }
+
+    fn upload(&mut self, args: &str) {
+        let upload = match ::upload::parse(args) {
+            Ok(upload) => upload,
+            Err(e) => {
+                self.notice(e);
+                return;
+            }
+        };
+        if let Some(missing) = upload.files.iter().find(|f| !f.is_file()) {
+            self.notice(format!("{} isn't a file", missing.display()));
+            return;
+        }
+        let thread = match (upload.thread, self.current_channel().selected) {
+            (true, None) => {
+                self.notice(String::from(
+                    "Click on a message first to upload into its thread",
+                ));
+                return;
+            }
+            (true, selected) => selected,
+            (false, _) => None,
+        };
+        let server = self.servers.get();
+        let _ = server.sender.send(TuiEvent::Upload {
+            server: server.id,
+            channel: self.current_channel().id.clone(),
+            files: upload.files,
+            title: upload.title,
+            comment: upload.comment,
+            thread,
+        });
+    }
+
+    fn update_transfer(
+        &mut self,
+        server: ServerId,
+        id: String,
+        direction: Direction,
+        status: TransferStatus,
+    ) {
+        let position = self
+            .transfers
+            .iter()
+            .position(|t| t.server == server && t.id == id && t.direction == direction);
+        match status {
+            TransferStatus::Progress { file, done, total } => match position {
+                Some(i) => self.transfers[i].done = done,
+                None => self.transfers.push(Transfer {
+                    server,
+                    id,
+                    direction,
+                    file,
+                    done,
+                    total,
+                }),
+            },
+            TransferStatus::Finished(path) => {
+                if let Some(i) = position {
+                    self.transfers.remove(i);
+                }
+                match direction {
+                    Direction::Download => {
+                        self.status = Some(format!("Saved {}", path.display()));
+                        if self.downloads.open {
+                            if let Err(e) = self.urls.open(&path.to_string_lossy()) {
+                                error!("{}", e);
+                            }
+                        }
+                    }
+                    Direction::Upload => {
+                        self.status = Some(format!("Uploaded {}", path.display()));
+                    }
+                }
+            }
+            TransferStatus::Failed(e) => {
+                if let Some(i) = position {
+                    self.transfers.remove(i);
+                }
+                let message = match direction {
+                    Direction::Download => format!("Couldn't download {}: {}", id, e),
+                    Direction::Upload => format!("Couldn't upload {}: {}", id, e),
+                };
+                error!("{}", message);
+                self.status = Some(message);
+            }
+        }
+    }
+
+    fn status_line(&self) -> Option<String> {
+        if self.transfers.is_empty() {
+            return self.status.clone();
+        }
+        let transfers: Vec<String> = self
+            .transfers
+            .iter()
+            .map(|t| {
+                let verb = match t.direction {
+                    Direction::Download => "Downloading",
+                    Direction::Upload => "Uploading",
+                };
+                match t.total {
+                    Some(total) if total > 0 => format!(
+                        "{} {} {}% of {}",
+                        verb,
+                        t.file,
+                        t.done * 100 / total,
+                        ::download::human_size(total)
+                    ),
+                    _ => format!("{} {} {}", verb, t.file, ::download::human_size(t.done)),
+                }
+            })
+            .collect();
+        Some(transfers.join(", "))
+    }
+
     fn draw(&mut self, render_buffer: &mut String) {
         use std::fmt::Write;
-        use termion::color::Fg;
         use termion::cursor::Goto;
-        use termion::{color, style};
 
         let (terminal_width, terminal_height) =
             ::termion::terminal_size().expect("TUI draw couldn't get terminal dimensions");
 
-        if terminal_height != self.previous_terminal_height {
+        let sidebar = self.sidebar_columns(terminal_width);
+        self.click_regions.clear();
+
+        if (terminal_height, sidebar) != self.previous_frame_size {
             render_buffer.clear();
             let _ = write!(render_buffer, "{}", ::termion::clear::All);
 
-            for i in 1..=terminal_height {
-                let _ = write!(render_buffer, "{}|", Goto(CHAN_WIDTH, i));
+            if sidebar > 0 {
+                for i in 1..=terminal_height {
+                    let _ = write!(
+                        render_buffer,
+                        "{}{}|{}",
+                        Goto(sidebar, i),
+                        self.theme.border,
+                        RESET
+                    );
+                }
             }
             self.truncate_buffer_to = render_buffer.len();
-            self.previous_terminal_height = terminal_height;
+            self.previous_frame_size = (terminal_height, sidebar);
         } else {
             render_buffer.truncate(self.truncate_buffer_to);
         }
 
-        let remaining_width = (terminal_width - CHAN_WIDTH) as usize;
+        let remaining_width = (terminal_width - sidebar) as usize;
+
+        // Scroll the server bar so the current server is on it
+        let bar_rows = if self.config.wrap_server_names { 2 } else { 1 };
+        let server_names: Vec<&str> = self.servers.iter().map(|s| s.name.as_ref()).collect();
+        self.server_scroll_offset = scroll_server_bar(
+            &server_names,
+            self.server_scroll_offset,
+            self.servers.tell(),
+            remaining_width,
+            bar_rows,
+        );
+        let bar = layout_server_bar(
+            &server_names,
+            self.server_scroll_offset,
+            remaining_width,
+            bar_rows,
+        );
+        let bar_height = bar.last().map_or(1, |e| e.row as u16 + 1);
 
         // Draw the message input area
         // We need this message area height to render the channel messages
         // More NLL hacking
-        let total_chars = self.current_channel().message_buffer.chars().count();
-        let rows = (total_chars / remaining_width) + 1;
-        for row in (0..rows).rev() {
+        let input_rows =
+            ::width::split_rows(&self.current_channel().message_buffer, remaining_width);
+        let rows = input_rows.len();
+        for (row, range) in input_rows.iter().enumerate() {
             let _ = write!(
                 render_buffer,
-                "{}",
-                Goto(CHAN_WIDTH + 1, terminal_height - (rows - row - 1) as u16)
-            );
-            render_buffer.extend(
-                self.current_channel()
-                    .message_buffer
-                    .chars()
-                    .skip(remaining_width * row)
-                    .take(remaining_width),
+                "{}{}{}{}",
+                Goto(sidebar + 1, terminal_height - (rows - row - 1) as u16),
+                self.theme.input,
+                &self.current_channel().message_buffer[range.clone()],
+                RESET
             );
         }
-        let message_area_height = terminal_height - rows as u16 + 1;
+        let status_rows = match self.status_line() {
+            Some(status) => {
+                let _ = write!(
+                    render_buffer,
+                    "{}{}{}{}",
+                    Goto(sidebar + 1, terminal_height - rows as u16),
+                    self.theme.inactive,
+                    ::width::shorten(&status, remaining_width),
+                    RESET
+                );
+                1
+            }
+            None => 0,
+        };
+        let message_area_height = terminal_height - rows as u16 - status_rows + 1;
 
         // Draw all the messages by looping over them in reverse
         let num_unreads = self.current_channel().num_unreads();
         let mut draw_unread_marker = num_unreads > 0;
 
+        let message_rows = message_area_height.saturating_sub(bar_height + 1) as usize;
+        {
+            let found = self.find.as_ref().map(|find| &find.regex);
+            let server = self.servers.get_mut();
+            for message in &mut server.channels[server.current_channel].messages {
+                message.highlight(found);
+            }
+            server.channels[server.current_channel].scroll_to_target(
+                remaining_width,
+                message_rows,
+                &self.config,
+                &self.theme,
+            );
+        }
         let offset = self.current_channel().message_scroll_offset;
 
         let mut row = message_area_height - 1;
         let mut skipped = 0;
-        'outer: for (m, message) in self
-            .current_channel_mut()
-            .messages
-            .iter_mut()
-            .rev()
-            .enumerate()
-        {
+        let config = &self.config;
+        let theme = &self.theme;
+        let click_regions = &mut self.click_regions;
+        let server = self.servers.get_mut();
+        let channel = &mut server.channels[server.current_channel];
+        let selected = channel.selected;
+        // Resets inside a highlighted line have to turn the highlight back on
+        let highlight_reset = format!("{}{}", RESET, theme.highlight);
+        let message_columns = sidebar + 1..terminal_width + 1;
+        'outer: for (m, message) in channel.messages.iter_mut().rev().enumerate() {
             // Unread marker
             if (draw_unread_marker) && (m == num_unreads) {
-                let _ = write!(
-                    render_buffer,
-                    "{}{}",
-                    Goto(CHAN_WIDTH + 1, row),
-                    Fg(color::Red)
-                );
+                let _ = write!(render_buffer, "{}{}", Goto(sidebar + 1, row), theme.unread);
                 render_buffer.extend(::std::iter::repeat('-').take(remaining_width));
-                let _ = write!(render_buffer, "{}", Fg(color::Reset));
+                render_buffer.push_str(RESET);
                 row -= 1;
                 draw_unread_marker = false;
-                if row == 1 {
+                if row == bar_height {
                     break 'outer;
                 }
             }
 
-            for line in message.formatted_to(remaining_width).lines().rev() {
+            message.formatted_to(remaining_width, config, theme);
+            let message = &*message;
+            let timestamp = *message.timestamp();
+            let lines: Vec<&str> = message.formatted().lines().collect();
+            for (l, line) in lines.iter().enumerate().rev() {
                 if skipped < offset {
                     skipped += 1;
                     continue;
                 }
-                let _ = write!(render_buffer, "{}", Goto(CHAN_WIDTH + 1, row));
-                render_buffer.push_str(line);
+                let _ = write!(render_buffer, "{}", Goto(sidebar + 1, row));
+                if selected == Some(timestamp) {
+                    let _ = write!(
+                        render_buffer,
+                        "{}{}{}{}",
+                        theme.highlight,
+                        line.replace(RESET, &highlight_reset),
+                        " ".repeat(remaining_width.saturating_sub(::width::str_width(line))),
+                        RESET
+                    );
+                } else {
+                    render_buffer.push_str(line);
+                }
+
+                click_regions.push(ClickRegion {
+                    row,
+                    columns: message_columns.clone(),
+                    target: ClickTarget::Message(timestamp),
+                });
+                for span in message.spans().iter().filter(|s| s.line == l) {
+                    let target = match span.target {
+                        SpanTarget::Reaction(ref reaction) => ClickTarget::Reaction {
+                            timestamp,
+                            reaction: reaction.clone(),
+                        },
+                        SpanTarget::Url(ref url) => ClickTarget::Url(url.clone()),
+                        SpanTarget::Action(index) => ClickTarget::Action { timestamp, index },
+                    };
+                    click_regions.push(ClickRegion {
+                        row,
+                        columns: sidebar + 1 + span.columns.start as u16
+                            ..sidebar + 1 + span.columns.end as u16,
+                        target,
+                    });
+                }
+
                 row -= 1;
-                if row == 1 {
+                if row == bar_height {
                     break 'outer;
                 }
             }
@@ -513,119 +1351,251 @@ impl Tui {
 
         // If we didn't draw the unread marker, put it at the top of the screen
         if draw_unread_marker {
-            let _ = write!(render_buffer, "{}", Goto(CHAN_WIDTH + 1, max(2, row)));
-            let _ = write!(render_buffer, "{}", Fg(color::Red));
+            let _ = write!(
+                render_buffer,
+                "{}",
+                Goto(sidebar + 1, max(bar_height + 1, row))
+            );
+            let _ = write!(render_buffer, "{}", self.theme.unread);
             render_buffer.extend(::std::iter::repeat('-').take(remaining_width));
-            let _ = write!(render_buffer, "{}", Fg(color::Reset));
+            render_buffer.push_str(RESET);
         }
 
-        // Draw all the server names across the top
-        let _ = write!(render_buffer, "{}", Goto(CHAN_WIDTH + 1, 1)); // Move to the top-right corner
-        let num_servers = self.servers.len();
-        for (s, server) in self
-            .servers
-            .iter()
-            .enumerate()
-            .skip(self.server_scroll_offset)
-        {
-            if s == self.servers.tell() {
+        // Lists like the URL picker cover the messages, so they can't be clicked on
+        if let Some(list) = self.overlay() {
+            self.click_regions.retain(|r| {
+                !matches!(
+                    r.target,
+                    ClickTarget::Message(_)
+                        | ClickTarget::Reaction { .. }
+                        | ClickTarget::Url(_)
+                        | ClickTarget::Action { .. }
+                )
+            });
+
+            let top = bar_height + 1;
+            let height = message_area_height.saturating_sub(top) as usize;
+            let _ = write!(
+                render_buffer,
+                "{}{}{}{}{}",
+                Goto(sidebar + 1, top),
+                ::termion::clear::UntilNewline,
+                self.theme.date,
+                ::width::shorten(&list.title, remaining_width),
+                RESET
+            );
+            let visible = height.saturating_sub(1);
+            let scroll = list.selected.map_or(0, |s| s + 1).saturating_sub(visible);
+            for i in 0..visible {
+                let row = top + 1 + i as u16;
                 let _ = write!(
                     render_buffer,
-                    "{}{}{}",
-                    style::Bold,
-                    server.name,
-                    style::Reset
+                    "{}{}",
+                    Goto(sidebar + 1, row),
+                    ::termion::clear::UntilNewline
                 );
-            } else if server.has_unreads() {
+                let (text, target) = match list.rows.get(scroll + i) {
+                    Some(row) => row,
+                    None => continue,
+                };
+                let style = if Some(scroll + i) == list.selected {
+                    &self.theme.highlight
+                } else {
+                    &self.theme.text
+                };
                 let _ = write!(
                     render_buffer,
                     "{}{}{}",
-                    Fg(color::Red),
-                    server.name,
-                    Fg(color::Reset),
+                    style,
+                    ::width::shorten(text, remaining_width),
+                    RESET
                 );
+                self.click_regions.push(ClickRegion {
+                    row,
+                    columns: sidebar + 1..terminal_width + 1,
+                    target: target.clone(),
+                });
+            }
+        }
+
+        // Draw all the server names across the top, with markers for any that are scrolled out of
+        // view which are highlighted if those servers have unread messages
+        let theme = &self.theme;
+        let hidden_style = |unreads: bool| {
+            if unreads {
+                &theme.unread
             } else {
+                &theme.inactive
+            }
+        };
+        let hidden_before = self.server_scroll_offset;
+        if hidden_before > 0 {
+            self.click_regions.push(ClickRegion {
+                row: 1,
+                columns: sidebar + 1..sidebar + 1 + overflow_width(hidden_before) as u16,
+                target: ClickTarget::Server(hidden_before - 1),
+            });
+            let unreads = self
+                .servers
+                .iter()
+                .take(hidden_before)
+                .any(|s| s.has_unreads());
+            let _ = write!(
+                render_buffer,
+                "{}{}<{} {}",
+                Goto(sidebar + 1, 1),
+                hidden_style(unreads),
+                hidden_before,
+                RESET
+            );
+        }
+        let mut previous: Option<&BarEntry> = None;
+        for (server, entry) in self.servers.iter().skip(hidden_before).zip(&bar) {
+            if let Some(previous) = previous.filter(|p| p.row == entry.row) {
+                let end = previous.column + ::width::str_width(&previous.name);
                 let _ = write!(
                     render_buffer,
-                    "{}{}{}",
-                    Fg(color::AnsiValue::rgb(3, 3, 3)),
-                    server.name,
-                    Fg(color::Reset),
+                    "{}{}",
+                    Goto(sidebar + 1 + end as u16, entry.row as u16 + 1),
+                    SERVER_SEPARATOR
                 );
             }
+            let style = if entry.server == self.servers.tell() {
+                &self.theme.selected
+            } else if server.has_unreads() {
+                &self.theme.unread
+            } else {
+                &self.theme.inactive
+            };
             let _ = write!(
                 render_buffer,
-                "{}",
-                if s == num_servers - 1 { "" } else { " • " }
+                "{}{}{}{}",
+                Goto(sidebar + 1 + entry.column as u16, entry.row as u16 + 1),
+                style,
+                entry.name,
+                RESET
+            );
+            let start = sidebar + 1 + entry.column as u16;
+            self.click_regions.push(ClickRegion {
+                row: entry.row as u16 + 1,
+                columns: start..start + ::width::str_width(&entry.name) as u16,
+                target: ClickTarget::Server(entry.server),
+            });
+            previous = Some(entry);
+        }
+        let shown_through = bar.last().map_or(hidden_before, |e| e.server + 1);
+        let hidden_after = self.servers.len() - shown_through;
+        if hidden_after > 0 {
+            let start =
+                sidebar + 1 + remaining_width.saturating_sub(overflow_width(hidden_after)) as u16;
+            self.click_regions.push(ClickRegion {
+                row: bar_height,
+                columns: start..terminal_width + 1,
+                target: ClickTarget::Server(shown_through),
+            });
+            let unreads = self
+                .servers
+                .iter()
+                .skip(shown_through)
+                .any(|s| s.has_unreads());
+            let _ = write!(
+                render_buffer,
+                "{}{} {}>{}",
+                Goto(start, bar_height),
+                hidden_style(unreads),
+                hidden_after,
+                RESET
             );
         }
 
-        {
+        if sidebar > 0 {
             // Draw all the channels for the current server down the left side
             let server = self.servers.get_mut();
+            let sidebar_rows = server.sidebar_rows();
             {
                 let height = terminal_height as usize;
-                if server.current_channel + 1 > height + server.channel_scroll_offset {
-                    server.channel_scroll_offset = server.current_channel - height + 1
-                } else if server.current_channel < server.channel_scroll_offset {
-                    server.channel_scroll_offset = server.current_channel;
-                }
-            }
-
-            fn write_shortened_name(f: &mut String, name: &str, max_len: usize) {
-                if name.chars().count() < max_len {
-                    let _ = write!(f, "{}", name);
-                } else {
-                    f.extend(name.chars().take(max_len - 4).chain("...".chars()));
+                let current_row = sidebar_rows
+                    .iter()
+                    .position(|r| *r == SidebarRow::Channel(server.current_channel))
+                    .unwrap_or(0);
+                if current_row + 1 > height + server.channel_scroll_offset {
+                    server.channel_scroll_offset = current_row - height + 1
+                } else if current_row < server.channel_scroll_offset {
+                    server.channel_scroll_offset = current_row;
                 }
             }
 
-            for (c, channel) in server
-                .channels
-                .iter_mut()
+            // The last column is taken up by the separator
+            let max_len = sidebar as usize - 1;
+            for (r, sidebar_row) in sidebar_rows
+                .iter()
                 .enumerate()
                 .skip(server.channel_scroll_offset)
                 .take(terminal_height as usize)
             {
-                if c == server.current_channel {
-                    let _ = write!(
-                        render_buffer,
-                        "{}{}",
-                        Goto(1, (c - server.channel_scroll_offset) as u16 + 1),
-                        style::Bold
-                    );
-                    write_shortened_name(render_buffer, &channel.name, CHAN_WIDTH as usize);
-                    let _ = write!(render_buffer, "{}", style::Reset);
-                } else if channel.num_unreads() > 0 {
-                    let _ = write!(
-                        render_buffer,
-                        "{}{}",
-                        Goto(1, (c - server.channel_scroll_offset) as u16 + 1),
-                        Fg(color::Red)
-                    );
-                    write_shortened_name(render_buffer, &channel.name, CHAN_WIDTH as usize);
-                    let _ = write!(render_buffer, "{}", style::Reset);
-                } else {
-                    let gray = color::AnsiValue::rgb(3, 3, 3);
-                    let _ = write!(
-                        render_buffer,
-                        "{}{}",
-                        Goto(1, (c - server.channel_scroll_offset) as u16 + 1),
-                        Fg(gray)
-                    );
-                    write_shortened_name(render_buffer, &channel.name, CHAN_WIDTH as usize);
-                    let _ = write!(render_buffer, "{}", style::Reset);
-                }
+                let (style, text) = match *sidebar_row {
+                    SidebarRow::Header(section) => {
+                        let collapsed = server.collapsed.contains(&section);
+                        let hidden_unreads = collapsed
+                            && server.channels.iter().enumerate().any(|(c, chan)| {
+                                chan.section == section
+                                    && server.is_hidden(c)
+                                    && chan.num_unreads() > 0
+                            });
+                        let style = if hidden_unreads {
+                            &self.theme.unread
+                        } else {
+                            &self.theme.inactive
+                        };
+                        let arrow = if collapsed { '▸' } else { '▾' };
+                        (style, format!("{} {}", arrow, section.title()))
+                    }
+                    SidebarRow::Channel(c) => {
+                        let channel = &server.channels[c];
+                        let style = if c == server.current_channel {
+                            &self.theme.selected
+                        } else if channel.num_unreads() > 0 {
+                            &self.theme.unread
+                        } else {
+                            &self.theme.inactive
+                        };
+                        (style, channel.name.to_string())
+                    }
+                };
+                let screen_row = (r - server.channel_scroll_offset) as u16 + 1;
+                let _ = write!(
+                    render_buffer,
+                    "{}{}{}{}",
+                    Goto(1, screen_row),
+                    style,
+                    ::width::shorten(&text, max_len),
+                    RESET
+                );
+                self.click_regions.push(ClickRegion {
+                    row: screen_row,
+                    columns: 1..sidebar,
+                    target: match *sidebar_row {
+                        SidebarRow::Header(section) => ClickTarget::Section(section),
+                        SidebarRow::Channel(c) => ClickTarget::Channel(c),
+                    },
+                });
             }
         }
 
+        // Place the cursor in the row that contains it, after everything before it in that row
+        let cursor_row = input_rows
+            .iter()
+            .rposition(|r| r.start <= self.cursor_pos)
+            .unwrap_or(0);
+        let cursor_col = ::width::str_width(
+            &self.current_channel().message_buffer[input_rows[cursor_row].start..self.cursor_pos],
+        );
         let _ = write!(
             render_buffer,
             "{}",
             Goto(
-                CHAN_WIDTH + 1 + (self.cursor_pos % remaining_width) as u16,
-                terminal_height - (total_chars / remaining_width) as u16
-                    + (self.cursor_pos / remaining_width) as u16
+                sidebar + 1 + cursor_col as u16,
+                terminal_height - (rows - cursor_row - 1) as u16
             )
         );
         {
@@ -638,11 +1608,512 @@ impl Tui {
         }
     }
 
+    fn scroll_messages(&mut self, up: bool) {
+        let chan = self.current_channel_mut();
+        if up {
+            chan.message_scroll_offset += 1;
+        } else {
+            chan.message_scroll_offset = chan.message_scroll_offset.saturating_sub(1);
+        }
+        chan.scroll_to = None;
+    }
+
+    /// Does whatever was drawn at column `x` and row `y` the last time the screen was drawn.
+    /// A left click opens URLs and a right click copies them.
+    fn click(&mut self, left: bool, x: u16, y: u16) {
+        let target = match self
+            .click_regions
+            .iter()
+            .rev()
+            .find(|r| r.row == y && r.columns.contains(&x))
+        {
+            Some(region) => region.target.clone(),
+            None => return,
+        };
+
+        match target {
+            ClickTarget::Server(index) => {
+                self.reset_current_unreads();
+                self.servers.seek(index);
+                self.clamp_cursor();
+            }
+            ClickTarget::Section(section) => self.servers.get_mut().toggle_section(section),
+            ClickTarget::Channel(index) => {
+                self.reset_current_unreads();
+                self.servers.get_mut().current_channel = index;
+                self.clamp_cursor();
+            }
+            ClickTarget::Message(timestamp) => {
+                let chan = self.current_channel_mut();
+                chan.selected = if chan.selected == Some(timestamp) {
+                    None
+                } else {
+                    Some(timestamp)
+                };
+            }
+            ClickTarget::Reaction {
+                timestamp,
+                reaction,
+            } => {
+                let server = self.servers.get();
+                let _ = server.sender.send(TuiEvent::ToggleReaction {
+                    server: server.id,
+                    channel: self.current_channel().id.clone(),
+                    reaction,
+                    timestamp,
+                });
+            }
+            ClickTarget::Url(url) => {
+                self.url_picker = None;
+                self.open_or_copy(&url, left);
+            }
+            ClickTarget::Action { timestamp, index } => {
+                let action = self
+                    .current_channel()
+                    .messages
+                    .iter()
+                    .rev()
+                    .find(|m| *m.timestamp() == timestamp)
+                    .and_then(|m| m.actions().get(index))
+                    .cloned();
+                if let Some(action) = action {
+                    if let Some(ref url) = action.url {
+                        self.open_or_copy(url, true);
+                    }
+                    let server = self.servers.get();
+                    let _ = server.sender.send(TuiEvent::TakeAction {
+                        server: server.id,
+                        channel: self.current_channel().id.clone(),
+                        timestamp,
+                        action,
+                    });
+                }
+            }
+            ClickTarget::SearchHit(index) => {
+                if let Some(ref mut results) = self.search {
+                    results.selected = index;
+                }
+                self.jump_to_search_hit();
+            }
+            ClickTarget::FindHit(index) => {
+                if let Some(ref mut find) = self.find {
+                    find.selected = index;
+                }
+                self.jump_to_find_hit();
+            }
+        }
+    }
+
+    /// Opens or copies `url`, saying why in the status line if that didn't work
+    fn open_or_copy(&mut self, url: &str, open: bool) {
+        let result = if open {
+            self.urls.open(url)
+        } else {
+            self.urls.copy(url)
+        };
+        if let Err(e) = result {
+            self.status = Some(e);
+        }
+    }
+
+    /// Keys go to the URL picker while it's open. Returns false for anything it doesn't handle.
+    fn handle_picker_input(&mut self, event: &::termion::event::Event) -> bool {
+        use termion::event::Event::Key;
+        use termion::event::Key::*;
+
+        let (len, selected) = match self.url_picker {
+            Some(ref picker) => (picker.urls.len(), picker.selected),
+            None => return false,
+        };
+        let key = match *event {
+            Key(key) => key,
+            _ => return false,
+        };
+        match key {
+            Up | Char('k') => self.move_picker(selected.saturating_sub(1)),
+            Down | Char('j') => self.move_picker(min(selected + 1, len - 1)),
+            PageUp => self.move_picker(selected.saturating_sub(10)),
+            PageDown => self.move_picker(min(selected + 10, len - 1)),
+            Char('\n') | Char('o') | Char('c') | Char('y') => {
+                if let Some(picker) = self.url_picker.take() {
+                    let open = key == Char('\n') || key == Char('o');
+                    self.open_or_copy(&picker.urls[picker.selected], open);
+                }
+            }
+            Char('d') => {
+                if let Some(mut picker) = self.url_picker.take() {
+                    self.download(vec![picker.urls.swap_remove(picker.selected)]);
+                }
+            }
+            Esc | Char('q') => self.url_picker = None,
+            Ctrl('c') => self.shutdown = true,
+            _ => {}
+        }
+        true
+    }
+
+    fn move_picker(&mut self, to: usize) {
+        if let Some(ref mut picker) = self.url_picker {
+            picker.selected = to;
+        }
+    }
+
+    /// Keys go to the search results while they're open. Returns false for anything they don't
+    /// handle.
+    fn handle_search_input(&mut self, event: &::termion::event::Event) -> bool {
+        use termion::event::Event::Key;
+        use termion::event::Key::*;
+
+        let (last, selected) = match self.search {
+            Some(ref results) => (results.hits.len().saturating_sub(1), results.selected),
+            None => return false,
+        };
+        let key = match *event {
+            Key(key) => key,
+            _ => return false,
+        };
+        match key {
+            Up | Char('k') => self.move_search(selected.saturating_sub(1)),
+            Down | Char('j') => self.move_search(min(selected + 1, last)),
+            PageUp => self.move_search(selected.saturating_sub(10)),
+            PageDown => self.move_search(min(selected + 10, last)),
+            Left | Char('p') => self.turn_search_page(false),
+            Right | Char('n') => self.turn_search_page(true),
+            Char('\n') => self.jump_to_search_hit(),
+            Esc | Char('q') => self.search = None,
+            Ctrl('c') => self.shutdown = true,
+            _ => {}
+        }
+        true
+    }
+
+    fn move_search(&mut self, to: usize) {
+        if let Some(ref mut results) = self.search {
+            results.selected = to;
+        }
+    }
+
+    /// Asks the server the results came from for the next or previous page of them
+    fn turn_search_page(&mut self, next: bool) {
+        let (server, query, page) = match self.search {
+            Some(ref r) if next && r.has_next_page() => (r.server, r.query.clone(), r.page + 1),
+            Some(ref r) if !next && r.has_previous_page() => {
+                (r.server, r.query.clone(), r.page - 1)
+            }
+            _ => return,
+        };
+        let index = match self.server_indices.get(&server) {
+            Some(index) => *index,
+            None => return,
+        };
+        let server = &self.servers[index];
+        let _ = server.sender.send(TuiEvent::Search {
+            server: server.id,
+            channel: server.channels[server.current_channel].id.clone(),
+            query,
+            page,
+        });
+    }
+
+    /// Switches to `channel`, then selects the message sent at `timestamp` and scrolls to it once
+    /// it's loaded
+    fn show_message(&mut self, server: ServerId, channel: &ChannelId, timestamp: DateTime) {
+        self.show_channel(server, channel);
+        if let Some((s, c)) = self.locate(server, channel) {
+            let channel = &mut self.servers[s].channels[c];
+            channel.selected = Some(timestamp);
+            channel.scroll_to = Some(timestamp);
+        }
+    }
+
+    /// The list to draw over the messages, if one is open
+    fn overlay(&self) -> Option<Overlay> {
+        use find::Mode;
+        if let Some(ref picker) = self.url_picker {
+            return Some(Overlay {
+                title: String::from(
+                    "URLs, newest first. Enter opens, c copies, d downloads, Esc closes",
+                ),
+                rows: picker
+                    .urls
+                    .iter()
+                    .map(|url| (url.clone(), ClickTarget::Url(url.clone())))
+                    .collect(),
+                selected: Some(picker.selected),
+            });
+        }
+        if let Some(ref results) = self.search {
+            return Some(Overlay {
+                title: results.title(),
+                rows: results
+                    .hits
+                    .iter()
+                    .enumerate()
+                    .map(|(i, hit)| (::search::row(hit), ClickTarget::SearchHit(i)))
+                    .collect(),
+                selected: Some(results.selected),
+            });
+        }
+        match self.find {
+            Some(ref find) if find.mode != Mode::Stepping => Some(Overlay {
+                title: find.title(),
+                rows: find
+                    .hits
+                    .iter()
+                    .enumerate()
+                    .map(|(i, hit)| (hit.row(), ClickTarget::FindHit(i)))
+                    .collect(),
+                // Nothing is selected while the regex is being typed
+                selected: Some(find.selected).filter(|_| find.mode == Mode::Browsing),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Every loaded message in every server that matches `regex`, newest first
+    fn find_hits(&self, regex: &Regex) -> Vec<::find::Hit> {
+        let mut hits = Vec::new();
+        for server in self.servers.iter() {
+            for channel in &server.channels {
+                for message in &channel.messages {
+                    if let Some(line) = ::find::matching_line(regex, message.texts()) {
+                        hits.push(::find::Hit {
+                            server: server.id,
+                            server_name: server.name.clone(),
+                            channel: channel.id.clone(),
+                            channel_name: channel.name.clone(),
+                            sender: message.sender().clone(),
+                            timestamp: *message.timestamp(),
+                            line: line.to_string(),
+                        });
+                    }
+                }
+            }
+        }
+        hits.sort_by_key(|hit| ::std::cmp::Reverse(hit.timestamp));
+        hits
+    }
+
+    /// Runs /find, which lists the matches for `pattern` so one can be picked
+    fn find(&mut self, pattern: &str) {
+        if pattern.is_empty() {
+            self.find = None;
+            self.notice(String::from("Usage: /find REGEX"));
+            return;
+        }
+        let regex = match Regex::new(pattern) {
+            Ok(regex) => regex,
+            Err(e) => {
+                self.find = None;
+                self.notice(format!("Can't search for {}: {}", pattern, e));
+                return;
+            }
+        };
+        let hits = self.find_hits(&regex);
+        if hits.is_empty() {
+            self.find = None;
+            self.notice(format!("Nothing that's loaded matches /{}/", pattern));
+            return;
+        }
+        self.url_picker = None;
+        self.search = None;
+        self.find = Some(::find::Find {
+            regex,
+            hits,
+            selected: 0,
+            mode: ::find::Mode::Browsing,
+        });
+    }
+
+    /// Shows the matches for a `/find` command as it's typed, or stops when it's deleted
+    fn update_incremental_find(&mut self) {
+        use find::Mode;
+        let pattern = match ::commands::split(&self.current_channel().message_buffer) {
+            Some(("find", args)) if !args.trim().is_empty() => args.trim().to_string(),
+            _ => {
+                if self.find.as_ref().map(|f| f.mode) == Some(Mode::Typing) {
+                    self.find = None;
+                }
+                return;
+            }
+        };
+        if let Some(ref find) = self.find {
+            if find.mode == Mode::Typing && find.regex.as_str() == pattern {
+                return;
+            }
+        }
+        // A regex that's half typed, like `(foo`, leaves the last results up
+        if let Ok(regex) = Regex::new(&pattern) {
+            let hits = self.find_hits(&regex);
+            self.find = Some(::find::Find {
+                regex,
+                hits,
+                selected: 0,
+                mode: Mode::Typing,
+            });
+        }
+    }
+
+    /// Keys go to /find while its results are open, or while Ctrl-n and Ctrl-p are stepping
+    /// through the matches. Returns false for anything it doesn't handle.
+    fn handle_find_input(&mut self, event: &::termion::event::Event) -> bool {
+        use find::Mode;
+        use termion::event::Event::Key;
+        use termion::event::Key::*;
+
+        let (mode, last, selected) = match self.find {
+            Some(ref find) => (find.mode, find.hits.len().saturating_sub(1), find.selected),
+            None => return false,
+        };
+        let key = match *event {
+            Key(key) => key,
+            _ => return false,
+        };
+        if let Some(older) = ::find::step_direction(mode, key) {
+            self.step_find(older);
+            return true;
+        }
+        match (mode, key) {
+            (Mode::Typing, _) => return false,
+            (Mode::Browsing, Up) | (Mode::Browsing, Char('k')) => {
+                self.move_find(selected.saturating_sub(1))
+            }
+            (Mode::Browsing, Down) | (Mode::Browsing, Char('j')) => {
+                self.move_find(min(selected + 1, last))
+            }
+            (Mode::Browsing, PageUp) => self.move_find(selected.saturating_sub(10)),
+            (Mode::Browsing, PageDown) => self.move_find(min(selected + 10, last)),
+            (Mode::Browsing, Char('\n')) => self.jump_to_find_hit(),
+            (Mode::Browsing, Char('q')) | (_, Esc) => self.find = None,
+            (_, Ctrl('c')) => self.shutdown = true,
+            (Mode::Browsing, _) => {}
+            // Typing anything else puts the search away, and the highlights with it
+            (Mode::Stepping, Char(_)) => {
+                self.find = None;
+                return false;
+            }
+            (Mode::Stepping, _) => return false,
+        }
+        true
+    }
+
+    fn move_find(&mut self, to: usize) {
+        if let Some(ref mut find) = self.find {
+            find.selected = to;
+        }
+    }
+
+    /// Shows the selected /find result, after which Ctrl-n and Ctrl-p go to the matches around it
+    fn jump_to_find_hit(&mut self) {
+        let (server, channel, timestamp) = match self.find {
+            Some(ref mut find) => match find.hits.get(find.selected) {
+                Some(hit) => {
+                    find.mode = ::find::Mode::Stepping;
+                    (hit.server, hit.channel.clone(), hit.timestamp)
+                }
+                None => return,
+            },
+            None => return,
+        };
+        self.show_message(server, &channel, timestamp);
+    }
+
+    /// Selects the next older or newer message in the current channel that matches /find
+    fn step_find(&mut self, older: bool) {
+        let regex = match self.find {
+            Some(ref find) => find.regex.clone(),
+            None => return,
+        };
+        let next = {
+            let channel = self.current_channel();
+            let from = channel
+                .selected
+                .or_else(|| channel.messages.last().map(|m| *m.timestamp()));
+            let matches = channel
+                .messages
+                .iter()
+                .filter(|m| ::find::matching_line(&regex, m.texts()).is_some())
+                .map(|m| *m.timestamp());
+            let mut matches: Vec<DateTime> = match from {
+                Some(from) if older => matches.filter(|t| *t < from).collect(),
+                Some(from) => matches.filter(|t| *t > from).collect(),
+                None => Vec::new(),
+            };
+            if older {
+                matches.pop()
+            } else {
+                matches.into_iter().next()
+            }
+        };
+        match next {
+            Some(timestamp) => {
+                let channel = self.current_channel_mut();
+                channel.selected = Some(timestamp);
+                channel.scroll_to = Some(timestamp);
+            }
+            None => {
+                let which = if older { "older" } else { "newer" };
+                self.status = Some(format!(
+                    "No {} matches for /{}/ in this channel",
+                    which, regex
+                ));
+            }
+        }
+    }
+
+    /// Closes the search results and shows the selected one where it was said, loading the
+    /// messages around it if they aren't loaded yet
+    fn jump_to_search_hit(&mut self) {
+        let (server, hit) = match self.search.take() {
+            Some(mut results) => {
+                if results.selected >= results.hits.len() {
+                    return;
+                }
+                (results.server, results.hits.swap_remove(results.selected))
+            }
+            None => return,
+        };
+        let (s, c) = match self.locate(server, &hit.channel) {
+            Some(found) => found,
+            None => {
+                self.notice(format!(
+                    "Can't show that message, #{} isn't open here",
+                    hit.channel_name
+                ));
+                return;
+            }
+        };
+        self.show_message(server, &hit.channel, hit.timestamp);
+        let channel = &self.servers[s].channels[c];
+        if !channel
+            .messages
+            .iter()
+            .any(|m| *m.timestamp() == hit.timestamp)
+        {
+            let _ = self.servers[s].sender.send(TuiEvent::LoadAround {
+                server,
+                channel: hit.channel,
+                timestamp: hit.timestamp,
+            });
+        }
+    }
+
     fn handle_input(&mut self, event: &::termion::event::Event) {
         use termion::event::Event::*;
         use termion::event::Key::*;
         use termion::event::{MouseButton, MouseEvent};
 
+        if let Key(_) = *event {
+            self.status = None;
+        }
+
+        if self.handle_picker_input(event)
+            || self.handle_search_input(event)
+            || self.handle_find_input(event)
+        {
+            return;
+        }
+
         match *event {
             Key(Char('\n')) => {
                 if !self.current_channel().message_buffer.is_empty() {
@@ -653,16 +2124,17 @@ impl Tui {
             }
             Key(Backspace) => {
                 if self.cursor_pos > 0 {
-                    let remove_pos = self.cursor_pos as usize - 1;
-                    self.current_channel_mut().message_buffer.remove(remove_pos);
-                    self.cursor_pos -= 1;
+                    let end = self.cursor_pos;
+                    let start = ::width::prev_boundary(&self.current_channel().message_buffer, end);
+                    self.current_channel_mut().message_buffer.drain(start..end);
+                    self.cursor_pos = start;
                 }
             }
             Key(Delete) => {
-                let buffer_chars = self.current_channel().message_buffer.chars().count();
-                if buffer_chars > 0 && self.cursor_pos < buffer_chars {
-                    let remove_pos = self.cursor_pos;
-                    self.current_channel_mut().message_buffer.remove(remove_pos);
+                if self.cursor_pos < self.current_channel().message_buffer.len() {
+                    let start = self.cursor_pos;
+                    let end = ::width::next_boundary(&self.current_channel().message_buffer, start);
+                    self.current_channel_mut().message_buffer.drain(start..end);
                 }
             }
             Key(Ctrl('c')) => self.shutdown = true,
@@ -678,6 +2150,21 @@ impl Tui {
             Key(Ctrl('a')) => {
                 self.previous_server();
             }
+            Key(Ctrl('b')) => {
+                self.sidebar_hidden = !self.sidebar_hidden;
+            }
+            Key(Alt(',')) | Key(Alt('<')) => {
+                self.resize_sidebar(false);
+            }
+            Key(Alt('.')) | Key(Alt('>')) => {
+                self.resize_sidebar(true);
+            }
+            Key(Alt('d')) => {
+                self.download_message();
+            }
+            Key(Alt('c')) => {
+                self.toggle_current_section();
+            }
             Key(PageDown) | Key(Ctrl('s')) => {
                 self.next_channel_unread();
             }
@@ -685,64 +2172,73 @@ impl Tui {
                 self.previous_channel_unread();
             }
             Key(Ctrl('q')) | Mouse(MouseEvent::Press(MouseButton::WheelUp, ..)) => {
-                self.current_channel_mut().message_scroll_offset += 1;
+                self.scroll_messages(true);
             }
             Key(Ctrl('e')) | Mouse(MouseEvent::Press(MouseButton::WheelDown, ..)) => {
-                let chan = self.current_channel_mut();
-                let previous_offset = chan.message_scroll_offset;
-                chan.message_scroll_offset = previous_offset.saturating_sub(1);
+                self.scroll_messages(false);
             }
             Key(Left) => {
-                if self.cursor_pos > 0 {
-                    self.cursor_pos -= 1;
-                }
+                self.cursor_pos =
+                    ::width::prev_boundary(&self.current_channel().message_buffer, self.cursor_pos);
             }
             Key(Right) => {
-                if self.cursor_pos < self.current_channel().message_buffer.len() {
-                    self.cursor_pos += 1;
-                }
+                self.cursor_pos =
+                    ::width::next_boundary(&self.current_channel().message_buffer, self.cursor_pos);
             }
             Key(Char('\t')) => {
                 if self.autocompletions.is_empty() {
-                    self.autocompletions = if let Some(last_word) = self
-                        .current_channel()
-                        .message_buffer
-                        .split_whitespace()
-                        .last()
-                    {
-                        self.servers
-                            .get()
-                            .completer
-                            .as_ref()
-                            .map(|c| c.autocomplete(last_word))
-                            .unwrap_or(Vec::new())
-                    } else {
-                        Vec::new()
-                    }
+                    let buffer = &self.current_channel().message_buffer;
+                    let completer = self.servers.get().completer.as_ref();
+                    // Arguments to a command can have quoted or escaped spaces in them
+                    let (start, completions) = match ::commands::split(buffer) {
+                        Some((name, "")) => (
+                            1,
+                            ::commands::complete_name(self.commands(), name)
+                                .into_iter()
+                                .map(|name| name + " ")
+                                .collect(),
+                        ),
+                        Some((name, _)) => {
+                            let (start, argument) = ::upload::last_argument(buffer);
+                            (start, self.complete_command(name, &argument))
+                        }
+                        None => match buffer.split_whitespace().last() {
+                            Some(last_word) => {
+                                let start = buffer
+                                    .char_indices()
+                                    .rev()
+                                    .find(|&(_, c)| c.is_whitespace())
+                                    .map_or(0, |(i, c)| i + c.len_utf8());
+                                let completions = completer
+                                    .map(|c| c.autocomplete(last_word))
+                                    .unwrap_or_default();
+                                (start, completions)
+                            }
+                            None => (buffer.len(), Vec::new()),
+                        },
+                    };
+                    self.autocomplete_start = start;
+                    self.autocompletions = completions;
                 }
                 if !self.autocompletions.is_empty() {
-                    while let Some(c) = self.current_channel().message_buffer.chars().last() {
-                        if c.is_whitespace() {
-                            break;
-                        } else {
-                            self.current_channel_mut().message_buffer.pop();
-                        }
-                    }
                     self.autocomplete_index %= self.autocompletions.len();
                     let chosen_completion = self.autocompletions[self.autocomplete_index].clone();
-                    self.current_channel_mut()
-                        .message_buffer
-                        .push_str(&chosen_completion);
+                    let start = self.autocomplete_start;
+                    let buffer = &mut self.current_channel_mut().message_buffer;
+                    if buffer.is_char_boundary(start) {
+                        buffer.truncate(start);
+                    }
+                    buffer.push_str(&chosen_completion);
                     self.cursor_pos = self.current_channel().message_buffer.len();
                     self.autocomplete_index += 1;
                 }
             }
             Key(Char(c)) => {
-                let current_server_name = self.servers.get().name.clone();
-                let current_channel_name = self.current_channel().name.clone();
+                let current_server = self.servers.get().id;
+                let current_channel = self.current_channel().id.clone();
                 let _ = self.servers.get_mut().sender.send(TuiEvent::SendTyping {
-                    server: current_server_name,
-                    channel: current_channel_name,
+                    server: current_server,
+                    channel: current_channel,
                 });
                 self.autocompletions.clear();
                 self.autocomplete_index = 0;
@@ -750,28 +2246,37 @@ impl Tui {
                 self.current_channel_mut()
                     .message_buffer
                     .insert(current_pos, c);
-                self.cursor_pos += 1;
+                self.cursor_pos += c.len_utf8();
             }
-            Unsupported(ref bytes) => match bytes.as_slice() {
-                [27, 79, 65] => {
-                    let _ = self.sender.send(ConnEvent::Input(Mouse(MouseEvent::Press(
-                        MouseButton::WheelUp,
-                        1,
-                        1,
-                    ))));
-                }
-                [27, 79, 66] => {
-                    let _ = self.sender.send(ConnEvent::Input(Mouse(MouseEvent::Press(
-                        MouseButton::WheelDown,
-                        1,
-                        1,
-                    ))));
+            Mouse(MouseEvent::Press(button @ MouseButton::Left, x, y))
+            | Mouse(MouseEvent::Press(button @ MouseButton::Right, x, y)) => {
+                self.click(button == MouseButton::Left, x, y);
+            }
+            Key(Esc) => {
+                self.current_channel_mut().selected = None;
+            }
+            // Starts typing a /find, whose matches are shown as it's typed
+            Key(Ctrl('f')) => {
+                if self.current_channel().message_buffer.is_empty() {
+                    self.current_channel_mut().message_buffer = String::from("/find ");
+                    self.cursor_pos = self.current_channel().message_buffer.len();
+                } else {
+                    self.status = Some(String::from("Ctrl-f needs an empty message box"));
                 }
-
+            }
+            // With mouse reporting turned off, terminals in alternate scroll mode send the wheel
+            // as cursor keys
+            Unsupported(ref bytes) => match bytes.as_slice() {
+                [27, 79, 65] => self.scroll_messages(true),
+                [27, 79, 66] => self.scroll_messages(false),
                 _ => {}
             },
             _ => {}
         }
+
+        if let Key(_) = *event {
+            self.update_incremental_find();
+        }
     }
 
     fn handle_event(&mut self, event: ConnEvent) {
@@ -828,10 +2333,8 @@ impl Tui {
                 reaction,
             } => {
                 if let Some(msg) = self
-                    .servers
-                    .iter_mut()
-                    .find(|s| s.name == server)
-                    .and_then(|server| server.channels.iter_mut().find(|c| c.name == channel))
+                    .server_mut(server)
+                    .and_then(|server| server.channel_mut(&channel))
                     .and_then(|c| {
                         c.messages
                             .iter_mut()
@@ -841,7 +2344,7 @@ impl Tui {
                     msg.add_reaction(&reaction);
                 } else {
                     error!(
-                        "Couldn't add reaction {} to message: server: {}, channel: {}, timestamp: {}",
+                        "Couldn't add reaction {} to message: server: {:?}, channel: {}, timestamp: {}",
                         reaction, server, channel, timestamp
                     );
                 }
@@ -853,10 +2356,8 @@ impl Tui {
                 reaction,
             } => {
                 if let Some(msg) = self
-                    .servers
-                    .iter_mut()
-                    .find(|s| s.name == server)
-                    .and_then(|server| server.channels.iter_mut().find(|c| c.name == channel))
+                    .server_mut(server)
+                    .and_then(|server| server.channel_mut(&channel))
                     .and_then(|c| {
                         c.messages
                             .iter_mut()
@@ -866,11 +2367,19 @@ impl Tui {
                     msg.remove_reaction(&reaction);
                 } else {
                     error!(
-                        "Couldn't remove reaction {} from message server: {}, channel: {}, timestamp: {}",
+                        "Couldn't remove reaction {} from message server: {:?}, channel: {}, timestamp: {}",
                         reaction, server, channel, timestamp
                     );
                 }
             }
+            ConnEvent::Transfer {
+                server,
+                id,
+                direction,
+                status,
+            } => {
+                self.update_transfer(server, id, direction, status);
+            }
             ConnEvent::Error(message) => {
                 self.add_client_message(message);
             }
@@ -879,46 +2388,101 @@ impl Tui {
                 server,
                 channel,
                 read_at,
-            } => if let Some(c) = self
-                .servers
-                .iter_mut()
-                .find(|s| s.name == server)
-                .and_then(|server| server.channels.iter_mut().find(|c| c.name == channel))
-            {
+            } => if let Some((s, c)) = self.locate(server, &channel) {
+                let c = &mut self.servers[s].channels[c];
                 for m in messages {
                     c.messages.push(m.into());
                 }
-                c.messages
-                    .sort_unstable_by(|m1, m2| m1.timestamp().cmp(&m2.timestamp()));
-                c.read_at = read_at;
+                c.messages.sort_by_key(|m| *m.timestamp());
+                // History can overlap what we already have, which is kept since it may have edits.
+                // Discord timestamps are only to the millisecond, so the sender has to match too.
+                c.messages.dedup_by(|m1, m2| {
+                    m1.timestamp() == m2.timestamp() && m1.sender() == m2.sender()
+                });
+                chan_message::regroup(&mut c.messages, 0, &self.config);
+                if let Some(read_at) = read_at {
+                    c.read_at = read_at;
+                }
             } else {
                 error!(
-                    "Got history for an unknown channel {} in server {}",
+                    "Got history for an unknown channel {} in server {:?}",
                     channel, server
                 );
             },
+            ConnEvent::SearchResults {
+                server,
+                query,
+                page,
+                pages,
+                total,
+                hits,
+            } => {
+                self.url_picker = None;
+                self.find = None;
+                self.search = Some(::search::Results {
+                    server,
+                    query,
+                    page,
+                    pages,
+                    total,
+                    hits,
+                    selected: 0,
+                });
+            }
             ConnEvent::ServerConnected {
+                id,
                 name,
                 channels,
                 completer,
                 sender,
+                commands,
             } => {
-                self.add_server(name, channels, completer, sender);
+                self.add_server(id, name, channels, completer, sender, commands);
+            }
+            ConnEvent::ChannelAdded { server, channel } => {
+                self.add_channel(server, channel);
+            }
+            ConnEvent::ShowChannel { server, channel } => {
+                self.show_channel(server, &channel);
+            }
+            ConnEvent::ChannelRemoved { server, channel } => {
+                self.remove_channel(server, &channel);
+            }
+            ConnEvent::ChannelRenamed {
+                server,
+                channel,
+                name,
+            } => {
+                self.rename_channel(server, &channel, name);
+            }
+            ConnEvent::Notice {
+                server,
+                channel,
+                text,
+            } => {
+                self.add_message(Message {
+                    server,
+                    channel,
+                    sender: "Client".into(),
+                    contents: text,
+                    attachments: Vec::new(),
+                    actions: Vec::new(),
+                    files: Vec::new(),
+                    is_bot: false,
+                    timestamp: DateTime::now(),
+                    reactions: Vec::new(),
+                });
             }
             ConnEvent::MarkChannelRead {
                 server,
                 channel,
                 read_at,
             } => {
-                let current_channel_name = self.current_channel().name.clone();
-                if let Some(c) = self
-                    .servers
-                    .iter_mut()
-                    .find(|s| s.name == server)
-                    .and_then(|server| server.channels.iter_mut().find(|c| c.name == channel))
-                {
-                    if current_channel_name != c.name {
-                        c.read_at = read_at;
+                // We've seen everything in the channel we're looking at
+                let current = (self.servers.tell(), self.servers.get().current_channel);
+                if let Some((s, c)) = self.locate(server, &channel) {
+                    if (s, c) != current {
+                        self.servers[s].channels[c].read_at = read_at;
                     }
                 }
             }
@@ -959,3 +2523,221 @@ impl Tui {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NAMES: [&str; 6] = ["alpha", "bravo", "charlie", "delta", "echo", "foxtrot"];
+
+    fn shown(entries: &[BarEntry]) -> Vec<(usize, usize)> {
+        entries.iter().map(|e| (e.server, e.row)).collect()
+    }
+
+    #[test]
+    fn server_bar_fits_what_it_can() {
+        // Everything fits, no room is kept for markers
+        let all = layout_server_bar(&NAMES[..2], 0, 13, 1);
+        assert_eq!(shown(&all), vec![(0, 0), (1, 0)]);
+        assert_eq!(all[1].column, 8);
+
+        // "alpha • bravo" is 13 columns, but " 4>" has to fit after it
+        let some = layout_server_bar(&NAMES, 0, 15, 1);
+        assert_eq!(shown(&some), vec![(0, 0)]);
+
+        // "<2 " comes first when scrolled
+        let scrolled = layout_server_bar(&NAMES, 2, 40, 1);
+        assert_eq!(scrolled[0].column, 3);
+        assert_eq!(scrolled.last().unwrap().server, 5);
+
+        let wrapped = layout_server_bar(&NAMES, 0, 20, 2);
+        assert_eq!(shown(&wrapped), vec![(0, 0), (1, 0), (2, 1), (3, 1)]);
+        assert_eq!(wrapped[2].column, 0);
+
+        // A name wider than the bar is shortened
+        let long = layout_server_bar(&["a very long server name"], 0, 10, 1);
+        assert_eq!(long[0].name, "a very ...");
+    }
+
+    fn server(channels: &[(&str, Section)]) -> Server {
+        let mut server = Server {
+            channels: channels
+                .iter()
+                .map(|&(name, section)| Channel::new(name.into(), name.into(), section))
+                .collect(),
+            channel_indices: HashMap::new(),
+            completer: None,
+            id: ServerId::unique(),
+            name: "server".into(),
+            current_channel: 0,
+            channel_scroll_offset: 0,
+            collapsed: Vec::new(),
+            sender: sync_channel(1).0,
+            commands: &[],
+        };
+        server.index_channels();
+        server
+    }
+
+    #[test]
+    fn sidebar_sections() {
+        use self::SidebarRow::*;
+
+        // No headers when everything is in one section
+        let single = server(&[("a", Section::Channels), ("b", Section::Channels)]);
+        assert_eq!(single.sidebar_rows(), vec![Channel(0), Channel(1)]);
+
+        let mut server = server(&[
+            ("random", Section::Starred),
+            ("general", Section::Channels),
+            ("dev", Section::Channels),
+            ("alice", Section::DirectMessages),
+        ]);
+        assert_eq!(
+            server.sidebar_rows(),
+            vec![
+                Header(Section::Starred),
+                Channel(0),
+                Header(Section::Channels),
+                Channel(1),
+                Channel(2),
+                Header(Section::DirectMessages),
+                Channel(3),
+            ]
+        );
+
+        // Collapsed sections keep their header, and the current channel stays visible
+        server.toggle_section(Section::Channels);
+        server.toggle_section(Section::Starred);
+        assert_eq!(
+            server.sidebar_rows(),
+            vec![
+                Header(Section::Starred),
+                Channel(0),
+                Header(Section::Channels),
+                Header(Section::DirectMessages),
+                Channel(3),
+            ]
+        );
+
+        // Moving between channels skips the collapsed ones
+        assert_eq!(server.step_channel(true), 3);
+        server.current_channel = 3;
+        assert_eq!(server.step_channel(true), 3);
+        assert_eq!(server.step_channel(false), 3);
+
+        server.toggle_section(Section::Channels);
+        assert_eq!(server.step_channel(false), 2);
+        assert_eq!(server.step_channel(true), 1);
+    }
+
+    #[test]
+    fn channels_come_and_go() {
+        let mut server = server(&[
+            ("general", Section::Channels),
+            ("random", Section::Channels),
+            ("alice", Section::DirectMessages),
+        ]);
+        server.current_channel = 1;
+
+        // Adding a channel before the current one keeps the same channel current
+        server
+            .channels
+            .push(Channel::new("dev".into(), "dev".into(), Section::Channels));
+        server.sort_channels();
+        assert_eq!(server.channels[server.current_channel].name, "random");
+        assert_eq!(server.channels[0].name, "dev");
+
+        server.remove_channel(&"dev".into());
+        assert_eq!(server.channels[server.current_channel].name, "random");
+
+        // Removing the current channel moves to the one after it
+        server.remove_channel(&"random".into());
+        assert_eq!(server.channels[server.current_channel].name, "alice");
+        server.remove_channel(&"alice".into());
+        assert_eq!(server.channels[server.current_channel].name, "general");
+
+        // The last channel stays
+        server.remove_channel(&"general".into());
+        assert_eq!(server.channels.len(), 1);
+    }
+
+    #[test]
+    fn channels_are_found_by_id() {
+        let mut server = server(&[
+            ("general", Section::Channels),
+            ("random", Section::Channels),
+            ("alice", Section::DirectMessages),
+        ]);
+        server.current_channel = 1;
+
+        // Renaming moves the channel down the list, but its id still finds it
+        server.rename_channel(&"general".into(), "zoo".into());
+        assert_eq!(server.channels[1].name, "zoo");
+        assert_eq!(server.channel_mut(&"general".into()).unwrap().name, "zoo");
+        assert!(server.channel_mut(&"zoo".into()).is_none());
+        assert_eq!(server.channels[server.current_channel].name, "random");
+
+        // So does moving it to another section and sorting again
+        server.channel_mut(&"general".into()).unwrap().section = Section::Starred;
+        server.sort_channels();
+        assert_eq!(server.channels[0].name, "zoo");
+        assert_eq!(server.channel_mut(&"random".into()).unwrap().name, "random");
+        assert_eq!(server.channel_mut(&"alice".into()).unwrap().name, "alice");
+
+        // Indexing from scratch finds the same places
+        let indices = server.channel_indices.clone();
+        server.index_channels();
+        assert_eq!(server.channel_indices, indices);
+    }
+
+    #[test]
+    fn servers_with_the_same_name_stay_apart() {
+        let named = |name: &str| Server {
+            name: name.into(),
+            ..server(&[("general", Section::Channels)])
+        };
+        let first = named("work");
+        let second = named("work");
+        let (first_id, second_id) = (first.id, second.id);
+        let mut servers = CursorVec::new(named("Client"));
+        insert_server(&mut servers, first);
+        let mut indices = insert_server(&mut servers, second);
+        assert_eq!(servers.len(), 3);
+
+        let (s, c) = locate(&servers, &indices, second_id, &"general".into()).unwrap();
+        assert_eq!(servers[s].id, second_id);
+        servers[s].rename_channel(&"general".into(), "announcements".into());
+        assert_eq!(servers[s].channels[c].name, "announcements");
+
+        // A server sorted in front of them moves them along, and the ids still find them
+        indices = insert_server(&mut servers, named("home"));
+        assert_eq!(servers.get().name, "Client");
+        let (s, c) = locate(&servers, &indices, first_id, &"general".into()).unwrap();
+        assert_eq!(
+            (servers[s].id, &*servers[s].channels[c].name),
+            (first_id, "general")
+        );
+        let (s, c) = locate(&servers, &indices, second_id, &"general".into()).unwrap();
+        assert_eq!(
+            (servers[s].id, &*servers[s].channels[c].name),
+            (second_id, "announcements")
+        );
+        assert!(locate(&servers, &indices, ServerId::unique(), &"general".into()).is_none());
+    }
+
+    #[test]
+    fn server_bar_follows_selection() {
+        assert_eq!(scroll_server_bar(&NAMES, 0, 0, 20, 1), 0);
+        let first = scroll_server_bar(&NAMES, 0, 5, 20, 1);
+        assert!(first > 0);
+        let entries = layout_server_bar(&NAMES, first, 20, 1);
+        assert_eq!(entries.last().unwrap().server, 5);
+
+        // Going back to the start scrolls back
+        assert_eq!(scroll_server_bar(&NAMES, first, 0, 20, 1), 0);
+
+        // A wider terminal shows everything again
+        assert_eq!(scroll_server_bar(&NAMES, first, 5, 200, 1), 0);
+    }
+}
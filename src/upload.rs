@@ -0,0 +1,335 @@
+//! Sending files with /upload.
+//!
+//! The arguments are split like a shell would, so a path with spaces in it can be quoted or have
+//! its spaces escaped, and paths are completed with tab. Each connection sends the files its own
+//! way, and reads them through a `ProgressReader` so the TUI can show how far along they are.
+use conn::{ConnEvent, Direction, ServerId, TransferStatus};
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::SyncSender;
+use std::time::{Duration, Instant};
+
+pub const USAGE: &str = "/upload [--title TITLE] [--comment TEXT] [--thread] FILE...";
+
+/// A parsed /upload command
+#[derive(Debug, PartialEq)]
+pub struct Upload {
+    pub files: Vec<PathBuf>,
+    pub title: Option<String>,
+    pub comment: Option<String>,
+    /// Post in the thread of the selected message
+    pub thread: bool,
+}
+
+struct Lexed {
+    /// Each word and the byte offset it starts at
+    words: Vec<(usize, String)>,
+    /// The quote that was never closed, if any
+    open_quote: Option<char>,
+    /// Whether the last word runs to the end of the line, rather than being followed by a space
+    ends_in_word: bool,
+}
+
+/// Splits a line into words: whitespace separates words, unless it's quoted or escaped with a
+/// backslash. Nothing is special inside single quotes.
+fn lex(line: &str) -> Lexed {
+    let mut words = Vec::new();
+    let mut current: Option<(usize, String)> = None;
+    let mut quote = None;
+    let mut chars = line.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('\''), c) => current.get_or_insert((i, String::new())).1.push(c),
+            (None, c) if c.is_whitespace() => words.extend(current.take()),
+            (None, '\'') | (None, '"') => {
+                current.get_or_insert((i, String::new()));
+                quote = Some(c);
+            }
+            (_, '\\') => {
+                let word = current.get_or_insert((i, String::new()));
+                if let Some((_, escaped)) = chars.next() {
+                    word.1.push(escaped);
+                }
+            }
+            (_, c) => current.get_or_insert((i, String::new())).1.push(c),
+        }
+    }
+    let ends_in_word = current.is_some();
+    words.extend(current);
+    Lexed {
+        words,
+        open_quote: quote,
+        ends_in_word,
+    }
+}
+
+pub fn split_args(line: &str) -> Result<Vec<String>, String> {
+    let lexed = lex(line);
+    match lexed.open_quote {
+        Some(q) => Err(format!("Missing a closing {}", q)),
+        None => Ok(lexed.words.into_iter().map(|(_, w)| w).collect()),
+    }
+}
+
+/// The word being typed at the end of `line` and the byte offset it starts at, for completion
+pub fn last_argument(line: &str) -> (usize, String) {
+    let mut lexed = lex(line);
+    match lexed.words.pop() {
+        Some(word) if lexed.ends_in_word => word,
+        _ => (line.len(), String::new()),
+    }
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    match ::dirs::home_dir() {
+        Some(ref home) if path == "~" => home.clone(),
+        Some(ref home) if path.starts_with("~/") => home.join(&path[2..]),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// Puts a backslash before anything `lex` would otherwise treat specially
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c.is_whitespace() || c == '\\' || c == '\'' || c == '"' {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Files and directories that start with `partial`, escaped so they can go straight into the
+/// message buffer. Directories end in a slash so completion can carry on into them.
+pub fn complete_path(partial: &str) -> Vec<String> {
+    if partial == "~" {
+        return vec![String::from("~/")];
+    }
+    let split = partial.rfind('/').map_or(0, |i| i + 1);
+    let (typed_dir, prefix) = partial.split_at(split);
+    let dir = if typed_dir.is_empty() {
+        PathBuf::from(".")
+    } else {
+        expand_home(typed_dir)
+    };
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    let mut completions: Vec<String> = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            // Like a shell, only show hidden files when asked for them
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+            let slash = if entry.path().is_dir() { "/" } else { "" };
+            Some(format!("{}{}{}", escape(typed_dir), escape(&name), slash))
+        })
+        .collect();
+    completions.sort();
+    completions
+}
+
+/// Parses everything after `/upload`
+pub fn parse(args: &str) -> Result<Upload, String> {
+    let mut upload = Upload {
+        files: Vec::new(),
+        title: None,
+        comment: None,
+        thread: false,
+    };
+    let mut words = split_args(args)?.into_iter();
+    let mut options_done = false;
+    while let Some(word) = words.next() {
+        if options_done {
+            upload.files.push(expand_home(&word));
+            continue;
+        }
+        match word.as_str() {
+            "--title" | "-t" => {
+                upload.title = Some(
+                    words
+                        .next()
+                        .ok_or_else(|| String::from("--title needs a title"))?,
+                )
+            }
+            "--comment" | "-m" => {
+                upload.comment = Some(
+                    words
+                        .next()
+                        .ok_or_else(|| String::from("--comment needs a comment"))?,
+                )
+            }
+            "--thread" | "-r" => upload.thread = true,
+            "--" => options_done = true,
+            option if option.starts_with('-') && option.len() > 1 => {
+                return Err(format!("Unknown option {}, usage: {}", option, USAGE))
+            }
+            _ => upload.files.push(expand_home(&word)),
+        }
+    }
+    if upload.files.is_empty() {
+        return Err(format!("No files to upload, usage: {}", USAGE));
+    }
+    Ok(upload)
+}
+
+/// The name a file is uploaded under
+pub fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.display().to_string())
+}
+
+/// Reads a file that's being uploaded, telling the TUI how much of it has been sent
+pub struct ProgressReader {
+    file: File,
+    server: ServerId,
+    path: PathBuf,
+    done: u64,
+    total: u64,
+    last_update: Option<Instant>,
+    sender: SyncSender<ConnEvent>,
+}
+
+impl ProgressReader {
+    pub fn open(path: &Path, server: ServerId, sender: SyncSender<ConnEvent>) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let total = file.metadata()?.len();
+        Ok(ProgressReader {
+            file,
+            server,
+            path: path.to_path_buf(),
+            done: 0,
+            total,
+            last_update: None,
+            sender,
+        })
+    }
+
+    /// How many bytes are in the file
+    pub fn size(&self) -> u64 {
+        self.total
+    }
+
+    /// A form part that reads the file through this reader
+    pub fn into_part(self) -> ::reqwest::multipart::Part {
+        let name = file_name(&self.path);
+        let total = self.total;
+        ::reqwest::multipart::Part::reader_with_length(self, total).file_name(name)
+    }
+}
+
+impl Read for ProgressReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.file.read(buf)?;
+        self.done += n as u64;
+        let due = match self.last_update {
+            Some(t) => t.elapsed() > Duration::from_millis(100),
+            None => true,
+        };
+        if due {
+            self.last_update = Some(Instant::now());
+            let _ = self.sender.send(ConnEvent::Transfer {
+                server: self.server,
+                id: self.path.display().to_string(),
+                direction: Direction::Upload,
+                status: TransferStatus::Progress {
+                    file: file_name(&self.path),
+                    done: self.done,
+                    total: Some(self.total),
+                },
+            });
+        }
+        Ok(n)
+    }
+}
+
+/// Tells the TUI that each of `paths` has been uploaded, or why they couldn't be
+pub fn finish(
+    paths: &[PathBuf],
+    result: Result<(), String>,
+    server: ServerId,
+    sender: &SyncSender<ConnEvent>,
+) {
+    for path in paths {
+        let _ = sender.send(ConnEvent::Transfer {
+            server,
+            id: path.display().to_string(),
+            direction: Direction::Upload,
+            status: match result {
+                Ok(()) => TransferStatus::Finished(path.clone()),
+                Err(ref e) => TransferStatus::Failed(e.clone()),
+            },
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_like_a_shell() {
+        assert_eq!(
+            split_args(r#"a "b c" 'd "e"' f\ g "h\"i" '\'"#).unwrap(),
+            vec!["a", "b c", "d \"e\"", "f g", "h\"i", "\\"]
+        );
+        assert_eq!(split_args("  ").unwrap(), Vec::<String>::new());
+        assert_eq!(split_args("\"\"").unwrap(), vec![""]);
+        assert!(split_args("\"open").is_err());
+    }
+
+    #[test]
+    fn last_arguments() {
+        assert_eq!(last_argument("upload my\\ fi"), (7, String::from("my fi")));
+        assert_eq!(last_argument("upload \"my fi"), (7, String::from("my fi")));
+        assert_eq!(last_argument("upload a "), (9, String::new()));
+        assert_eq!(escape("my file's"), "my\\ file\\'s");
+    }
+
+    #[test]
+    fn parses_uploads() {
+        assert_eq!(
+            parse("-t \"Weekly report\" --comment 'see page 2' --thread a.pdf \"b c.png\"")
+                .unwrap(),
+            Upload {
+                files: vec![PathBuf::from("a.pdf"), PathBuf::from("b c.png")],
+                title: Some(String::from("Weekly report")),
+                comment: Some(String::from("see page 2")),
+                thread: true,
+            }
+        );
+        assert_eq!(parse("-- -r").unwrap().files, vec![PathBuf::from("-r")]);
+        assert!(parse("").is_err());
+        assert!(parse("--title").is_err());
+        assert!(parse("--force a.txt").is_err());
+    }
+
+    #[test]
+    fn completes_paths() {
+        let dir = ::std::env::temp_dir().join(format!("omnichat-upload-{}", ::std::process::id()));
+        fs::create_dir_all(dir.join("sub dir")).unwrap();
+        File::create(dir.join("some file.txt")).unwrap();
+        File::create(dir.join(".hidden")).unwrap();
+        let typed = format!("{}/s", dir.display());
+        let completions = complete_path(&typed);
+        let hidden = complete_path(&format!("{}/.", dir.display()));
+        fs::remove_dir_all(&dir).unwrap();
+
+        let base = escape(&format!("{}/", dir.display()));
+        assert_eq!(
+            completions,
+            vec![
+                format!("{}some\\ file.txt", base),
+                format!("{}sub\\ dir/", base)
+            ]
+        );
+        assert_eq!(hidden, vec![format!("{}.hidden", base)]);
+    }
+}
@@ -0,0 +1,220 @@
+//! Opening URLs and copying things to the clipboard.
+//!
+//! There is no one clipboard program that works everywhere: xclip and xsel only talk to X, wl-copy
+//! only to Wayland, and none of them can reach the clipboard of the machine you're sitting at when
+//! omnichat runs over SSH. For that last case the terminal itself can set the clipboard with an
+//! OSC 52 escape sequence, if it supports it.
+use chan_message::ChanMessage;
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+use tui::URL_REGEX;
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Clipboard {
+    /// OSC 52 over SSH, otherwise whichever of the programs below is installed
+    Auto,
+    Xclip,
+    Xsel,
+    WlCopy,
+    Osc52,
+}
+
+/// The `[urls]` section of the config file
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct UrlConfig {
+    /// The program URLs are opened with, followed by any arguments. The URL is added at the end.
+    pub open_command: Option<String>,
+    pub clipboard: Clipboard,
+}
+
+impl Default for UrlConfig {
+    fn default() -> Self {
+        UrlConfig {
+            open_command: None,
+            clipboard: Clipboard::Auto,
+        }
+    }
+}
+
+impl UrlConfig {
+    pub fn open(&self, url: &str) -> Result<(), String> {
+        let default = if cfg!(target_os = "macos") {
+            "open"
+        } else {
+            "xdg-open"
+        };
+        let command = self.open_command.as_ref().map_or(default, |c| c.as_str());
+        let mut words = command.split_whitespace();
+        let program = words.next().unwrap_or(default);
+        Command::new(program)
+            .args(words)
+            .arg(url)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map(|_| ())
+            .map_err(|e| format!("Couldn't open {} with {}: {}", url, program, e))
+    }
+
+    pub fn copy(&self, text: &str) -> Result<(), String> {
+        match self.clipboard {
+            Clipboard::Auto => {
+                if ::std::env::var_os("SSH_CONNECTION").is_some()
+                    || ::std::env::var_os("SSH_TTY").is_some()
+                {
+                    return copy_with(Clipboard::Osc52, text).map_err(|e| e.to_string());
+                }
+                let mut candidates = Vec::new();
+                if ::std::env::var_os("WAYLAND_DISPLAY").is_some() {
+                    candidates.push(Clipboard::WlCopy);
+                }
+                candidates.extend(&[Clipboard::Xclip, Clipboard::Xsel]);
+                for backend in candidates {
+                    match copy_with(backend, text) {
+                        Err(ref e) if e.kind() == io::ErrorKind::NotFound => continue,
+                        result => return result.map_err(|e| format!("{:?}: {}", backend, e)),
+                    }
+                }
+                Err(String::from(
+                    "No clipboard program found, install xclip, xsel or wl-copy, \
+                     or set clipboard = \"osc52\" in the [urls] config section",
+                ))
+            }
+            backend => copy_with(backend, text).map_err(|e| format!("{:?}: {}", backend, e)),
+        }
+    }
+}
+
+fn copy_with(backend: Clipboard, text: &str) -> io::Result<()> {
+    let (program, args): (&str, &[&str]) = match backend {
+        Clipboard::Xclip => ("xclip", &["-selection", "clipboard"]),
+        Clipboard::Xsel => ("xsel", &["--clipboard", "--input"]),
+        Clipboard::WlCopy => ("wl-copy", &[]),
+        Clipboard::Osc52 | Clipboard::Auto => {
+            let stdout = io::stdout();
+            let mut lock = stdout.lock();
+            write!(lock, "\x1b]52;c;{}\x07", base64(text.as_bytes()))?;
+            return lock.flush();
+        }
+    };
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    child
+        .stdin
+        .take()
+        .expect("child stdin is piped")
+        .write_all(text.as_bytes())?;
+    let status = child.wait()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!(
+            "{} exited with {}",
+            program, status
+        )))
+    }
+}
+
+fn base64(input: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut output = String::with_capacity(input.len().div_ceil(3) * 4);
+    for chunk in input.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = (u32::from(bytes[0]) << 16) | (u32::from(bytes[1]) << 8) | u32::from(bytes[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                output.push(ALPHABET[(n >> (18 - 6 * i)) as usize & 63] as char);
+            } else {
+                output.push('=');
+            }
+        }
+    }
+    output
+}
+
+/// Every URL mentioned in `messages`, newest first, each only once
+pub fn collect(messages: &[ChanMessage]) -> Vec<String> {
+    let mut urls: Vec<String> = Vec::new();
+    for message in messages.iter().rev() {
+        let found: Vec<&str> = message
+            .texts()
+            .flat_map(|text| URL_REGEX.find_iter(text))
+            .map(|m| m.as_str())
+            .collect();
+        // Keep each message's URLs in the order they were written
+        for url in found {
+            if !urls.iter().any(|u| u == url) {
+                urls.push(String::from(url));
+            }
+        }
+    }
+    urls
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_base64() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(
+            base64(b"https://example.com"),
+            "aHR0cHM6Ly9leGFtcGxlLmNvbQ=="
+        );
+    }
+
+    #[test]
+    fn collects_newest_first() {
+        let message = |contents: &str| {
+            ChanMessage::from(::conn::Message {
+                server: ::conn::ServerId::unique(),
+                channel: "channel".into(),
+                sender: "sender".into(),
+                contents: contents.into(),
+                attachments: Vec::new(),
+                actions: Vec::new(),
+                files: Vec::new(),
+                is_bot: false,
+                timestamp: ::conn::DateTime::now(),
+                reactions: Vec::new(),
+            })
+        };
+        let messages = vec![
+            message("old https://a.example.com/ and https://b.example.com/"),
+            message("nothing here"),
+            message("new https://c.example.com/x and again https://a.example.com/"),
+        ];
+        assert_eq!(
+            collect(&messages),
+            vec![
+                "https://c.example.com/x",
+                "https://a.example.com/",
+                "https://b.example.com/",
+            ]
+        );
+    }
+
+    #[test]
+    fn clipboard_names() {
+        let config: UrlConfig = ::toml::from_str("clipboard = \"wl-copy\"").unwrap();
+        assert_eq!(config.clipboard, Clipboard::WlCopy);
+        let config: UrlConfig = ::toml::from_str("clipboard = \"osc52\"").unwrap();
+        assert_eq!(config.clipboard, Clipboard::Osc52);
+        assert!(::toml::from_str::<UrlConfig>("clipboard = \"pbcopy\"").is_err());
+    }
+}
@@ -0,0 +1,297 @@
+//! Terminal display width of text.
+//!
+//! Everything that lays text out on the screen must measure it in terminal columns, not chars.
+//! CJK and most emoji take two columns, combining marks take none, and a ZWJ sequence or a flag
+//! is several chars that render as one double-width glyph. We measure per grapheme cluster so all
+//! of those come out right, and we skip over ANSI escape sequences so already-colored strings can
+//! be measured too.
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthChar;
+
+/// The number of terminal columns a single grapheme cluster occupies
+pub fn grapheme_width(grapheme: &str) -> usize {
+    let mut chars = grapheme.chars();
+    let first = match chars.next() {
+        Some(c) => c,
+        None => return 0,
+    };
+    let has_more = chars.next().is_some();
+
+    // Emoji presentation selector, ZWJ sequences, skin tones, flags: all render as one wide glyph
+    if has_more
+        && (grapheme.contains('\u{FE0F}')
+            || grapheme.contains('\u{200D}')
+            || is_regional_indicator(first)
+            || grapheme.chars().any(is_skin_tone))
+    {
+        return 2;
+    }
+
+    match first.width() {
+        Some(0) | None => grapheme.chars().filter_map(|c| c.width()).sum(),
+        Some(w) => w,
+    }
+}
+
+fn is_regional_indicator(c: char) -> bool {
+    ('\u{1F1E6}'..='\u{1F1FF}').contains(&c)
+}
+
+fn is_skin_tone(c: char) -> bool {
+    ('\u{1F3FB}'..='\u{1F3FF}').contains(&c)
+}
+
+/// Length in bytes of the ANSI escape sequence at the start of `s`, if there is one
+fn escape_len(s: &str) -> Option<usize> {
+    let bytes = s.as_bytes();
+    if bytes.len() < 2 || bytes[0] != 0x1b || bytes[1] != b'[' {
+        return None;
+    }
+    bytes[2..]
+        .iter()
+        .position(|b| (0x40..=0x7e).contains(b))
+        .map(|end| end + 3)
+}
+
+/// The number of terminal columns `s` occupies, ignoring any ANSI escape sequences
+pub fn str_width(s: &str) -> usize {
+    let mut width = 0;
+    let mut rest = s;
+    while !rest.is_empty() {
+        let plain_len = rest.find('\x1b').unwrap_or(rest.len());
+        width += rest[..plain_len]
+            .graphemes(true)
+            .map(grapheme_width)
+            .sum::<usize>();
+        rest = &rest[plain_len..];
+        if !rest.is_empty() {
+            rest = &rest[escape_len(rest).unwrap_or(1)..];
+        }
+    }
+    width
+}
+
+/// The longest prefix of `s` which fits in `max_width` columns
+pub fn truncate_to_width(s: &str, max_width: usize) -> &str {
+    let mut width = 0;
+    for (i, g) in s.grapheme_indices(true) {
+        width += grapheme_width(g);
+        if width > max_width {
+            return &s[..i];
+        }
+    }
+    s
+}
+
+/// `s` if it fits in `max_width` columns, otherwise as much of it as fits followed by "..."
+pub fn shorten(s: &str, max_width: usize) -> String {
+    if str_width(s) <= max_width {
+        String::from(s)
+    } else {
+        let mut short = String::from(truncate_to_width(s, max_width.saturating_sub(3)));
+        short.push_str(&"..."[..::std::cmp::min(3, max_width)]);
+        short
+    }
+}
+
+/// Byte offset of the grapheme boundary before `pos`, or 0
+pub fn prev_boundary(s: &str, pos: usize) -> usize {
+    s[..pos]
+        .grapheme_indices(true)
+        .next_back()
+        .map(|(i, _)| i)
+        .unwrap_or(0)
+}
+
+/// Byte offset of the grapheme boundary after `pos`, or the length of `s`
+pub fn next_boundary(s: &str, pos: usize) -> usize {
+    s[pos..]
+        .graphemes(true)
+        .next()
+        .map(|g| pos + g.len())
+        .unwrap_or(s.len())
+}
+
+/// Moves `pos` back until it lands on a grapheme boundary of `s`
+pub fn snap_to_boundary(s: &str, pos: usize) -> usize {
+    if pos >= s.len() {
+        return s.len();
+    }
+    let mut boundary = 0;
+    for (i, _) in s.grapheme_indices(true) {
+        if i > pos {
+            break;
+        }
+        boundary = i;
+    }
+    boundary
+}
+
+/// Splits `s` into rows of at most `width` columns without regard for words, as a terminal would.
+/// Returns the byte range of each row. If the last row is exactly full, an empty row follows it
+/// so that there is always somewhere to put a cursor at the end of the text.
+pub fn split_rows(s: &str, width: usize) -> Vec<::std::ops::Range<usize>> {
+    let mut rows = Vec::new();
+    let mut start = 0;
+    let mut row_width = 0;
+    for (i, g) in s.grapheme_indices(true) {
+        let w = grapheme_width(g);
+        if row_width + w > width && i > start {
+            rows.push(start..i);
+            start = i;
+            row_width = 0;
+        }
+        row_width += w;
+    }
+    if row_width >= width && start < s.len() {
+        rows.push(start..s.len());
+        start = s.len();
+    }
+    rows.push(start..s.len());
+    rows
+}
+
+/// Word-wraps `text` to `width` columns, prefixing the first line with `initial_indent` and all
+/// others with `subsequent_indent`. Words longer than a line are broken between graphemes.
+/// Whitespace at the start of the text is preserved; whitespace at a line break is dropped.
+pub fn wrap(
+    text: &str,
+    width: usize,
+    initial_indent: &str,
+    subsequent_indent: &str,
+) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::from(initial_indent);
+    let mut line_width = str_width(initial_indent);
+    let mut line_empty = true;
+
+    let word_start = text
+        .find(|c: char| !c.is_whitespace())
+        .unwrap_or(text.len());
+    let mut space = &text[..word_start];
+    let mut rest = &text[word_start..];
+
+    while !rest.is_empty() {
+        let word_end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let word = &rest[..word_end];
+        rest = &rest[word_end..];
+        let space_end = rest
+            .find(|c: char| !c.is_whitespace())
+            .unwrap_or(rest.len());
+
+        let space_width = str_width(space);
+        let word_width = str_width(word);
+        if !line_empty && line_width + space_width + word_width > width {
+            lines.push(::std::mem::replace(&mut line, String::from(subsequent_indent)));
+            line_width = str_width(subsequent_indent);
+            line_empty = true;
+        } else {
+            line.push_str(space);
+            line_width += space_width;
+        }
+
+        if line_width + word_width <= width {
+            line.push_str(word);
+            line_width += word_width;
+        } else {
+            for g in word.graphemes(true) {
+                let w = grapheme_width(g);
+                if line_width + w > width && !line_empty {
+                    lines.push(::std::mem::replace(&mut line, String::from(subsequent_indent)));
+                    line_width = str_width(subsequent_indent);
+                }
+                line.push_str(g);
+                line_width += w;
+                line_empty = false;
+            }
+        }
+        line_empty = false;
+
+        space = &rest[..space_end];
+        rest = &rest[space_end..];
+    }
+
+    if !line_empty {
+        lines.push(line);
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FAMILY: &str = "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}";
+    const THUMBS_UP_DARK: &str = "\u{1F44D}\u{1F3FF}";
+    const FLAG_JP: &str = "\u{1F1EF}\u{1F1F5}";
+    const E_ACUTE: &str = "e\u{0301}";
+
+    #[test]
+    fn widths() {
+        assert_eq!(str_width("hello"), 5);
+        assert_eq!(str_width("日本語"), 6);
+        assert_eq!(str_width("\u{1F600}"), 2);
+        assert_eq!(str_width(FAMILY), 2);
+        assert_eq!(str_width(THUMBS_UP_DARK), 2);
+        assert_eq!(str_width(FLAG_JP), 2);
+        assert_eq!(str_width(E_ACUTE), 1);
+        assert_eq!(str_width("\u{2764}\u{FE0F}"), 2);
+        assert_eq!(str_width("\x1b[38;5;8m(12:00)\x1b[39m 한국"), 12);
+    }
+
+    #[test]
+    fn wrap_respects_width() {
+        let text = format!(
+            "日本語のテキスト {} and {} family {} mixed with ascii wörds{}",
+            THUMBS_UP_DARK, FAMILY, FLAG_JP, E_ACUTE
+        );
+        // Narrower than an indent plus one wide glyph is hopeless
+        for width in 6..30 {
+            let lines = wrap(&text, width, "  ", "    ");
+            assert!(!lines.is_empty());
+            for line in &lines {
+                assert!(str_width(line) <= width, "{:?} wider than {}", line, width);
+            }
+            // Nothing but whitespace is lost
+            let joined: String = lines.concat().split_whitespace().collect();
+            let original: String = text.split_whitespace().collect();
+            assert_eq!(joined, original);
+        }
+    }
+
+    #[test]
+    fn wrap_keeps_leading_whitespace() {
+        assert_eq!(wrap("  a b", 10, "", ""), vec!["  a b"]);
+        assert_eq!(wrap("aa bb", 4, "", "."), vec!["aa", ".bb"]);
+        assert!(wrap("", 10, "", "").is_empty());
+    }
+
+    #[test]
+    fn shorten_wide() {
+        assert_eq!(shorten("general", 10), "general");
+        assert_eq!(shorten("日本語チャンネル", 10), "日本語...");
+        assert!(str_width(&shorten("日本語チャンネル", 8)) <= 8);
+        assert_eq!(
+            shorten(&format!("{}{}{}", FAMILY, FAMILY, FAMILY), 5),
+            format!("{}...", FAMILY)
+        );
+    }
+
+    #[test]
+    fn rows_and_boundaries() {
+        let text = format!("ab日本{}c", FAMILY);
+        let rows = split_rows(&text, 3);
+        let rows: Vec<&str> = rows.into_iter().map(|r| &text[r]).collect();
+        assert_eq!(rows, vec!["ab", "日", "本", &format!("{}c", FAMILY), ""]);
+
+        let full = split_rows("abc", 3);
+        assert_eq!(full, vec![0..3, 3..3]);
+        assert_eq!(split_rows("", 3), vec![0..0]);
+
+        let s = format!("a{}b", E_ACUTE);
+        assert_eq!(next_boundary(&s, 1), 1 + E_ACUTE.len());
+        assert_eq!(prev_boundary(&s, 1 + E_ACUTE.len()), 1);
+        assert_eq!(snap_to_boundary(&s, 2), 1);
+        assert_eq!(snap_to_boundary(&s, 100), s.len());
+    }
+}
@@ -0,0 +1,251 @@
+//! Block Kit, the layout that messages from apps are built out of, and the rich text that
+//! Slack's own clients send alongside the plain text of a message.
+//!
+//! See https://api.slack.com/reference/block-kit/blocks
+//!
+//! Every enum here has an `Unknown` variant, so a kind of block that Slack adds later doesn't stop
+//! the rest of the message from being read.
+use crate::id::*;
+
+/// One block of a message or attachment
+#[derive(Deserialize)]
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
+pub enum Block {
+    /// Text, and/or fields shown as two columns
+    Section {
+        #[serde(default)]
+        block_id: String,
+        text: Option<Text>,
+        #[serde(default)]
+        fields: Vec<Text>,
+        /// Shown to the right of the text
+        accessory: Option<Action>,
+    },
+    /// A row of buttons and menus
+    Actions {
+        #[serde(default)]
+        block_id: String,
+        elements: Vec<Action>,
+    },
+    /// Small text and images, shown in a line
+    Context {
+        elements: Vec<ContextElement>,
+    },
+    Divider {},
+    /// Large plain text
+    Header {
+        text: Text,
+    },
+    Image {
+        image_url: String,
+        alt_text: String,
+        title: Option<Text>,
+    },
+    /// What a message written in a Slack client looks like
+    RichText {
+        elements: Vec<RichText>,
+    },
+    #[serde(other)]
+    Unknown,
+}
+
+/// https://api.slack.com/reference/block-kit/composition-objects#text
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Text {
+    #[serde(rename = "type")]
+    pub kind: TextKind,
+    pub text: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TextKind {
+    PlainText,
+    /// Slack's markup, which has mentions and links in `<...>` just like the text of a message
+    Mrkdwn,
+}
+
+/// Something in a message that can be clicked on, which tells the app that posted it
+#[derive(Deserialize)]
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Button {
+        action_id: String,
+        text: Text,
+        value: Option<String>,
+        /// Opened in the browser as well as being sent to the app
+        url: Option<String>,
+        /// `primary` or `danger`
+        style: Option<String>,
+    },
+    /// A menu with a fixed list of options
+    #[serde(rename = "static_select")]
+    Select {
+        action_id: String,
+        placeholder: Option<Text>,
+        options: Vec<SelectOption>,
+    },
+    #[serde(other)]
+    Unknown,
+}
+
+/// https://api.slack.com/reference/block-kit/composition-objects#option
+#[derive(Clone, Deserialize, Serialize)]
+pub struct SelectOption {
+    pub text: Text,
+    pub value: String,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
+pub enum ContextElement {
+    PlainText {
+        text: String,
+    },
+    Mrkdwn {
+        text: String,
+    },
+    Image {
+        image_url: String,
+        alt_text: String,
+    },
+    #[serde(other)]
+    Unknown,
+}
+
+/// A paragraph, list, code block or quote in a `rich_text` block
+#[derive(Deserialize)]
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
+pub enum RichText {
+    RichTextSection {
+        elements: Vec<RichTextElement>,
+    },
+    /// Each item is a `RichTextSection`
+    RichTextList {
+        style: ListStyle,
+        /// How many levels this list is nested under another
+        #[serde(default)]
+        indent: usize,
+        elements: Vec<RichText>,
+    },
+    RichTextPreformatted {
+        elements: Vec<RichTextElement>,
+    },
+    RichTextQuote {
+        elements: Vec<RichTextElement>,
+    },
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ListStyle {
+    Bullet,
+    Ordered,
+}
+
+/// A run of text, or a mention, link or emoji, inside rich text
+#[derive(Deserialize)]
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
+pub enum RichTextElement {
+    Text {
+        text: String,
+        #[serde(default)]
+        style: RichTextStyle,
+    },
+    Link {
+        url: String,
+        text: Option<String>,
+    },
+    User {
+        user_id: UserId,
+    },
+    Channel {
+        channel_id: ConversationId,
+    },
+    Usergroup {
+        usergroup_id: UsergroupId,
+    },
+    /// @here, @channel or @everyone
+    Broadcast {
+        range: String,
+    },
+    /// Just the name, without the colons
+    Emoji {
+        name: String,
+    },
+    Date {
+        timestamp: i64,
+        format: String,
+        fallback: Option<String>,
+    },
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+pub struct RichTextStyle {
+    #[serde(default)]
+    pub bold: bool,
+    #[serde(default)]
+    pub italic: bool,
+    #[serde(default)]
+    pub strike: bool,
+    #[serde(default)]
+    pub code: bool,
+}
+
+/// The attachments that came before Block Kit, which most integrations still send.
+///
+/// See https://api.slack.com/reference/messaging/attachments
+#[derive(Deserialize)]
+pub struct Attachment {
+    /// Plain text for clients that can't show anything else
+    pub fallback: Option<String>,
+    /// `good`, `warning`, `danger` or a `#rrggbb` color for the bar down the side
+    pub color: Option<String>,
+    /// Shown above the attachment, outside its bar
+    pub pretext: Option<String>,
+    pub author_name: Option<String>,
+    pub author_link: Option<String>,
+    pub author_icon: Option<String>,
+    pub title: Option<String>,
+    pub title_link: Option<String>,
+    pub text: Option<String>,
+    #[serde(default)]
+    pub fields: Vec<AttachmentField>,
+    pub image_url: Option<String>,
+    pub thumb_url: Option<String>,
+    pub footer: Option<String>,
+    pub footer_icon: Option<String>,
+    /// Shown after the footer
+    pub ts: Option<crate::Timestamp>,
+    /// Which of `text`, `pretext` and `fields` have markup in them
+    #[serde(default)]
+    pub mrkdwn_in: Vec<String>,
+    /// An attachment can be laid out with blocks instead of the fields above
+    #[serde(default)]
+    pub blocks: Vec<Block>,
+    #[serde(default)]
+    pub files: Vec<File>,
+}
+
+#[derive(Deserialize)]
+pub struct AttachmentField {
+    pub title: Option<String>,
+    pub value: Option<String>,
+    /// Whether the field is short enough to be shown next to another one
+    #[serde(default)]
+    pub short: bool,
+}
+
+#[derive(Deserialize)]
+pub struct File {
+    pub url_private: String,
+}
@@ -0,0 +1,22 @@
+//! Take the actions in messages, like pressing a button an app has posted.
+use crate::id::*;
+
+/// Tells the app that posted a message that one of its buttons was pressed or a menu option
+/// chosen, the same way Slack's own clients do. The app answers by posting or updating a message,
+/// not in the response.
+///
+/// Wraps blocks.actions, which Slack's clients use but doesn't document
+
+#[derive(Serialize, new)]
+pub struct ActionsRequest {
+    /// The bot that posted the message
+    pub service_id: BotId,
+    /// The message the action is in, as JSON: `{"type": "message", "message_ts": ...,
+    /// "channel_id": ..., "is_ephemeral": false}`
+    pub container: String,
+    /// A JSON list of the block elements being acted on, each with its `block_id`, and with a
+    /// `selected_option` for menus
+    pub actions: String,
+    /// Anything unique, so a retried request isn't taken as a second press
+    pub client_token: String,
+}
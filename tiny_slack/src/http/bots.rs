@@ -0,0 +1,29 @@
+//! Get info on the bots that integrations and apps post as.
+use crate::id::*;
+
+/// Gets information about a bot user.
+///
+/// Wraps https://api.slack.com/methods/bots.info
+
+#[derive(Serialize, new)]
+pub struct InfoRequest {
+    /// Bot user to get info on
+    pub bot: BotId,
+}
+
+#[derive(Deserialize)]
+pub struct InfoResponse {
+    ok: bool,
+    pub bot: Bot,
+}
+
+#[derive(Deserialize)]
+pub struct Bot {
+    pub id: BotId,
+    pub name: String,
+    #[serde(default)]
+    pub deleted: bool,
+    pub app_id: Option<AppId>,
+    /// The user the bot posts as, if it has one
+    pub user_id: Option<UserId>,
+}
@@ -1,12 +1,7 @@
-use crate::http::Cursor;
+use crate::http::{Cursor, ResponseMetadata};
 use crate::id::*;
 use crate::timestamp::Timestamp;
 
-#[derive(Deserialize)]
-pub struct ResponseMetadata {
-    pub next_cursor: String,
-}
-
 /// Fetches a conversation's history of messages and events.
 ///
 /// Wraps https://api.slack.com/methods/conversations.history
@@ -34,7 +29,7 @@ pub struct HistoryRequest {
 
     /// Start of time range of messages to include in results.
     #[new(default)]
-    pub oldest: Option<u32>,
+    pub oldest: Option<Timestamp>,
 }
 
 /// Retrieve information about a conversation.
@@ -330,3 +325,159 @@ pub enum ConversationInfo {
         user: UserId,
     },
 }
+
+/// The parts of a conversation object we use, for the methods and RTM events whose responses we
+/// only need a little of. Most fields are missing from some of them.
+#[derive(Deserialize)]
+pub struct ConversationSummary {
+    pub id: ConversationId,
+    pub name: Option<String>,
+    #[serde(default)]
+    pub is_private: bool,
+    #[serde(default)]
+    pub is_archived: bool,
+    #[serde(default)]
+    pub is_im: bool,
+    #[serde(default)]
+    pub is_mpim: bool,
+    #[serde(default)]
+    pub is_member: bool,
+    pub num_members: Option<u32>,
+    /// The other person in a direct message
+    pub user: Option<UserId>,
+    pub topic: Option<ConversationTopic>,
+    pub purpose: Option<ConversationPurpose>,
+}
+
+/// What conversations.create, conversations.join, conversations.invite and conversations.info
+/// return, read as a `ConversationSummary`
+#[derive(Deserialize)]
+pub struct SummaryResponse {
+    ok: bool,
+    pub channel: ConversationSummary,
+}
+
+/// What conversations.list returns, read as `ConversationSummary`s
+#[derive(Deserialize)]
+pub struct SummaryListResponse {
+    ok: bool,
+    pub channels: Vec<ConversationSummary>,
+    pub response_metadata: Option<ResponseMetadata>,
+}
+
+/// Archives a conversation.
+///
+/// Wraps https://api.slack.com/methods/conversations.archive
+
+#[derive(Serialize, new)]
+pub struct ArchiveRequest {
+    /// ID of conversation to archive
+    pub channel: ConversationId,
+}
+
+/// Initiates a public or private channel-based conversation
+///
+/// Wraps https://api.slack.com/methods/conversations.create
+
+#[derive(Serialize, new)]
+pub struct CreateRequest<'a> {
+    /// Name of the channel to create
+    pub name: &'a str,
+
+    /// Create a private channel instead of a public one
+    #[new(default)]
+    pub is_private: Option<bool>,
+}
+
+/// Invites users to a channel.
+///
+/// Wraps https://api.slack.com/methods/conversations.invite
+
+#[derive(Serialize, new)]
+pub struct InviteRequest {
+    /// The ID of the public or private channel to invite user(s) to.
+    pub channel: ConversationId,
+    /// A comma separated list of user IDs. Up to 30 users may be listed.
+    #[serde(serialize_with = "crate::serialize_comma_separated")]
+    pub users: Vec<UserId>,
+}
+
+/// Joins an existing conversation.
+///
+/// Wraps https://api.slack.com/methods/conversations.join
+
+#[derive(Serialize, new)]
+pub struct JoinRequest {
+    /// ID of conversation to join
+    pub channel: ConversationId,
+}
+
+/// Leaves a conversation.
+///
+/// Wraps https://api.slack.com/methods/conversations.leave
+
+#[derive(Serialize, new)]
+pub struct LeaveRequest {
+    /// Conversation to leave
+    pub channel: ConversationId,
+}
+
+/// Retrieve members of a conversation.
+///
+/// Wraps https://api.slack.com/methods/conversations.members
+
+#[derive(Serialize, new)]
+pub struct MembersRequest {
+    /// ID of the conversation to retrieve members for
+    pub channel: ConversationId,
+
+    /// Paginate through collections of data by setting the cursor parameter to a next_cursor attribute returned by a previous request's response_metadata. Default value fetches the first "page" of the collection. See pagination for more detail.
+    #[new(default)]
+    pub cursor: Option<Cursor>,
+
+    /// The maximum number of items to return. Fewer than the requested number of items may be returned, even if the end of the list hasn't been reached.
+    #[new(default)]
+    pub limit: Option<u32>,
+}
+
+#[derive(Deserialize)]
+pub struct MembersResponse {
+    ok: bool,
+    pub members: Vec<UserId>,
+    pub response_metadata: Option<ResponseMetadata>,
+}
+
+/// Opens or resumes a direct message or multi-person direct message.
+///
+/// Wraps https://api.slack.com/methods/conversations.open
+
+#[derive(Serialize, new)]
+pub struct OpenRequest {
+    /// Comma separated lists of users. If only one user is included, this creates a 1:1 DM. The ordering of the users is preserved whenever a multi-person direct message is returned.
+    #[serde(serialize_with = "crate::serialize_comma_separated")]
+    pub users: Vec<UserId>,
+}
+
+/// Sets the purpose for a conversation.
+///
+/// Wraps https://api.slack.com/methods/conversations.setPurpose
+
+#[derive(Serialize, new)]
+pub struct SetPurposeRequest<'a> {
+    /// Conversation to set the purpose of
+    pub channel: ConversationId,
+    /// A new, specialer purpose
+    pub purpose: &'a str,
+}
+
+/// Sets the topic for a conversation
+///
+/// Wraps https://api.slack.com/methods/conversations.setTopic
+
+#[derive(Serialize, new)]
+pub struct SetTopicRequest<'a> {
+    /// Conversation to set the topic of
+    pub channel: ConversationId,
+    /// The new topic string. Does not support formatting or linkification.
+    pub topic: &'a str,
+}
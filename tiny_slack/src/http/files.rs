@@ -0,0 +1,43 @@
+//! Uploading files, which takes three steps: ask for somewhere to send each file, send it there,
+//! then share everything that was sent in one go.
+use crate::id::*;
+use crate::timestamp::Timestamp;
+
+/// Gets a URL for an edge external file upload.
+///
+/// Wraps https://api.slack.com/methods/files.getUploadURLExternal
+
+#[derive(Serialize, new)]
+pub struct GetUploadUrlExternalRequest<'a> {
+    /// Name of the file being uploaded.
+    pub filename: &'a str,
+    /// Size in bytes of the file being uploaded.
+    pub length: u64,
+}
+
+#[derive(Deserialize)]
+pub struct GetUploadUrlExternalResponse {
+    ok: bool,
+    /// Where to send the file's contents
+    pub upload_url: String,
+    pub file_id: FileId,
+}
+
+/// Finishes an upload started with files.getUploadURLExternal.
+///
+/// Wraps https://api.slack.com/methods/files.completeUploadExternal
+
+#[derive(Serialize, new)]
+pub struct CompleteUploadExternalRequest<'a> {
+    /// A JSON array of the uploaded files, each an object with an `id` and optionally a `title`.
+    pub files: &'a str,
+    /// Channel ID where the file will be shared. If not specified the file will be private.
+    #[new(default)]
+    pub channel_id: Option<ConversationId>,
+    /// The message text introducing the file in specified channels.
+    #[new(default)]
+    pub initial_comment: Option<&'a str>,
+    /// Provide another message's ts value to upload this file as a reply.
+    #[new(default)]
+    pub thread_ts: Option<Timestamp>,
+}
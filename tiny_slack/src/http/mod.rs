@@ -7,6 +7,19 @@ pub struct Error {
 #[derive(Deserialize, Serialize)]
 pub struct Cursor(String); // TODO: Type safety goes here
 
+impl From<String> for Cursor {
+    fn from(cursor: String) -> Self {
+        Cursor(cursor)
+    }
+}
+
+/// Where the next page starts, for the methods that use cursors. The cursor is empty on the last
+/// page.
+#[derive(Deserialize)]
+pub struct ResponseMetadata {
+    pub next_cursor: String,
+}
+
 #[derive(Deserialize)]
 pub struct Paging {
     pub count: Option<u32>,
@@ -15,11 +28,17 @@ pub struct Paging {
     pub total: Option<u32>,
 }
 
+pub mod blocks;
+pub mod bots;
 pub mod channels;
 pub mod conversations;
 pub mod emoji;
+pub mod files;
 pub mod groups;
 pub mod im;
 pub mod reactions;
 pub mod rtm;
+pub mod search;
+pub mod stars;
+pub mod usergroups;
 pub mod users;
@@ -0,0 +1,74 @@
+//! Search the messages of your Slack team, including ones too old to have been loaded.
+use crate::http::Paging;
+use crate::id::*;
+use crate::timestamp::Timestamp;
+
+/// Searches for messages matching a query.
+///
+/// Wraps https://api.slack.com/methods/search.messages
+
+#[derive(Serialize, new)]
+pub struct MessagesRequest<'a> {
+    /// Search query. May contain booleans, etc.
+    pub query: &'a str,
+    /// Number of items to return per page.
+    #[new(default)]
+    pub count: Option<u32>,
+    /// Pass a value of true to enable query highlight markers.
+    #[new(default)]
+    pub highlight: Option<bool>,
+    /// Page number of results to return.
+    #[new(default)]
+    pub page: Option<u32>,
+    /// Return matches sorted by either score or timestamp.
+    #[new(default)]
+    pub sort: Option<SortBy>,
+    /// Change sort direction to ascending (asc) or descending (desc).
+    #[new(default)]
+    pub sort_dir: Option<SortDirection>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortBy {
+    Score,
+    Timestamp,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortDirection {
+    Asc,
+    Desc,
+}
+
+#[derive(Deserialize)]
+pub struct MessagesResponse {
+    ok: bool,
+    pub messages: Matches,
+}
+
+#[derive(Deserialize)]
+pub struct Matches {
+    pub total: u32,
+    pub paging: Paging,
+    pub matches: Vec<Match>,
+}
+
+#[derive(Deserialize)]
+pub struct Match {
+    pub channel: MatchChannel,
+    pub user: Option<UserId>,
+    /// The sender's handle, or the name a bot posted as
+    pub username: Option<String>,
+    pub ts: Timestamp,
+    pub text: String,
+    pub permalink: String,
+}
+
+#[derive(Deserialize)]
+pub struct MatchChannel {
+    pub id: ConversationId,
+    /// For direct messages, this is the other person's user id
+    pub name: String,
+}
@@ -0,0 +1,42 @@
+use crate::http::Paging;
+use crate::id::*;
+
+/// Lists stars for a user.
+///
+/// Wraps https://api.slack.com/methods/stars.list
+
+#[derive(Serialize, new)]
+pub struct ListRequest {
+    /// Number of items to return per page.
+    #[new(default)]
+    pub count: Option<u32>,
+
+    /// Page number of results to return.
+    #[new(default)]
+    pub page: Option<u32>,
+}
+
+#[derive(Deserialize)]
+pub struct ListResponse {
+    ok: bool,
+    #[serde(default)]
+    pub items: Vec<ListResponseItem>,
+    pub paging: Option<Paging>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ListResponseItem {
+    Channel {
+        channel: ConversationId,
+    },
+    Group {
+        group: ConversationId,
+    },
+    Im {
+        channel: ConversationId,
+    },
+    /// Messages and files can be starred too, but we don't do anything with them
+    #[serde(other)]
+    Other,
+}
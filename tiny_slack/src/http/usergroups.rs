@@ -0,0 +1,33 @@
+//! Get info on the user groups of your Slack team, which can be mentioned like @design.
+use crate::id::*;
+
+/// Lists all user groups for a team.
+///
+/// Wraps https://api.slack.com/methods/usergroups.list
+
+#[derive(Serialize, new)]
+pub struct ListRequest {
+    /// Include the number of users in each User Group
+    #[new(default)]
+    pub include_count: Option<bool>,
+    /// Include disabled User Groups
+    #[new(default)]
+    pub include_disabled: Option<bool>,
+    /// Include the list of users for each User Group
+    #[new(default)]
+    pub include_users: Option<bool>,
+}
+
+#[derive(Deserialize)]
+pub struct ListResponse {
+    ok: bool,
+    pub usergroups: Vec<Usergroup>,
+}
+
+#[derive(Deserialize)]
+pub struct Usergroup {
+    pub id: UsergroupId,
+    /// What the group is mentioned by, without the @
+    pub handle: String,
+    pub name: String,
+}
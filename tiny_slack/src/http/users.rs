@@ -1,5 +1,5 @@
 //! Get info on members of your Slack team.
-use crate::http::{Cursor, Paging};
+use crate::http::{Cursor, ResponseMetadata};
 use crate::id::*;
 use crate::Timestamp;
 
@@ -31,7 +31,7 @@ pub struct ListResponse {
     ok: bool,
     pub members: Vec<User>,
     pub cache_ts: Option<Timestamp>,
-    pub response_metadata: Option<Paging>,
+    pub response_metadata: Option<ResponseMetadata>,
     pub is_limited: Option<bool>,
 }
 
@@ -40,4 +40,35 @@ pub struct User {
     pub id: UserId,
     pub name: String,
     pub real_name: Option<String>,
+    #[serde(default)]
+    pub deleted: bool,
+    pub profile: Option<Profile>,
+}
+
+#[derive(Deserialize)]
+pub struct Profile {
+    /// Empty when the user hasn't chosen one
+    #[serde(default)]
+    pub display_name: String,
+    #[serde(default)]
+    pub real_name: String,
+}
+
+/// Gets information about a user.
+///
+/// Wraps https://api.slack.com/methods/users.info
+
+#[derive(Serialize, new)]
+pub struct InfoRequest {
+    /// User to get info on
+    pub user: UserId,
+    /// Set this to true to receive the locale for this user. Defaults to false
+    #[new(default)]
+    pub include_locale: Option<bool>,
+}
+
+#[derive(Deserialize)]
+pub struct InfoResponse {
+    ok: bool,
+    pub user: User,
 }
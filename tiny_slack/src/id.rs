@@ -1,4 +1,5 @@
-pub const ID_LENGTH: usize = 9;
+/// Ids used to be 9 characters long and newer ones are 11, so this leaves room for them to grow
+pub const ID_LENGTH: usize = 15;
 
 macro_rules! make_id {
     ($name:ident, $($firstchar:expr),+) => {
@@ -13,6 +14,21 @@ macro_rules! make_id {
             pub fn as_str(&self) -> &str {
                 ::std::str::from_utf8(&self.buf[..self.len as usize]).unwrap()
             }
+
+            /// Like `From`, but for text that might not be an id, like the inside of a mention
+            #[inline]
+            pub fn parse(input: &str) -> Option<Self> {
+                if input.is_empty()
+                    || input.len() > ID_LENGTH
+                    || !input.bytes().all(|b| b.is_ascii_alphanumeric())
+                {
+                    return None;
+                }
+                match input.as_bytes().get(0) {
+                    $(|Some($firstchar))* => Some(Self::from(input)),
+                    _ => None,
+                }
+            }
         }
 
         // TODO: This needs to eventually be TryFrom
@@ -153,6 +169,15 @@ impl ConversationId {
             ConversationId::DirectMessage(id) => id.as_str(),
         }
     }
+
+    /// Whichever kind of conversation `input` is the id of, if any
+    #[inline]
+    pub fn parse(input: &str) -> Option<Self> {
+        ChannelId::parse(input)
+            .map(ConversationId::Channel)
+            .or_else(|| GroupId::parse(input).map(ConversationId::Group))
+            .or_else(|| DmId::parse(input).map(ConversationId::DirectMessage))
+    }
 }
 
 impl ::std::fmt::Display for ConversationId {
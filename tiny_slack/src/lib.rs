@@ -4,6 +4,7 @@ extern crate serde_derive;
 #[macro_use]
 extern crate derive_new;
 
+pub mod blocks;
 pub mod http;
 pub mod rtm;
 
@@ -1,3 +1,7 @@
+pub use crate::blocks::{Attachment, File};
+use crate::blocks::Block;
+use crate::http::bots::Bot;
+use crate::http::conversations::ConversationSummary;
 use crate::id::*;
 use crate::timestamp::Timestamp;
 
@@ -5,14 +9,57 @@ use crate::timestamp::Timestamp;
 #[serde(tag = "type")]
 #[serde(rename_all = "snake_case")]
 pub enum Event {
+    BotAdded {
+        bot: Bot,
+    },
+    /// A bot was renamed, or its app was changed or removed
+    BotChanged {
+        bot: Bot,
+    },
+    ChannelArchive {
+        channel: ConversationId,
+    },
+    ChannelJoined {
+        channel: ConversationSummary,
+    },
+    ChannelLeft {
+        channel: ConversationId,
+    },
     ChannelMarked {
         channel: ChannelId,
         ts: Timestamp,
     },
+    /// Only carries the channel's id and new name
+    ChannelRename {
+        channel: ConversationSummary,
+    },
+    GroupArchive {
+        channel: ConversationId,
+    },
+    GroupJoined {
+        channel: ConversationSummary,
+    },
+    GroupLeft {
+        channel: ConversationId,
+    },
     GroupMarked {
         channel: GroupId,
         ts: Timestamp,
     },
+    GroupOpen {
+        channel: ConversationId,
+    },
+    GroupRename {
+        channel: ConversationSummary,
+    },
+    ImCreated {
+        channel: ConversationSummary,
+        user: UserId,
+    },
+    ImOpen {
+        channel: ConversationId,
+        user: UserId,
+    },
     ImMarked {
         channel: DmId,
         ts: Timestamp,
@@ -25,10 +72,18 @@ pub enum Event {
         ts: Timestamp,
         bot_id: Option<BotId>,
         #[serde(default)]
+        blocks: Vec<Block>,
+        #[serde(default)]
         attachments: Vec<Attachment>,
         #[serde(default)]
         files: Vec<File>,
     },
+    MpimJoined {
+        channel: ConversationSummary,
+    },
+    MpimOpen {
+        channel: ConversationId,
+    },
     ReactionAdded {
         item: Reactable,
         reaction: String,
@@ -57,17 +112,3 @@ pub struct Reaction {
     pub name: String,
     pub count: u32,
 }
-
-#[derive(Deserialize)]
-pub struct File {
-    pub url_private: String,
-}
-
-#[derive(Deserialize)]
-pub struct Attachment {
-    pub pretext: Option<String>,
-    pub text: Option<String>,
-    pub title: Option<String>,
-    #[serde(default)]
-    pub files: Vec<File>,
-}
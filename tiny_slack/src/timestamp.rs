@@ -52,6 +52,16 @@ impl<'de> Visitor<'de> for TimestampVisitor {
             microseconds: value as i64 * 1_000_000,
         })
     }
+
+    #[inline]
+    fn visit_f64<E>(self, value: f64) -> Result<Timestamp, E>
+    where
+        E: de::Error,
+    {
+        Ok(Timestamp {
+            microseconds: (value * 1_000_000.0).round() as i64,
+        })
+    }
 }
 
 impl<'de> Deserialize<'de> for Timestamp {